@@ -0,0 +1,51 @@
+use naughtyfy::api::*;
+use naughtyfy::flags::*;
+use naughtyfy::policy::*;
+
+/// Using naughtyfy to answer file open events under `/tmp` with the
+/// rules of a policy file, Eg: `cargo run --example policy_file -- tmp.policy`
+/// with a file holding
+/// ```text
+/// default allow
+/// deny path=/tmp/tmp.txt event=OPEN_PERM
+/// ```
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .expect("Usage: policy_file <policy file>");
+    let policy = match Policy::load(&path) {
+        Ok(policy) => policy,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    for issue in validate(&policy) {
+        eprintln!("{path}: {issue}");
+    }
+
+    let fd = &init(FAN_CLOEXEC | FAN_CLASS_CONTENT, O_RDONLY | O_LARGEFILE);
+    if fd.is_err() {
+        eprintln!("Encountered err due to {fd:?}");
+    }
+    let fd = fd.as_ref().unwrap();
+    mark(
+        fd,
+        FAN_MARK_ADD | FAN_MARK_MOUNT,
+        FAN_OPEN_PERM,
+        AT_FDCWD,
+        "/tmp",
+    )
+    .unwrap();
+
+    loop {
+        for event in read(fd).unwrap() {
+            match policy.answer(fd, &event) {
+                // Dry-run decisions are printed to stderr by the policy.
+                Ok(record) if record.mode() == Mode::Enforce => println!("{record}"),
+                Ok(_) => (),
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+    }
+}
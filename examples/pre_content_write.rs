@@ -11,7 +11,7 @@ fn main() {
     }
     let fd = fd.as_ref().unwrap();
     let status = mark(
-        &fd,
+        fd,
         FAN_MARK_ADD | FAN_MARK_MOUNT,
         FAN_OPEN_PERM | FAN_CLOSE_WRITE,
         AT_FDCWD,
@@ -20,39 +20,23 @@ fn main() {
     if status.is_err() {
         eprintln!("Encountered err due to {fd:?}");
     }
-    let _status = status.unwrap();
+    status.unwrap();
 
     loop {
         // read_do(fd, print_meta).unwrap();
-        let data = read(&fd).unwrap();
+        let data = read(fd).unwrap();
         data.iter().for_each(|e| {
-            if e.fd >= 0 {
-                let path =
-                    std::fs::read_link(format!("/proc/self/fd/{}", e.fd)).unwrap_or_default();
-                if e.mask & FAN_OPEN_PERM != 0 {
+            if let Some(efd) = e.fd() {
+                let path = e.path().unwrap_or_default();
+                if e.mask().contains(FAN_OPEN_PERM) {
                     if path.to_str().unwrap() == "/tmp/tmp.txt" {
                         println!("Denied: {path:?}");
-                        write(
-                            &fd,
-                            &fanotify_response {
-                                fd: e.fd,
-                                response: FAN_DENY,
-                            },
-                        )
-                        .unwrap();
+                        write(fd, &fanotify_response::new(&efd, FAN_DENY)).unwrap();
                     } else {
                         println!("Allowed: {path:?}");
-                        write(
-                            &fd,
-                            &fanotify_response {
-                                fd: e.fd,
-                                response: FAN_ALLOW,
-                            },
-                        )
-                        .unwrap();
+                        write(fd, &fanotify_response::new(&efd, FAN_ALLOW)).unwrap();
                     }
                 }
-                close(e.fd).unwrap();
             }
         });
     }
@@ -1,16 +1,16 @@
 use naughtyfy::api::*;
+use naughtyfy::event::*;
 use naughtyfy::flags::*;
-use naughtyfy::types::*;
 
 /// What to do with the event?
-fn procedure(md: &fanotify_event_with_fid) {
-    println!("{md:#?}");
+fn procedure(event: &EventRef) {
+    println!("{event:#?}");
 }
 
 /// Run this example with sudo privilages and create
 /// a directory in root of this project to see results
 fn main() {
-    let fd = &init(FAN_CLASS_NOTIF | FAN_REPORT_DFID_NAME, 0);
+    let fd = &init(FAN_CLASS_NOTIF | FAN_REPORT_DFID_NAME, O_RDONLY);
     match fd {
         Ok(fd) => {
             mark(
@@ -1,23 +1,29 @@
-use naughtyfy::api::*;
 use naughtyfy::flags::*;
+use naughtyfy::group::*;
+use naughtyfy::reader::*;
 
 fn main() {
-    unsafe {
-        // Use carefully in multi thread.
-        FAN_EVENT_BUFFER_LEN = 230.into();
-    }
-    // Initialise fanotify
-    let fd = &init(FAN_CLASS_NOTIF, 0).unwrap();
+    // Initialise fanotify, the read buffer grows from 4KiB up to 64KiB
+    // while reads come back full.
+    let group = Fanotify::builder()
+        .buffer_size(BufferSize::Adaptive {
+            min: 4096,
+            max: 65536,
+        })
+        .build()
+        .unwrap();
 
     // Mark file descriptor for events
-    mark(fd, FAN_MARK_ADD | FAN_MARK_MOUNT, FAN_ACCESS, AT_FDCWD, "/").unwrap();
+    group
+        .mark(FAN_MARK_ADD | FAN_MARK_MOUNT, FAN_ACCESS, AT_FDCWD, "/")
+        .unwrap();
 
     // Try extracting events from the buffer and print it
     let mut iter = 1;
     loop {
-        let res = read(fd).unwrap();
+        let res = group.read().unwrap();
+        // Event fds are closed when `res` is dropped.
         println!("{res:#?}");
-        res.iter().for_each(|e| close(e.fd).unwrap());
         iter += 1;
         if iter > 100 {
             break;
@@ -1,32 +1,30 @@
 //! Low level function mapping for fanotify
 
-use crate::{errors::*, types::*};
+use crate::{
+    cancel::{wait_readable, Canceller},
+    errors::*,
+    event::*,
+    reader::EventReader,
+    types::*,
+};
 use libc::c_void;
 use std::{
     ffi::CString,
     io::Error,
-    mem,
+    ops::ControlFlow,
     os::{
-        fd::{AsRawFd, FromRawFd, OwnedFd as Fd},
+        fd::{AsFd, AsRawFd, FromRawFd, OwnedFd as Fd},
         unix::ffi::OsStrExt,
     },
+    time::Duration,
 };
 
-// Used for docs test
-#[allow(unused_imports)]
 use crate::flags::*;
 
-/// Get current platform sizeof of [`fanotify_event_metadata`].
-const FAN_EVENT_METADATA_LEN: usize = mem::size_of::<fanotify_event_metadata>();
-
-/// Get current platform sizeof of [`fanotify_event_with_fid`].
-const FAN_EVENT_METADATA_FID_LEN: usize = mem::size_of::<fanotify_event_with_fid>();
-
-/// Get current platform size of [`fanotify_response`]
-const FAN_WRITE_RESPONSE_LEN: usize = mem::size_of::<fanotify_response>();
-
-/// Length of memory to be allocated for read buffer
-pub static mut FAN_EVENT_BUFFER_LEN: std::sync::Mutex<usize> = std::sync::Mutex::new(250);
+/// Size in bytes of the buffer used by [`read()`], [`read_do()`],
+/// [`read_with_fid()`] and [`read_with_fid_do()`].
+/// Groups and readers pick their own with [`crate::reader::BufferSize`].
+pub const FAN_EVENT_BUFFER_LEN: usize = 8192;
 
 /// Initializes a new fanotify group and returns a
 /// file descriptor [`Fd`] for the event queue associated
@@ -66,7 +64,7 @@ pub static mut FAN_EVENT_BUFFER_LEN: std::sync::Mutex<usize> = std::sync::Mutex:
 ///     * [`FAN_CLASS_CONTENT`]
 ///     * [`FAN_CLASS_NOTIF`]
 ///
-///     The following bits can additionally be set in `flags`: <br>
+///   The following bits can additionally be set in `flags`: <br>
 ///     * [`FAN_CLOEXEC`]
 ///     * [`FAN_NONBLOCK`]
 ///     * [`FAN_UNLIMITED_QUEUE`]
@@ -77,16 +75,16 @@ pub static mut FAN_EVENT_BUFFER_LEN: std::sync::Mutex<usize> = std::sync::Mutex:
 ///     * [`FAN_REPORT_NAME`]
 ///     * [`FAN_REPORT_DFID_NAME`]
 /// * `event_f_flags` - Defines the file status flags that
-///                     will be set on the open file descriptions that are created for
-///                     fanotify events.  For details of these flags, see the description
-///                     of the flags values in open(2).  `event_f_flags` includes a multi-
-///                     bit field for the access mode. This field can take the following
-///                     values:
+///   will be set on the open file descriptions that are created for
+///   fanotify events.  For details of these flags, see the description
+///   of the flags values in open(2).  `event_f_flags` includes a multi-
+///   bit field for the access mode. This field can take the following
+///   values:
 ///     * [`O_RDONLY`]
 ///     * [`O_WRONLY`]
 ///     * [`O_RDWR`]
 ///
-///     Additional bits can be set in `event_f_flags`.
+///   Additional bits can be set in `event_f_flags`.
 ///     * [`O_LARGEFILE`]
 ///     * [`O_CLOEXEC`]
 ///     * [`O_APPEND`]
@@ -112,9 +110,9 @@ pub static mut FAN_EVENT_BUFFER_LEN: std::sync::Mutex<usize> = std::sync::Mutex:
 /// }
 /// ```
 ///
-pub fn init(flags: u32, event_f_flags: u32) -> Result<Fd, FanotifyError> {
+pub fn init(flags: InitFlags, event_f_flags: EventFFlags) -> Result<Fd, FanotifyError> {
     unsafe {
-        match libc::fanotify_init(flags, event_f_flags) {
+        match libc::fanotify_init(flags.bits(), event_f_flags.bits()) {
             -1 => Err(FanotifyError::Init(
                 Error::last_os_error().raw_os_error().unwrap_or_default(),
             )),
@@ -130,13 +128,13 @@ pub fn init(flags: u32, event_f_flags: u32) -> Result<Fd, FanotifyError> {
 /// # Arguments
 /// * `fd` - Refrence to [`Fd`] returned by [`init()`]
 /// * `flags` - Bit mask describing the modification to perform. <br>
-///     It must include **exactly one** of the following values:
+///   It must include **exactly one** of the following values:
 ///     * [`FAN_MARK_ADD`]
 ///     * [`FAN_MARK_REMOVE`]
 ///     * [`FAN_MARK_FLUSH`]
 ///
-///      In addition, zero or more of the following values may be ORed
-///      into flags:
+///   In addition, zero or more of the following values may be ORed
+///   into flags:
 ///     * [`FAN_MARK_DONT_FOLLOW`]
 ///     * [`FAN_MARK_ONLYDIR`]
 ///     * [`FAN_MARK_MOUNT`]
@@ -144,7 +142,7 @@ pub fn init(flags: u32, event_f_flags: u32) -> Result<Fd, FanotifyError> {
 ///     * [`FAN_MARK_IGNORED_MASK`]
 ///     * [`FAN_MARK_IGNORED_SURV_MODIFY`]
 /// * `mask` - Which events shall be listened for (or which shall be ignored). <br>
-///     It is a bit mask composed of the following values:
+///   It is a bit mask composed of the following values:
 ///     * [`FAN_ACCESS`]
 ///     * [`FAN_MODIFY`]
 ///     * [`FAN_CLOSE_WRITE`]
@@ -175,15 +173,12 @@ pub fn init(flags: u32, event_f_flags: u32) -> Result<Fd, FanotifyError> {
 ///   marked.
 /// * If pathname is `NULL`, and dirfd takes the special value
 ///   [`AT_FDCWD`], the current working directory is to be marked.
-
 /// * If pathname is absolute, it defines the filesystem object to
 ///   be marked, and dirfd is ignored.
-
 /// * If pathname is relative, and dirfd does not have the value
 ///   [`AT_FDCWD`], then the filesystem object to be marked is
 ///   determined by interpreting pathname relative the directory
 ///   referred to by dirfd.
-
 /// * If pathname is relative, and dirfd has the value [`AT_FDCWD`],
 ///   then the filesystem object to be marked is determined by
 ///   interpreting pathname relative to the current working
@@ -195,7 +190,7 @@ pub fn init(flags: u32, event_f_flags: u32) -> Result<Fd, FanotifyError> {
 /// # use naughtyfy::flags::*;
 /// # use naughtyfy::types::*;
 /// # use naughtyfy::api::*;
-/// let fd = &init(FAN_CLASS_NOTIF, 0);
+/// let fd = &init(FAN_CLASS_NOTIF, O_RDONLY);
 /// match fd {
 ///     Ok(fd) => {
 ///         let m = mark(fd, FAN_MARK_ADD | FAN_MARK_MOUNT, FAN_ACCESS, AT_FDCWD, "./");
@@ -211,14 +206,20 @@ pub fn init(flags: u32, event_f_flags: u32) -> Result<Fd, FanotifyError> {
 /// ```
 pub fn mark<P: ?Sized + Path>(
     fd: &Fd,
-    flags: u32,
-    mask: u64,
+    flags: MarkFlags,
+    mask: EventMask,
     dirfd: i32,
     path: &P,
 ) -> Result<(), FanotifyError> {
     let path = CString::new(path.as_os_str().as_bytes()).unwrap_or_default();
     unsafe {
-        match libc::fanotify_mark(fd.as_raw_fd(), flags, mask, dirfd, path.as_ptr()) {
+        match libc::fanotify_mark(
+            fd.as_raw_fd(),
+            flags.bits(),
+            mask.bits(),
+            dirfd,
+            path.as_ptr(),
+        ) {
             0 => Ok(()),
             _ => Err(FanotifyError::Mark(
                 Error::last_os_error().raw_os_error().unwrap_or_default(),
@@ -228,11 +229,14 @@ pub fn mark<P: ?Sized + Path>(
 }
 
 /// This function attempts to read from a file descriptor `fanotify_fd`
-/// into a `Vec<fanotify_event_metadata>` and return a Result.
+/// into a `Vec<Event>` and return a Result.
 ///
 /// # Note
-/// [`close()`] is called on metadata's fd when [`fanotify_event_metadata`]
-/// is dropped. No need to explicitly call [`close()`] on every fd.
+/// Every [`Event`] owns its file descriptor, it is closed when the
+/// event is dropped. No need to explicitly call [`close()`] on every fd,
+/// use [`Event::take_fd()`] or [`Event::into_file()`] to keep it.
+///
+/// Allocates on every call, [`crate::reader::EventReader`] reuses its buffer.
 ///
 /// # Argument
 /// * `fd` - Refrence to [`Fd`] returned by [`init()`]
@@ -243,14 +247,14 @@ pub fn mark<P: ?Sized + Path>(
 /// # use naughtyfy::flags::*;
 /// # use naughtyfy::types::*;
 /// # use naughtyfy::api::*;
-/// let fd = &init(FAN_CLASS_NOTIF, 0);
+/// let fd = &init(FAN_CLASS_NOTIF, O_RDONLY);
 /// match fd {
 ///     Ok(fd) => {
 ///         let m = mark(fd, FAN_MARK_ADD | FAN_MARK_MOUNT, FAN_ACCESS, AT_FDCWD, "./");
 ///         let res = read(fd);
 ///         assert!(res.is_ok());
-///         for meta in res.unwrap() {
-///             close(meta.fd).unwrap();
+///         for event in res.unwrap() {
+///             println!("{:?}", event.path());
 ///         }
 ///     }
 ///     Err(e) => {
@@ -260,52 +264,70 @@ pub fn mark<P: ?Sized + Path>(
 ///     }
 /// }
 /// ```
-pub fn read(fd: &Fd) -> Result<Vec<fanotify_event_metadata>, FanotifyError> {
-    let len;
-    unsafe {
-        match FAN_EVENT_BUFFER_LEN.lock() {
-            Ok(value) => {
-                len = *value;
-            }
-            Err(e) => {
-                eprintln!("{e}");
-                return Err(FanotifyError::Read(libc::ENOMEM));
-            }
-        }
-    }
-    let mut buff: Vec<fanotify_event_metadata> = Vec::with_capacity(len);
-    let sizeof;
-    unsafe {
-        // `libc::read()` is unsafe
-        sizeof = libc::read(
-            fd.as_raw_fd(),
-            buff.as_mut_ptr() as *mut c_void,
-            FAN_EVENT_METADATA_LEN * len,
-        );
-    }
+pub fn read(fd: &Fd) -> Result<Vec<Event>, FanotifyError> {
+    let mut reader = EventReader::default();
+    reader.fill(fd)?;
+    reader
+        .events()
+        .map(|event| event.map(Event::from))
+        .collect()
+}
 
-    if sizeof == -1 {
-        return Err(FanotifyError::Read(
-            Error::last_os_error().raw_os_error().unwrap_or_default(),
-        ));
+/// Same as [`read()`] but waits at most `timeout` for events to arrive,
+/// using `poll(2)`. Returns an empty `Vec` if none arrived in time.
+///
+/// # Argument
+/// * `fd` - Refrence to [`Fd`] returned by [`init()`]
+/// * `timeout` - Longest time to wait for events.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::api::*;
+/// # use std::time::Duration;
+/// match init(FAN_CLASS_NOTIF, O_RDONLY) {
+///     Ok(fd) => {
+///         // Nothing is marked, so the wait times out.
+///         let events = read_timeout(&fd, Duration::from_millis(10)).unwrap();
+///         assert!(events.is_empty());
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// ```
+pub fn read_timeout(fd: &Fd, timeout: Duration) -> Result<Vec<Event>, FanotifyError> {
+    match wait_readable(fd, Some(timeout), None)? {
+        true => read(fd),
+        false => Ok(Vec::new()),
     }
+}
 
-    unsafe {
-        // Vec.set_len() is unsafe operation. len must be less than capacity, which is gur
-        buff.set_len(sizeof as usize / FAN_EVENT_METADATA_LEN);
+/// Same as [`read_timeout()`] but returns [`FanotifyError::Cancelled`]
+/// as soon as `canceller` fires, see [`Canceller`].
+/// A `timeout` of `None` waits until an event arrives or cancellation.
+pub fn read_cancellable(
+    fd: &Fd,
+    canceller: &Canceller,
+    timeout: Option<Duration>,
+) -> Result<Vec<Event>, FanotifyError> {
+    match wait_readable(fd, timeout, Some(canceller))? {
+        true => read(fd),
+        false => Ok(Vec::new()),
     }
-    Ok(buff)
 }
 
 /// This function attempts to read from a file descriptor `fanotify_fd`
-/// and performs `process_metadata` on [`fanotify_event_metadata`] recieved after read.
+/// and performs `process_event` on every [`Event`] recieved after read.
 /// returns `Result<(),FanotifyError>`.
 ///
-/// This function closes `metadata.fd` after calling `process_metadata`
+/// The event fd is closed after calling `process_event`
 ///
 /// # Argument
 /// * `fd` - Refrence to [`Fd`] returned by [`init()`]
-/// * `process_metadata` - Function / Closure for processing [`fanotify_event_metadata`].
+/// * `process_event` - Function / Closure for processing [`Event`].
 ///
 /// # Example
 /// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
@@ -313,12 +335,13 @@ pub fn read(fd: &Fd) -> Result<Vec<fanotify_event_metadata>, FanotifyError> {
 /// # use naughtyfy::flags::*;
 /// # use naughtyfy::types::*;
 /// # use naughtyfy::api::*;
-/// fn procedure(md: &fanotify_event_metadata) {
-///     println!("{md:#?}");
+/// # use naughtyfy::event::*;
+/// fn procedure(event: &Event) {
+///     println!("{event:#?}");
 /// }
 ///
 /// fn main() {
-///     let fd = &init(FAN_CLASS_NOTIF, 0);
+///     let fd = &init(FAN_CLASS_NOTIF, O_RDONLY);
 ///      match fd {
 ///          Ok(fd) => {
 ///              let m = mark(fd, FAN_MARK_ADD | FAN_MARK_MOUNT, FAN_ACCESS, AT_FDCWD, "./");
@@ -334,142 +357,312 @@ pub fn read(fd: &Fd) -> Result<Vec<fanotify_event_metadata>, FanotifyError> {
 ///      }
 /// }
 /// ```
-pub fn read_do(
-    fd: &Fd,
-    process_metadata: fn(&fanotify_event_metadata),
-) -> Result<(), FanotifyError> {
-    let len;
-    unsafe {
-        match FAN_EVENT_BUFFER_LEN.lock() {
-            Ok(value) => {
-                len = *value;
-            }
-            Err(e) => {
-                eprintln!("{e}");
-                return Err(FanotifyError::Read(libc::ENOMEM));
-            }
-        }
-    }
-    let mut buff: Vec<fanotify_event_metadata> = Vec::with_capacity(len);
-    let sizeof;
-    unsafe {
-        // `libc::read()` is unsafe
-        sizeof = libc::read(
-            fd.as_raw_fd(),
-            buff.as_mut_ptr() as *mut c_void,
-            FAN_EVENT_METADATA_LEN * len,
-        );
+pub fn read_do<F: FnMut(&Event)>(fd: &Fd, mut process_event: F) -> Result<(), FanotifyError> {
+    for event in read(fd)? {
+        process_event(&event);
     }
+    Ok(())
+}
 
-    if sizeof == -1 {
-        return Err(FanotifyError::Read(
-            Error::last_os_error().raw_os_error().unwrap_or_default(),
-        ));
-    }
+/// This function attempts to read from a file descriptor `fanotify_fd`
+/// and performs `process_event` on every [`Event`] recieved after read
+/// until it returns [`ControlFlow::Break`].
+///
+/// Returns `Ok(ControlFlow::Break(value))` with the value `process_event`
+/// stopped with, or `Ok(ControlFlow::Continue(()))` when every event was
+/// processed. The events left after a break are dropped, closing their fd.
+///
+/// # Argument
+/// * `fd` - Refrence to [`Fd`] returned by [`init()`]
+/// * `process_event` - Closure for processing [`Event`], it can capture
+///   state (counters, channels ...) and stop the loop with an error.
+///
+/// # Example
+/// ```rust
+/// # use naughtyfy::api::*;
+/// # use naughtyfy::flags::*;
+/// # use std::io::Write;
+/// # use std::ops::ControlFlow;
+/// # use std::os::fd::{FromRawFd, OwnedFd};
+/// // A pipe stands in for the fanotify group.
+/// let mut fds = [0; 2];
+/// assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+/// let (rx, tx) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+/// let mut tx = std::fs::File::from(tx);
+/// for pid in [1_i32, 2, 3] {
+///     let mut event = Vec::new();
+///     event.extend(24_u32.to_ne_bytes());
+///     event.extend([FANOTIFY_METADATA_VERSION as u8, 0]);
+///     event.extend(24_u16.to_ne_bytes());
+///     event.extend(FAN_OPEN.bits().to_ne_bytes());
+///     event.extend(FAN_NOFD.to_ne_bytes());
+///     event.extend(pid.to_ne_bytes());
+///     tx.write_all(&event).unwrap();
+/// }
+///
+/// let mut seen = 0;
+/// let res = try_read_do(&rx, |event| {
+///     seen += 1;
+///     if event.pid() == 2 {
+///         return ControlFlow::Break(format!("stopped at {}", event.pid()));
+///     }
+///     ControlFlow::Continue(())
+/// });
+/// assert_eq!(res.unwrap(), ControlFlow::Break("stopped at 2".to_string()));
+/// assert_eq!(seen, 2);
+/// ```
+pub fn try_read_do<E, F>(fd: &Fd, process_event: F) -> Result<ControlFlow<E>, FanotifyError>
+where
+    F: FnMut(&Event) -> ControlFlow<E>,
+{
+    try_read_with(&mut EventReader::default(), fd, process_event)
+}
 
-    unsafe {
-        // Vec.set_len() is unsafe operation. len must be less than capacity, which is gur
-        buff.set_len(sizeof as usize / FAN_EVENT_METADATA_LEN);
-    }
-    for event in &buff {
-        process_metadata(event);
+/// Calls `process_event` on the events of one read from `fd` into `reader`.
+pub(crate) fn try_read_with<E, F, T>(
+    reader: &mut EventReader,
+    fd: &T,
+    mut process_event: F,
+) -> Result<ControlFlow<E>, FanotifyError>
+where
+    F: FnMut(&Event) -> ControlFlow<E>,
+    T: AsFd + ?Sized,
+{
+    reader.fill(fd)?;
+    for event in reader.events() {
+        if let ControlFlow::Break(value) = process_event(&event?.into_event()) {
+            return Ok(ControlFlow::Break(value));
+        }
     }
-    Ok(())
+    Ok(ControlFlow::Continue(()))
 }
 
-/// This function attempts to read from a file descriptor `fanotify_fd`
-/// into a [`Vec`] of [`fanotify_event_with_fid`] which was initilated with
-/// [`FAN_REPORT_FID`] or [`FAN_REPORT_DIR_FID`] flag. Returns the vector wrapped in `Result`.
+/// Keeps reading from a file descriptor `fanotify_fd` and performs
+/// `process_event` on every [`Event`] until it returns [`ControlFlow::Break`].
 ///
-/// # Important
-/// Use this only when `fd` is initialized with [`FAN_REPORT_FID`] or [`FAN_REPORT_DIR_FID`] flag.
+/// Returns the value `process_event` stopped with, or the first error
+/// of a read. A single buffer is reused for every read.
 ///
 /// # Argument
 /// * `fd` - Refrence to [`Fd`] returned by [`init()`]
-pub fn read_with_fid(fd: &Fd) -> Result<Vec<fanotify_event_with_fid>, FanotifyError> {
-    let len;
-    unsafe {
-        match FAN_EVENT_BUFFER_LEN.lock() {
-            Ok(value) => {
-                len = *value;
-            }
-            Err(e) => {
-                eprintln!("{e}");
-                return Err(FanotifyError::Read(libc::ENOMEM));
-            }
+/// * `process_event` - Closure for processing [`Event`].
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::api::*;
+/// # use std::ops::ControlFlow;
+/// let fd = &init(FAN_CLASS_NOTIF, O_RDONLY);
+/// match fd {
+///     Ok(fd) => {
+///         let tmp = std::env::temp_dir().canonicalize().unwrap();
+///         mark(fd, FAN_MARK_ADD, FAN_OPEN | FAN_EVENT_ON_CHILD, AT_FDCWD, tmp.as_path()).unwrap();
+///         let file = tmp.join(format!("naughtyfy-run-until-{}", std::process::id()));
+///         std::fs::write(&file, b"").unwrap();
+///
+///         let mut opens = 0;
+///         let pid = run_until(fd, |event| {
+///             opens += 1;
+///             match event.path() {
+///                 Ok(path) if path == file => ControlFlow::Break(event.pid()),
+///                 _ => ControlFlow::Continue(()),
+///             }
+///         });
+///         assert_eq!(pid.unwrap(), std::process::id() as i32);
+///         assert!(opens >= 1);
+///         std::fs::remove_file(&file).unwrap();
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// ```
+pub fn run_until<E, F>(fd: &Fd, process_event: F) -> Result<E, FanotifyError>
+where
+    F: FnMut(&Event) -> ControlFlow<E>,
+{
+    run_until_with(&mut EventReader::default(), fd, process_event)
+}
+
+/// Keeps calling [`try_read_with()`] until `process_event` breaks.
+pub(crate) fn run_until_with<E, F, T>(
+    reader: &mut EventReader,
+    fd: &T,
+    mut process_event: F,
+) -> Result<E, FanotifyError>
+where
+    F: FnMut(&Event) -> ControlFlow<E>,
+    T: AsFd + ?Sized,
+{
+    loop {
+        if let ControlFlow::Break(value) = try_read_with(reader, fd, &mut process_event)? {
+            return Ok(value);
         }
     }
-    let mut buff: Vec<fanotify_event_with_fid> = Vec::with_capacity(len);
+}
 
+/// Read from `fd` into `buffer`, replacing what it held.
+/// Descriptors left in `buffer` by the previous read are closed.
+pub(crate) fn read_into<F: AsFd + ?Sized>(
+    fd: &F,
+    buffer: &mut EventBuffer,
+) -> Result<(), FanotifyError> {
+    buffer.clear();
     let sizeof;
     unsafe {
+        // `libc::read()` is unsafe
         sizeof = libc::read(
-            fd.as_raw_fd(),
-            buff.as_mut_ptr() as *mut c_void,
-            FAN_EVENT_METADATA_FID_LEN * len,
+            fd.as_fd().as_raw_fd(),
+            buffer.as_mut_ptr() as *mut c_void,
+            buffer.capacity(),
         );
     }
-
     if sizeof == -1 {
         return Err(FanotifyError::Read(
             Error::last_os_error().raw_os_error().unwrap_or_default(),
         ));
     }
-
     unsafe {
-        buff.set_len(sizeof as usize / FAN_EVENT_METADATA_FID_LEN);
+        // read() never returns more than the capacity it was given.
+        buffer.set_len(sizeof as usize);
     }
-    Ok(buff)
+    Ok(())
+}
+
+/// This function attempts to read from a file descriptor `fanotify_fd`
+/// which was initilated with [`FAN_REPORT_FID`], [`FAN_REPORT_DIR_FID`]
+/// or any other flag that attaches information records to events.
+/// Returns the filled [`EventBuffer`], walk it with [`EventBuffer::events()`].
+///
+/// Events are variable length, each one is parsed using its `event_len`
+/// and `metadata_len` and its records are yielded as [`EventInfo`].
+/// Allocates on every call, [`crate::reader::EventReader`] reuses its buffer.
+///
+/// # Argument
+/// * `fd` - Refrence to [`Fd`] returned by [`init()`]
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::types::*;
+/// # use naughtyfy::api::*;
+/// # use naughtyfy::event::*;
+/// let fd = &init(FAN_CLASS_NOTIF | FAN_REPORT_DFID_NAME | FAN_NONBLOCK, O_RDONLY);
+/// match fd {
+///     Ok(fd) => {
+///         mark(fd, FAN_MARK_ADD, FAN_CREATE | FAN_ONDIR, AT_FDCWD, "/tmp").unwrap();
+///         let dir = std::env::temp_dir().join(format!("naughtyfy-{}", std::process::id()));
+///         std::fs::create_dir(&dir).unwrap();
+///         let mut events = read_with_fid(fd).unwrap();
+///         std::fs::remove_dir(&dir).unwrap();
+///         for event in events.events() {
+///             for info in event.unwrap().info() {
+///                 if let EventInfo::DfidName { name, .. } = info.unwrap() {
+///                     assert_eq!(name, dir.file_name().unwrap());
+///                 }
+///             }
+///         }
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// ```
+pub fn read_with_fid(fd: &Fd) -> Result<EventBuffer, FanotifyError> {
+    let mut buffer = EventBuffer::new(FAN_EVENT_BUFFER_LEN);
+    read_into(fd, &mut buffer)?;
+    Ok(buffer)
 }
 
 /// This function attempts to read from a file descriptor `fanotify_fd`
 /// which was initilated with [`FAN_REPORT_FID`] or [`FAN_REPORT_DIR_FID`] flag
-/// and performs `process_metadata_fid` on [`fanotify_event_with_fid`]
+/// and performs `process_event` on every [`EventRef`]
 /// recieved after read. Returns `Result<(),FanotifyError>`.
 ///
+/// Stops at the first event that fails to parse and returns the error.
+///
 /// # Argument
 /// * `fd` - Refrence to [`Fd`] returned by [`init()`].
-/// * `process_metadata` - Function / Closure for processing [`fanotify_event_with_fid`].
-pub fn read_with_fid_do(
+/// * `process_event` - Function / Closure for processing [`EventRef`].
+pub fn read_with_fid_do<F: FnMut(&EventRef)>(
     fd: &Fd,
-    process_metadata_fid: fn(&fanotify_event_with_fid),
+    mut process_event: F,
 ) -> Result<(), FanotifyError> {
-    let len;
-    unsafe {
-        match FAN_EVENT_BUFFER_LEN.lock() {
-            Ok(value) => {
-                len = *value;
-            }
-            Err(e) => {
-                eprintln!("{e}");
-                return Err(FanotifyError::Read(libc::ENOMEM));
-            }
-        }
-    }
-    let mut buff: Vec<fanotify_event_with_fid> = Vec::with_capacity(len);
-    let sizeof;
-    unsafe {
-        // `libc::read()` is unsafe
-        sizeof = libc::read(
-            fd.as_raw_fd(),
-            buff.as_mut_ptr() as *mut c_void,
-            FAN_EVENT_METADATA_FID_LEN * len,
-        );
-    }
-    if sizeof == -1 {
-        return Err(FanotifyError::Read(
-            Error::last_os_error().raw_os_error().unwrap_or_default(),
-        ));
+    let mut buffer = read_with_fid(fd)?;
+    for event in buffer.events() {
+        process_event(&event?);
     }
+    Ok(())
+}
 
-    // Vec.set_len() is unsafe operation. len must be less than capacity.
-    unsafe {
-        buff.set_len(sizeof as usize / FAN_EVENT_METADATA_FID_LEN);
-    }
-    for event in &buff {
-        process_metadata_fid(event);
+/// Same as [`read_with_fid_do()`] until `process_event` returns
+/// [`ControlFlow::Break`], see [`try_read_do()`].
+///
+/// Returns `Ok(ControlFlow::Break(value))` with the value `process_event`
+/// stopped with, or `Ok(ControlFlow::Continue(()))` when every event was
+/// processed. The events left after a break are dropped, closing their fd.
+///
+/// # Argument
+/// * `fd` - Refrence to [`Fd`] returned by [`init()`].
+/// * `process_event` - Closure for processing [`EventRef`].
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::api::*;
+/// # use naughtyfy::event::*;
+/// # use std::ops::ControlFlow;
+/// let fd = &init(FAN_CLASS_NOTIF | FAN_REPORT_DFID_NAME | FAN_NONBLOCK, O_RDONLY);
+/// match fd {
+///     Ok(fd) => {
+///         let tmp = std::env::temp_dir().canonicalize().unwrap();
+///         mark(fd, FAN_MARK_ADD, FAN_CREATE | FAN_ONDIR, AT_FDCWD, tmp.as_path()).unwrap();
+///         let dir = tmp.join(format!("naughtyfy-try-fid-{}", std::process::id()));
+///         std::fs::create_dir(&dir).unwrap();
+///         let res = try_read_with_fid_do(fd, |event| {
+///             for info in event.info() {
+///                 match info {
+///                     Ok(EventInfo::DfidName { name, .. }) if name == dir.file_name().unwrap() => {
+///                         return ControlFlow::Break(Ok(event.pid()));
+///                     }
+///                     Err(e) => return ControlFlow::Break(Err(e)),
+///                     _ => {}
+///                 }
+///             }
+///             ControlFlow::Continue(())
+///         });
+///         std::fs::remove_dir(&dir).unwrap();
+///         let pid = match res.unwrap() {
+///             ControlFlow::Break(pid) => pid.unwrap(),
+///             ControlFlow::Continue(()) => panic!("mkdir not reported"),
+///         };
+///         assert_eq!(pid, std::process::id() as i32);
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// ```
+pub fn try_read_with_fid_do<E, F>(
+    fd: &Fd,
+    mut process_event: F,
+) -> Result<ControlFlow<E>, FanotifyError>
+where
+    F: FnMut(&EventRef) -> ControlFlow<E>,
+{
+    let mut buffer = read_with_fid(fd)?;
+    for event in buffer.events() {
+        if let ControlFlow::Break(value) = process_event(&event?) {
+            return Ok(ControlFlow::Break(value));
+        }
     }
-    Ok(())
+    Ok(ControlFlow::Continue(()))
 }
 
 /// Writes up to count bytes from the buffer starting at buf
@@ -500,7 +693,9 @@ pub fn read_with_fid_do(
 /// # Argument
 /// * `fd` - Refrence to [`Fd`] returned by [`init()`].
 /// * `response` - This is a struct of type [`fanotify_response`]
-///                 that specifies how to deal with the request.
+///   that specifies how to deal with the request. It is written with
+///   its information record in a single write of
+///   [`fanotify_response::write_len()`] bytes.
 ///
 /// # Example
 /// ```rust
@@ -524,15 +719,10 @@ pub fn read_with_fid_do(
 ///         if events.len() > 1 {
 ///             for event in events {
 ///                 println!("{event:#?}");
-///                 write(
-///                     fd,
-///                     &fanotify_response {
-///                         fd: event.fd,
-///                         // Allowig all events
-///                         response: FAN_ALLOW,
-///                     },
-///                 )
-///                 .unwrap();
+///                 if let Some(efd) = event.fd() {
+///                     // Allowig all events
+///                     write(fd, &fanotify_response::new(&efd, FAN_ALLOW)).unwrap();
+///                 }
 ///             }
 ///         }
 ///     }
@@ -548,7 +738,7 @@ pub fn write(fd: &Fd, response: &fanotify_response) -> Result<isize, FanotifyErr
         match libc::write(
             fd.as_raw_fd(),
             response as *const fanotify_response as *const libc::c_void,
-            FAN_WRITE_RESPONSE_LEN,
+            response.write_len(),
         ) {
             -1 => Err(FanotifyError::Write(
                 Error::last_os_error().raw_os_error().unwrap_or_default(),
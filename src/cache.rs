@@ -0,0 +1,434 @@
+//! Reusing decisions on permission events for unchanged files.
+//!
+//! Busy hosts raise the same permission events over and over for the same
+//! files, and running the policy or a scanner for each of them is wasted
+//! work. A [`DecisionCache`] keeps the responses keyed on the identity of
+//! the file (device, inode, modification time and size) and the event, and
+//! optionally on the executable and user of the accessing process. Entries
+//! expire after a time to live, and the least recently used ones are
+//! evicted once the cache is full.
+//!
+//! Allowed files can also get an ignore mark, so the kernel stops
+//! reporting their permission events at all. The kernel clears the mark
+//! when the file is modified (it is placed without
+//! [`FAN_MARK_IGNORED_SURV_MODIFY`]), and the events come back.
+//! Ignore marks apply to every process, so they are refused unless the
+//! cache is keyed on neither the executable nor the user.
+//!
+//! # Example
+//! This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+//! ```rust
+//! # use naughtyfy::cache::*;
+//! # use naughtyfy::flags::*;
+//! # use naughtyfy::group::*;
+//! # use std::time::Duration;
+//! let dir = std::env::temp_dir().canonicalize().unwrap();
+//! let dir = dir.join(format!("naughtyfy-cache-{}", std::process::id()));
+//! std::fs::create_dir(&dir).unwrap();
+//! std::fs::write(dir.join("file"), b"").unwrap();
+//!
+//! // The accesses come from a thread of this process.
+//! let builder = Fanotify::builder().exclude_self(false);
+//! match builder.class(Class::Content).build() {
+//!     Ok(group) => {
+//!         group
+//!             .mark(FAN_MARK_ADD, FAN_OPEN_PERM | FAN_EVENT_ON_CHILD, AT_FDCWD, dir.as_path())
+//!             .unwrap();
+//!         let cache = DecisionCache::builder().capacity(1024).build();
+//!         let file = dir.join("file");
+//!         let reader = std::thread::spawn(move || (std::fs::read(&file), std::fs::read(&file)));
+//!
+//!         for mut event in group.events().take(2).map(Result::unwrap) {
+//!             let response = cache.decide(&event, |_| FAN_ALLOW);
+//!             event.take_permission().unwrap().respond(response).unwrap();
+//!         }
+//!         let (first, second) = reader.join().unwrap();
+//!         assert!(first.is_ok() && second.is_ok());
+//!         assert_eq!((cache.misses(), cache.hits()), (1, 1));
+//!     }
+//!     Err(e) => {
+//!         // This can fail for multiple reason, most common being privileges.
+//!         eprintln!("Cannot get fd due to {e}");
+//!     }
+//! }
+//! std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::{
+    api, errors::FanotifyError, event::Event, flags::*, group::Fanotify, group::PERM_EVENTS,
+    policy::Access,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    os::fd::{AsRawFd, BorrowedFd, OwnedFd},
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, Weak},
+    time::{Duration, Instant},
+};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::{api::*, dispatch::PermissionDispatcher};
+
+/// Time to live of [`CacheBuilder::default()`].
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+/// Capacity of [`CacheBuilder::default()`].
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+/// Identity of the content of a file, changes when it is modified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileKey {
+    /// Device holding the file.
+    pub dev: u64,
+    /// Inode of the file.
+    pub ino: u64,
+    /// Last modification, in nanoseconds since the epoch.
+    pub mtime: i128,
+    /// Size in bytes.
+    pub size: i64,
+    /// The file is a regular file, only those get ignore marks.
+    pub regular: bool,
+}
+
+impl FileKey {
+    /// Identity of the file open as `fd`, `None` if it cannot be stat'ed.
+    pub fn of(fd: BorrowedFd<'_>) -> Option<Self> {
+        let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+        if unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) } != 0 {
+            return None;
+        }
+        Some(FileKey {
+            dev: stat.st_dev,
+            ino: stat.st_ino,
+            mtime: stat.st_mtime as i128 * 1_000_000_000 + stat.st_mtime_nsec as i128,
+            size: stat.st_size,
+            regular: stat.st_mode & libc::S_IFMT == libc::S_IFREG,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    file: FileKey,
+    mask: EventMask,
+    exe: Option<PathBuf>,
+    uid: Option<u32>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    response: Response,
+    expires: Instant,
+    /// Position in [`State::recency`].
+    used: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<Key, Entry>,
+    /// Keys from the least to the most recently used.
+    recency: BTreeMap<u64, Key>,
+    /// Position given to the next use.
+    next_use: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl State {
+    /// Move `key` to the most recently used position.
+    fn touch(&mut self, key: &Key) {
+        let used = self.next_use;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.used);
+            entry.used = used;
+            self.recency.insert(used, key.clone());
+            self.next_use += 1;
+        }
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used);
+        }
+    }
+}
+
+/// Configuration of a [`DecisionCache`], created by [`DecisionCache::builder()`].
+#[derive(Debug, Clone)]
+pub struct CacheBuilder {
+    ttl: Duration,
+    capacity: usize,
+    exe: bool,
+    uid: bool,
+    ignore: Option<Weak<OwnedFd>>,
+}
+
+impl Default for CacheBuilder {
+    /// [`DEFAULT_TTL`] and [`DEFAULT_CAPACITY`], keyed on the executable
+    /// and the user, without ignore marks.
+    fn default() -> Self {
+        CacheBuilder {
+            ttl: DEFAULT_TTL,
+            capacity: DEFAULT_CAPACITY,
+            exe: true,
+            uid: true,
+            ignore: None,
+        }
+    }
+}
+
+impl CacheBuilder {
+    /// Time a decision is reused for, counted from when it was taken.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Number of decisions kept, the least recently used is evicted
+    /// for a new one.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Keep separate decisions per executable of the accessing process,
+    /// turning it on drops [`CacheBuilder::ignore_allowed()`].
+    pub fn key_exe(mut self, exe: bool) -> Self {
+        self.exe = exe;
+        if exe {
+            self.ignore = None;
+        }
+        self
+    }
+
+    /// Keep separate decisions per effective user id of the accessing process,
+    /// turning it on drops [`CacheBuilder::ignore_allowed()`].
+    pub fn key_uid(mut self, uid: bool) -> Self {
+        self.uid = uid;
+        if uid {
+            self.ignore = None;
+        }
+        self
+    }
+
+    /// Place an ignore mark in `group` on the regular files it allows,
+    /// see the [module documentation](crate::cache).
+    ///
+    /// Returns [`FanotifyError::Invalid`] unless set after
+    /// `key_exe(false)` and `key_uid(false)`, the mark would silence the
+    /// events of every process after one of them was allowed.
+    pub fn ignore_allowed(mut self, group: &Fanotify) -> Result<Self, FanotifyError> {
+        if self.exe || self.uid {
+            return Err(FanotifyError::Invalid(
+                "Ignore marks need a cache keyed on neither the executable nor the user"
+                    .to_string(),
+            ));
+        }
+        self.ignore = Some(group.downgrade());
+        Ok(self)
+    }
+
+    /// Create the cache.
+    pub fn build(self) -> DecisionCache {
+        DecisionCache {
+            config: self,
+            state: Mutex::default(),
+        }
+    }
+}
+
+/// Responses to permission events, reused while the file is unchanged.
+/// See the [module documentation](crate::cache).
+///
+/// Shared by reference or behind an [`std::sync::Arc`], Eg: between the
+/// workers of a [`PermissionDispatcher`]. Clear it when the policy
+/// deciding changes.
+#[derive(Debug)]
+pub struct DecisionCache {
+    config: CacheBuilder,
+    state: Mutex<State>,
+}
+
+impl Default for DecisionCache {
+    fn default() -> Self {
+        CacheBuilder::default().build()
+    }
+}
+
+impl DecisionCache {
+    /// Configure a new cache.
+    pub fn builder() -> CacheBuilder {
+        CacheBuilder::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Key of `event`, `None` if its file cannot be identified.
+    fn key(&self, event: &Event) -> Option<Key> {
+        let file = FileKey::of(event.fd()?)?;
+        let access = Access::new(PathBuf::new(), event.mask(), event.pid());
+        Some(Key {
+            file,
+            mask: event.mask(),
+            exe: self
+                .config
+                .exe
+                .then(|| access.exe().map(PathBuf::from))
+                .flatten(),
+            uid: self
+                .config
+                .uid
+                .then(|| access.ids().map(|ids| ids.0))
+                .flatten(),
+        })
+    }
+
+    /// Key of `event` if it is a permission event on a known file.
+    fn permission_key(&self, event: &Event) -> Option<Key> {
+        match event.mask().intersects(PERM_EVENTS) {
+            true => self.key(event),
+            false => None,
+        }
+    }
+
+    /// Kept response for `key`, counted as a hit or a miss.
+    fn lookup(&self, key: &Key, now: Instant) -> Option<Response> {
+        let mut state = self.state();
+        match state.entries.get(key) {
+            Some(entry) if entry.expires > now => {
+                let response = entry.response;
+                state.touch(key);
+                state.hits += 1;
+                return Some(response);
+            }
+            Some(_) => state.remove(key),
+            None => {}
+        }
+        state.misses += 1;
+        None
+    }
+
+    /// Keep `response`, and place the ignore mark of an allowed file.
+    fn store(&self, event: &Event, key: Key, response: Response, now: Instant) {
+        if response == FAN_ALLOW && key.file.regular {
+            self.ignore(event);
+        }
+        self.insert_key(key, response, now);
+    }
+
+    /// Response for `event`, from the cache or from `decide`.
+    ///
+    /// Events without a file that can be identified are always decided.
+    /// Decisions are only kept for permission events.
+    pub fn decide<F>(&self, event: &Event, decide: F) -> Response
+    where
+        F: FnOnce(&Event) -> Response,
+    {
+        let Some(key) = self.permission_key(event) else {
+            return decide(event);
+        };
+        let now = Instant::now();
+        if let Some(response) = self.lookup(&key, now) {
+            return response;
+        }
+        // Decided without the lock, concurrent misses on a key all decide.
+        let response = decide(event);
+        self.store(event, key, response, now);
+        response
+    }
+
+    /// Kept response for `event`, for callers deciding which responses
+    /// to keep with [`DecisionCache::insert()`].
+    pub fn get(&self, event: &Event) -> Option<Response> {
+        self.lookup(&self.permission_key(event)?, Instant::now())
+    }
+
+    /// Keep `response` for `event`, ignored if `event` is not a
+    /// permission event on a known file.
+    pub fn insert(&self, event: &Event, response: Response) {
+        if let Some(key) = self.permission_key(event) {
+            self.store(event, key, response, Instant::now());
+        }
+    }
+
+    fn insert_key(&self, key: Key, response: Response, now: Instant) {
+        if self.config.capacity == 0 {
+            return;
+        }
+        let mut state = self.state();
+        if !state.entries.contains_key(&key) {
+            while state.entries.len() >= self.config.capacity {
+                let Some((_, oldest)) = state.recency.pop_first() else {
+                    break;
+                };
+                state.entries.remove(&oldest);
+            }
+        }
+        let used = state.next_use;
+        let previous = state.entries.insert(
+            key.clone(),
+            Entry {
+                response,
+                expires: now + self.config.ttl,
+                used,
+            },
+        );
+        if let Some(previous) = previous {
+            state.recency.remove(&previous.used);
+        }
+        state.recency.insert(used, key);
+        state.next_use += 1;
+    }
+
+    /// Place the ignore mark of an allowed file.
+    fn ignore(&self, event: &Event) {
+        let (Some(group), Some(fd)) = (
+            self.config.ignore.as_ref().and_then(Weak::upgrade),
+            event.fd(),
+        ) else {
+            return;
+        };
+        let path = format!("/proc/self/fd/{}", fd.as_raw_fd());
+        let mask = event.mask() & PERM_EVENTS;
+        if let Err(e) = api::mark(
+            &group,
+            FAN_MARK_ADD | FAN_MARK_IGNORED_MASK,
+            mask,
+            AT_FDCWD,
+            path.as_str(),
+        ) {
+            eprintln!("{e}");
+        }
+    }
+
+    /// Number of decisions kept, expired ones included until evicted.
+    pub fn len(&self) -> usize {
+        self.state().entries.len()
+    }
+
+    /// Check if no decision is kept.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forget every decision, Eg: after the policy changed. Ignore marks
+    /// already placed stay until the files are modified.
+    pub fn clear(&self) {
+        let mut state = self.state();
+        state.entries.clear();
+        state.recency.clear();
+    }
+
+    /// Number of events answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.state().hits
+    }
+
+    /// Number of events decided by the caller.
+    pub fn misses(&self) -> u64 {
+        self.state().misses
+    }
+}
@@ -0,0 +1,177 @@
+//! Timeouts and cancellation of blocking reads.
+//!
+//! A read from a blocking group only returns once an event arrives.
+//! [`wait_readable()`] waits with [`poll(2)`](https://man7.org/linux/man-pages/man2/poll.2.html)
+//! instead, up to a timeout and until a [`Canceller`] fires, so a daemon
+//! can stop its reader threads without signals.
+//!
+//! # Example
+//! This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+//! ```rust
+//! # use naughtyfy::cancel::*;
+//! # use naughtyfy::errors::*;
+//! # use naughtyfy::group::*;
+//! match Fanotify::builder().build() {
+//!     Ok(group) => {
+//!         let canceller = Canceller::new().unwrap();
+//!         let handle = canceller.clone();
+//!         let stopper = std::thread::spawn(move || {
+//!             std::thread::sleep(std::time::Duration::from_millis(50));
+//!             handle.cancel().unwrap();
+//!         });
+//!         // Nothing is marked, only the canceller can wake the reader up.
+//!         let res = group.read_cancellable(&canceller, None);
+//!         assert!(matches!(res, Err(FanotifyError::Cancelled)));
+//!         stopper.join().unwrap();
+//!     }
+//!     Err(e) => {
+//!         // This can fail for multiple reason, most common being privileges.
+//!         eprintln!("Cannot get fd due to {e}");
+//!     }
+//! }
+//! ```
+
+use crate::errors::FanotifyError;
+use std::{
+    io::Error,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::api::*;
+
+/// Handle waking up readers blocked in [`wait_readable()`] from any thread.
+///
+/// Backed by an eventfd shared by all clones. Once [`Canceller::cancel()`]
+/// is called, every wait using it returns [`FanotifyError::Cancelled`]
+/// until [`Canceller::reset()`].
+#[derive(Debug, Clone)]
+pub struct Canceller {
+    fd: Arc<OwnedFd>,
+}
+
+impl Canceller {
+    /// Create a canceller that has not fired.
+    /// Returns [`FanotifyError::Init`] if the eventfd cannot be created.
+    pub fn new() -> Result<Self, FanotifyError> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd < 0 {
+            return Err(FanotifyError::Init(
+                Error::last_os_error().raw_os_error().unwrap_or_default(),
+            ));
+        }
+        Ok(Canceller {
+            fd: Arc::new(unsafe { OwnedFd::from_raw_fd(fd) }),
+        })
+    }
+
+    /// Wake up every reader waiting on this canceller, now and
+    /// until [`Canceller::reset()`].
+    pub fn cancel(&self) -> Result<(), FanotifyError> {
+        let one: u64 = 1;
+        let res = unsafe {
+            libc::write(
+                self.fd.as_raw_fd(),
+                &one as *const u64 as *const libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+        match res {
+            -1 => match Error::last_os_error().raw_os_error().unwrap_or_default() {
+                // The counter is saturated, it already fired.
+                libc::EAGAIN => Ok(()),
+                errno => Err(FanotifyError::Write(errno)),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Let waits block again after [`Canceller::cancel()`].
+    pub fn reset(&self) -> Result<(), FanotifyError> {
+        let mut count: u64 = 0;
+        let res = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                &mut count as *mut u64 as *mut libc::c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+        match res {
+            -1 => match Error::last_os_error().raw_os_error().unwrap_or_default() {
+                // Not fired.
+                libc::EAGAIN => Ok(()),
+                errno => Err(FanotifyError::Read(errno)),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Check if [`Canceller::cancel()`] was called since the last reset.
+    pub fn is_cancelled(&self) -> bool {
+        let mut fds = [libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        }];
+        unsafe { libc::poll(fds.as_mut_ptr(), 1, 0) == 1 }
+    }
+}
+
+impl AsFd for Canceller {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// Milliseconds for `poll()`, rounded up so a wait never ends early.
+pub(crate) fn poll_timeout(timeout: Duration) -> libc::c_int {
+    let millis = timeout.as_nanos().div_ceil(1_000_000);
+    millis.min(libc::c_int::MAX as u128) as libc::c_int
+}
+
+/// Wait until `fd` has events to read.
+///
+/// Returns `Ok(true)` once `fd` is readable and `Ok(false)` when `timeout`
+/// elapsed first, `None` waits forever. Returns [`FanotifyError::Cancelled`]
+/// if `canceller` fired, even when `fd` is readable too. A wait interrupted
+/// by a signal (`EINTR`) resumes with the time left. Other `poll()` errors
+/// are returned as [`FanotifyError::Read`].
+pub fn wait_readable<F: AsFd + ?Sized>(
+    fd: &F,
+    timeout: Option<Duration>,
+    canceller: Option<&Canceller>,
+) -> Result<bool, FanotifyError> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut fds = [
+        libc::pollfd {
+            fd: fd.as_fd().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: canceller.map_or(-1, |canceller| canceller.fd.as_raw_fd()),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    loop {
+        let millis = match deadline {
+            Some(deadline) => poll_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => -1,
+        };
+        let res = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, millis) };
+        if res == -1 {
+            match Error::last_os_error().raw_os_error().unwrap_or_default() {
+                libc::EINTR => continue,
+                errno => return Err(FanotifyError::Read(errno)),
+            }
+        }
+        if fds[1].revents != 0 {
+            return Err(FanotifyError::Cancelled);
+        }
+        return Ok(fds[0].revents != 0);
+    }
+}
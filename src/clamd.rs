@@ -0,0 +1,440 @@
+//! [`Scanner`] backed by a ClamAV daemon.
+//!
+//! [`ClamdScanner`] talks to `clamd` over its local Unix socket, in
+//! sessions (`IDSESSION`) kept in a small pool so each scan does not pay
+//! for a new connection. Files are either passed as a file descriptor
+//! with `SCM_RIGHTS` ([`ClamdMode::Fildes`], clamd reads the file itself)
+//! or streamed through the socket ([`ClamdMode::Instream`]).
+//!
+//! Every exchange is bounded by a timeout, a scan that cannot complete is
+//! reported as [`Verdict::Error`]. In a [`crate::scan::ScanPipeline`]
+//! the verdicts become the responses written to the kernel, and
+//! [`ClamdScanner::scan_fd()`] serves loops answering with [`write()`].
+//!
+//! # Example
+//! Scanning files against a fake clamd.
+//! ```rust
+//! # use naughtyfy::clamd::*;
+//! # use naughtyfy::scan::*;
+//! # use std::io::{Read, Write};
+//! # use std::os::{fd::*, unix::net::*};
+//! # // Minimal clamd: sessions, PING, INSTREAM and FILDES.
+//! # fn command(stream: &mut UnixStream) -> Option<String> {
+//! #     let (mut command, mut byte) = (Vec::new(), [0]);
+//! #     while stream.read(&mut byte).ok()? == 1 && byte[0] != 0 {
+//! #         command.push(byte[0]);
+//! #     }
+//! #     (!command.is_empty()).then(|| String::from_utf8_lossy(&command[1..]).into_owned())
+//! # }
+//! # fn receive_fd(stream: &UnixStream) -> std::fs::File {
+//! #     let mut byte = [0u8];
+//! #     let mut iov = libc::iovec { iov_base: byte.as_mut_ptr().cast(), iov_len: 1 };
+//! #     let mut control = [0u64; 8];
+//! #     let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+//! #     msg.msg_iov = &mut iov;
+//! #     msg.msg_iovlen = 1;
+//! #     msg.msg_control = control.as_mut_ptr().cast();
+//! #     msg.msg_controllen = std::mem::size_of_val(&control) as _;
+//! #     unsafe {
+//! #         assert_eq!(libc::recvmsg(stream.as_raw_fd(), &mut msg, 0), 1);
+//! #         let fd = std::ptr::read_unaligned(libc::CMSG_DATA(libc::CMSG_FIRSTHDR(&msg)).cast());
+//! #         std::fs::File::from_raw_fd(fd)
+//! #     }
+//! # }
+//! # fn serve(mut stream: UnixStream) {
+//! #     assert_eq!(command(&mut stream).as_deref(), Some("IDSESSION"));
+//! #     let mut id = 0;
+//! #     while let Some(command) = command(&mut stream) {
+//! #         id += 1;
+//! #         let mut data = Vec::new();
+//! #         let reply = match command.as_str() {
+//! #             "PING" => "PONG".to_string(),
+//! #             "END" => return,
+//! #             "FILDES" => {
+//! #                 receive_fd(&stream).read_to_end(&mut data).unwrap();
+//! #                 "fd[3]: ".to_string()
+//! #             }
+//! #             _ => {
+//! #                 let mut len = [0; 4];
+//! #                 stream.read_exact(&mut len).unwrap();
+//! #                 while u32::from_be_bytes(len) > 0 {
+//! #                     let start = data.len();
+//! #                     data.resize(start + u32::from_be_bytes(len) as usize, 0);
+//! #                     stream.read_exact(&mut data[start..]).unwrap();
+//! #                     stream.read_exact(&mut len).unwrap();
+//! #                 }
+//! #                 "stream: ".to_string()
+//! #             }
+//! #         };
+//! #         let infected = data.windows(7).any(|w| w == b"NAUGHTY");
+//! #         let verdict = match (command.as_str(), infected) {
+//! #             ("PING", _) => "",
+//! #             (_, true) => "Naughty.Test FOUND",
+//! #             (_, false) => "OK",
+//! #         };
+//! #         stream.write_all(format!("{id}: {reply}{verdict}\0").as_bytes()).unwrap();
+//! #     }
+//! # }
+//! let dir = std::env::temp_dir().join(format!("naughtyfy-clamd-{}", std::process::id()));
+//! std::fs::create_dir(&dir).unwrap();
+//! let socket = dir.join("clamd.sock");
+//! let listener = UnixListener::bind(&socket).unwrap();
+//! std::thread::spawn(move || {
+//!     for stream in listener.incoming() {
+//!         std::thread::spawn(move || serve(stream.unwrap()));
+//!     }
+//! });
+//! std::fs::write(dir.join("clean"), b"nothing to see").unwrap();
+//! std::fs::write(dir.join("infected"), b"something NAUGHTY").unwrap();
+//!
+//! for mode in [ClamdMode::Fildes, ClamdMode::Instream] {
+//!     let clamd = ClamdScanner::builder().socket(&socket).mode(mode).build();
+//!     clamd.ping().unwrap();
+//!     let clean = std::fs::File::open(dir.join("clean")).unwrap();
+//!     assert_eq!(clamd.scan_fd(clean.as_fd()), Verdict::Clean);
+//!     let infected = std::fs::File::open(dir.join("infected")).unwrap();
+//!     let verdict = clamd.scan_fd(infected.as_fd());
+//!     assert_eq!(verdict, Verdict::Infected("Naughty.Test".to_string()));
+//! }
+//! std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::{event::Event, scan::*};
+use std::{
+    io::{self, BufRead, BufReader},
+    mem,
+    os::{
+        fd::{AsRawFd, BorrowedFd, FromRawFd, RawFd},
+        unix::{fs::FileExt, net::UnixStream},
+    },
+    path::PathBuf,
+    ptr,
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::api::*;
+
+/// Socket of [`ClamdBuilder::default()`], the `LocalSocket` of most
+/// distributions.
+pub const DEFAULT_CLAMD_SOCKET: &str = "/var/run/clamav/clamd.ctl";
+
+/// Timeout of [`ClamdBuilder::default()`].
+pub const DEFAULT_CLAMD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sessions kept open by [`ClamdBuilder::default()`].
+pub const DEFAULT_CLAMD_POOL: usize = 4;
+
+/// Size of the chunks sent with [`ClamdMode::Instream`].
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// How files reach clamd.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ClamdMode {
+    /// Pass the file descriptor (`FILDES`), clamd must run on this host.
+    #[default]
+    Fildes,
+    /// Stream the content through the socket (`INSTREAM`),
+    /// limited by the `StreamMaxLength` of clamd.
+    Instream,
+}
+
+/// Configuration of a [`ClamdScanner`], created by [`ClamdScanner::builder()`].
+#[derive(Debug, Clone)]
+pub struct ClamdBuilder {
+    socket: PathBuf,
+    mode: ClamdMode,
+    timeout: Duration,
+    pool: usize,
+}
+
+impl Default for ClamdBuilder {
+    /// [`DEFAULT_CLAMD_SOCKET`], [`ClamdMode::Fildes`],
+    /// [`DEFAULT_CLAMD_TIMEOUT`] and [`DEFAULT_CLAMD_POOL`].
+    fn default() -> Self {
+        ClamdBuilder {
+            socket: PathBuf::from(DEFAULT_CLAMD_SOCKET),
+            mode: ClamdMode::default(),
+            timeout: DEFAULT_CLAMD_TIMEOUT,
+            pool: DEFAULT_CLAMD_POOL,
+        }
+    }
+}
+
+impl ClamdBuilder {
+    /// Path of the Unix socket clamd listens on.
+    pub fn socket<P: Into<PathBuf>>(mut self, socket: P) -> Self {
+        self.socket = socket.into();
+        self
+    }
+
+    /// How files reach clamd.
+    pub fn mode(mut self, mode: ClamdMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Time each read from or write to clamd may take.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Number of idle sessions kept open, more are opened when needed.
+    pub fn pool(mut self, pool: usize) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Create the scanner, connections are opened on first use.
+    pub fn build(self) -> ClamdScanner {
+        ClamdScanner {
+            config: self,
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+/// An `IDSESSION` connection, replies are prefixed with the request id.
+#[derive(Debug)]
+struct Session {
+    stream: BufReader<UnixStream>,
+    next_id: u64,
+}
+
+impl Session {
+    fn open(config: &ClamdBuilder) -> io::Result<Self> {
+        let stream = UnixStream::connect(&config.socket)?;
+        stream.set_read_timeout(Some(config.timeout))?;
+        stream.set_write_timeout(Some(config.timeout))?;
+        send(&stream, b"zIDSESSION\0")?;
+        Ok(Session {
+            stream: BufReader::new(stream),
+            next_id: 1,
+        })
+    }
+
+    /// Send `command` (without the `z` prefix), `body` sends what follows
+    /// it, and return the reply without its id.
+    fn request(
+        &mut self,
+        command: &str,
+        body: impl FnOnce(&UnixStream) -> io::Result<()>,
+    ) -> io::Result<String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        send(self.stream.get_ref(), format!("z{command}\0").as_bytes())?;
+        body(self.stream.get_ref())?;
+        let mut reply = Vec::new();
+        self.stream.read_until(0, &mut reply)?;
+        if reply.pop() != Some(0) {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "clamd closed the session",
+            ));
+        }
+        let reply = String::from_utf8_lossy(&reply);
+        match reply.split_once(": ") {
+            Some((reply_id, reply)) if reply_id == id.to_string() => Ok(reply.to_string()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unexpected clamd reply {reply:?} to request {id}"),
+            )),
+        }
+    }
+
+    /// End the session, clamd closes it.
+    fn end(self) {
+        let _ = send(self.stream.get_ref(), b"zEND\0");
+    }
+}
+
+/// Write all of `data`, without raising `SIGPIPE` if clamd is gone.
+fn send(stream: &UnixStream, mut data: &[u8]) -> io::Result<()> {
+    while !data.is_empty() {
+        let sent = unsafe {
+            libc::send(
+                stream.as_raw_fd(),
+                data.as_ptr().cast(),
+                data.len(),
+                libc::MSG_NOSIGNAL,
+            )
+        };
+        match sent {
+            -1 => {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+            sent => data = &data[sent as usize..],
+        }
+    }
+    Ok(())
+}
+
+/// Pass `fd` with `SCM_RIGHTS`, along with `data` (the byte clamd expects).
+pub(crate) fn send_fd(stream: &UnixStream, fd: RawFd, data: &[u8]) -> io::Result<()> {
+    let mut iov = libc::iovec {
+        iov_base: data.as_ptr() as *mut _,
+        iov_len: data.len(),
+    };
+    // `u64` keeps the control buffer aligned for `cmsghdr`.
+    let mut control = [0u64; 4];
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    unsafe {
+        msg.msg_controllen = libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) as _;
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<RawFd>() as u32) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg).cast(), fd);
+        loop {
+            match libc::sendmsg(stream.as_raw_fd(), &msg, libc::MSG_NOSIGNAL) {
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+                -1 => return Err(io::Error::last_os_error()),
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Stream the content of `fd` as `INSTREAM` chunks, then the end marker.
+fn send_stream(stream: &UnixStream, fd: BorrowedFd<'_>) -> io::Result<()> {
+    // Borrowed, never closed.
+    let file = mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(fd.as_raw_fd()) });
+    let mut chunk = vec![0; 4 + CHUNK_LEN];
+    let mut offset = 0;
+    loop {
+        let read = match file.read_at(&mut chunk[4..], offset) {
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        chunk[..4].copy_from_slice(&(read as u32).to_be_bytes());
+        send(stream, &chunk[..4 + read])?;
+        if read == 0 {
+            return Ok(());
+        }
+        offset += read as u64;
+    }
+}
+
+/// Socket timeouts surface as `WouldBlock`.
+fn timed_out(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+/// Verdict of a scan reply, Eg: `stream: Eicar-Signature FOUND`.
+fn verdict(reply: &str) -> Verdict {
+    if let Some(found) = reply.strip_suffix(" FOUND") {
+        let name = found.rsplit_once(": ").map_or(found, |(_, name)| name);
+        return Verdict::Infected(name.to_string());
+    }
+    match reply.ends_with(": OK") {
+        true => Verdict::Clean,
+        false => Verdict::Error(format!("clamd replied {reply:?}")),
+    }
+}
+
+/// Scanner asking clamd, see the [module documentation](crate::clamd).
+#[derive(Debug)]
+pub struct ClamdScanner {
+    config: ClamdBuilder,
+    idle: Mutex<Vec<Session>>,
+}
+
+impl ClamdScanner {
+    /// Configure a new scanner.
+    pub fn builder() -> ClamdBuilder {
+        ClamdBuilder::default()
+    }
+
+    fn idle(&self) -> MutexGuard<'_, Vec<Session>> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Run `command` on an idle session, or on a new one. A pooled session
+    /// clamd closed in the meantime (`IdleTimeout`) is retried on a new one.
+    fn request<B>(&self, command: &str, body: B) -> io::Result<String>
+    where
+        B: Fn(&UnixStream) -> io::Result<()>,
+    {
+        let pooled = self.idle().pop();
+        let mut reply = Err(io::Error::from(io::ErrorKind::NotConnected));
+        let mut session = None;
+        if let Some(mut pooled) = pooled {
+            reply = pooled.request(command, &body);
+            session = Some(pooled);
+        }
+        if matches!(&reply, Err(e) if !timed_out(e)) {
+            let mut fresh = Session::open(&self.config)?;
+            reply = fresh.request(command, &body);
+            session = Some(fresh);
+        }
+        let session = session.expect("a session was used");
+        // A failed session is in an unknown state, drop it.
+        let reply = match reply {
+            Ok(reply) => reply,
+            Err(e) if timed_out(&e) => {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "clamd timed out"))
+            }
+            Err(e) => return Err(e),
+        };
+        let mut idle = self.idle();
+        if idle.len() < self.config.pool {
+            idle.push(session);
+        } else {
+            drop(idle);
+            session.end();
+        }
+        Ok(reply)
+    }
+
+    /// Check that clamd answers.
+    pub fn ping(&self) -> io::Result<()> {
+        match self.request("PING", |_| Ok(()))?.as_str() {
+            "PONG" => Ok(()),
+            reply => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("clamd replied {reply:?} to PING"),
+            )),
+        }
+    }
+
+    /// Scan the file open as `fd`.
+    pub fn scan_fd(&self, fd: BorrowedFd<'_>) -> Verdict {
+        let reply = match self.config.mode {
+            ClamdMode::Fildes => {
+                self.request("FILDES", |stream| send_fd(stream, fd.as_raw_fd(), &[0]))
+            }
+            ClamdMode::Instream => self.request("INSTREAM", |stream| send_stream(stream, fd)),
+        };
+        match reply {
+            Ok(reply) => verdict(&reply),
+            Err(e) => Verdict::Error(e.to_string()),
+        }
+    }
+}
+
+impl Scanner for ClamdScanner {
+    fn scan(&self, fd: BorrowedFd<'_>, _event: &Event) -> Verdict {
+        self.scan_fd(fd)
+    }
+}
+
+impl Drop for ClamdScanner {
+    fn drop(&mut self) {
+        for session in self.idle().drain(..) {
+            session.end();
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! Answering permission events from a pool of worker threads.
+//!
+//! Deciding on an access can be slow (hashing, scanning), and the process
+//! opening the file waits for it. With a single thread reading and deciding,
+//! every other pending access waits too. [`PermissionDispatcher`] reads
+//! the events of a group on one thread and hands them to worker threads
+//! through an unbounded queue, so busy workers never block the reader.
+//!
+//! Each request has a deadline, once it passes the default response of the
+//! group is written for it and the late decision of the worker is dropped.
+//!
+//! # Example
+//! This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+//! ```rust
+//! # use naughtyfy::dispatch::*;
+//! # use naughtyfy::flags::*;
+//! # use naughtyfy::group::*;
+//! # use std::time::Duration;
+//! let dir = std::env::temp_dir().canonicalize().unwrap();
+//! let dir = dir.join(format!("naughtyfy-dispatch-{}", std::process::id()));
+//! std::fs::create_dir(&dir).unwrap();
+//! std::fs::write(dir.join("slow"), b"").unwrap();
+//!
+//! // The accesses come from this process.
+//! let builder = Fanotify::builder().exclude_self(false);
+//! match builder.class(Class::Content).build() {
+//!     Ok(group) => {
+//!         group
+//!             .mark(FAN_MARK_ADD, FAN_OPEN_PERM | FAN_EVENT_ON_CHILD, AT_FDCWD, dir.as_path())
+//!             .unwrap();
+//!         let dispatcher = PermissionDispatcher::builder()
+//!             .workers(2)
+//!             .deadline(Duration::from_millis(100))
+//!             .default_response(FAN_DENY)
+//!             .spawn(group, |event| {
+//!                 if event.path().is_ok_and(|path| path.ends_with("slow")) {
+//!                     // Misses the deadline, the access is denied.
+//!                     std::thread::sleep(Duration::from_millis(500));
+//!                 }
+//!                 FAN_ALLOW
+//!             })
+//!             .unwrap();
+//!
+//!         let err = std::fs::read(dir.join("slow")).unwrap_err();
+//!         assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+//!         assert_eq!(dispatcher.expired(), 1);
+//!         dispatcher.shutdown().unwrap();
+//!     }
+//!     Err(e) => {
+//!         // This can fail for multiple reason, most common being privileges.
+//!         eprintln!("Cannot get fd due to {e}");
+//!     }
+//! }
+//! std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::{
+    cancel::Canceller,
+    errors::FanotifyError,
+    event::Event,
+    flags::*,
+    group::{without_audit, Fanotify},
+    permission::PermissionRequest,
+};
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{
+        atomic::{self, AtomicBool, AtomicUsize},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::{api::*, group::*};
+
+/// Deadline of [`DispatcherBuilder::default()`].
+pub const DEFAULT_DEADLINE: Duration = Duration::from_secs(5);
+
+/// Request shared by the worker deciding on it and the deadline timer,
+/// answered by whichever takes it first.
+type Slot = Arc<Mutex<Option<PermissionRequest>>>;
+
+/// Take the request out of `slot` if nobody answered it yet.
+fn take(slot: &Slot) -> Option<PermissionRequest> {
+    slot.lock().unwrap_or_else(|e| e.into_inner()).take()
+}
+
+/// An event waiting for a worker.
+struct Job {
+    event: Event,
+    slot: Slot,
+}
+
+/// A request waiting for its deadline.
+struct Pending {
+    deadline: Instant,
+    slot: Slot,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Earliest deadline first in a [`BinaryHeap`].
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.deadline.cmp(&self.deadline)
+    }
+}
+
+/// Configuration of a [`PermissionDispatcher`],
+/// created by [`PermissionDispatcher::builder()`].
+#[derive(Debug, Clone)]
+pub struct DispatcherBuilder {
+    workers: usize,
+    deadline: Duration,
+    default_response: Option<Response>,
+}
+
+impl Default for DispatcherBuilder {
+    /// One worker per available CPU and a [`DEFAULT_DEADLINE`].
+    fn default() -> Self {
+        DispatcherBuilder {
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            deadline: DEFAULT_DEADLINE,
+            default_response: None,
+        }
+    }
+}
+
+impl DispatcherBuilder {
+    /// Number of worker threads deciding on requests.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers;
+        self
+    }
+
+    /// Time a request may wait for its decision, counted from the read.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Response written once the deadline passed, replaces the
+    /// [`Fanotify::default_response()`] of the group.
+    pub fn default_response(mut self, response: Response) -> Self {
+        self.default_response = Some(response);
+        self
+    }
+
+    /// Start reading `group` and deciding on its permission events
+    /// with `decide`.
+    ///
+    /// `decide` gets the event with its own duplicate of the event file
+    /// descriptor, other events of the group are dropped. [`FAN_AUDIT`]
+    /// is dropped from the responses if the group was initialized without
+    /// [`FAN_ENABLE_AUDIT`], Eg: for [`crate::policy::Action::Audit`].
+    /// Other invalid responses are logged and the default response sent
+    /// in their place.
+    ///
+    /// Returns [`FanotifyError::Invalid`] for a group of [`Class::Notif`],
+    /// without workers or with an invalid default response, and
+    /// [`FanotifyError::Init`] if a thread cannot be spawned.
+    pub fn spawn<F>(
+        self,
+        mut group: Fanotify,
+        decide: F,
+    ) -> Result<PermissionDispatcher, FanotifyError>
+    where
+        F: Fn(&Event) -> Response + Send + Sync + 'static,
+    {
+        if !group.class().allows_permission() {
+            return Err(FanotifyError::Invalid(
+                "Dispatching needs a group initialized with \
+                FAN_CLASS_CONTENT or FAN_CLASS_PRE_CONTENT"
+                    .to_string(),
+            ));
+        }
+        if self.workers == 0 {
+            return Err(FanotifyError::Invalid(
+                "Dispatching needs at least one worker".to_string(),
+            ));
+        }
+        if let Some(response) = self.default_response {
+            group.set_default_response(response)?;
+        }
+
+        let canceller = Canceller::new()?;
+        let expired = Arc::new(AtomicUsize::new(0));
+        let stopping = Arc::new(AtomicBool::new(false));
+        let (deadlines, timeouts) = mpsc::channel::<Pending>();
+        let group = Arc::new(group);
+        let mut dispatcher = PermissionDispatcher {
+            group: Some(group.clone()),
+            canceller: canceller.clone(),
+            stopping: stopping.clone(),
+            expired: expired.clone(),
+            deadlines: Some(deadlines.clone()),
+            reader: None,
+            workers: Vec::with_capacity(self.workers),
+            timer: None,
+        };
+
+        dispatcher.timer = Some(spawn("naughtyfy-timer", move || {
+            run_timer(timeouts, &expired)
+        })?);
+        // Dropped before `dispatcher` if spawning fails, so its workers end.
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        let decide = Arc::new(decide);
+        for _ in 0..self.workers {
+            let queue = queue.clone();
+            let decide = decide.clone();
+            let worker = spawn("naughtyfy-worker", move || run_worker(&queue, &*decide))?;
+            dispatcher.workers.push(worker);
+        }
+        let deadline = self.deadline;
+        dispatcher.reader = Some(spawn("naughtyfy-reader", move || {
+            run_reader(&group, &canceller, &stopping, deadline, &jobs, &deadlines)
+        })?);
+        Ok(dispatcher)
+    }
+}
+
+fn spawn<T: Send + 'static>(
+    name: &str,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<JoinHandle<T>, FanotifyError> {
+    thread::Builder::new()
+        .name(name.to_string())
+        .spawn(f)
+        .map_err(|e| FanotifyError::Init(e.raw_os_error().unwrap_or_default()))
+}
+
+/// Read until cancelled, queueing permission events for the workers.
+/// `stopping` ends it after the next event if it cannot be cancelled.
+fn run_reader(
+    group: &Fanotify,
+    canceller: &Canceller,
+    stopping: &AtomicBool,
+    deadline: Duration,
+    jobs: &Sender<Job>,
+    deadlines: &Sender<Pending>,
+) -> Result<(), FanotifyError> {
+    for event in group.events().cancel_on(canceller) {
+        let mut event = match event {
+            Ok(event) => event,
+            Err(FanotifyError::Cancelled) => break,
+            Err(e) => return Err(e),
+        };
+        let Some(request) = event.split_permission() else {
+            if stopping.load(atomic::Ordering::Relaxed) {
+                break;
+            }
+            continue;
+        };
+        let slot = Arc::new(Mutex::new(Some(request)));
+        let deadline = Instant::now() + deadline;
+        // Both receivers live until the dispatcher is shut down.
+        let _ = deadlines.send(Pending {
+            deadline,
+            slot: slot.clone(),
+        });
+        let _ = jobs.send(Job { event, slot });
+        if stopping.load(atomic::Ordering::Relaxed) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn run_worker(queue: &Mutex<Receiver<Job>>, decide: &(dyn Fn(&Event) -> Response + Sync)) {
+    loop {
+        let job = queue.lock().unwrap_or_else(|e| e.into_inner()).recv();
+        let Ok(job) = job else {
+            return;
+        };
+        // Skip requests the timer answered while queued.
+        if job.slot.lock().unwrap_or_else(|e| e.into_inner()).is_none() {
+            continue;
+        }
+        let response = decide(&job.event);
+        if let Some(request) = take(&job.slot) {
+            let response = without_audit(request.flags(), response);
+            if let Err(e) = request.respond(response) {
+                eprintln!("{e}");
+            }
+        }
+    }
+}
+
+/// Answer requests past their deadline with their default response.
+/// Once the workers are gone, answers whatever is left and returns.
+fn run_timer(timeouts: Receiver<Pending>, expired: &AtomicUsize) {
+    let mut pending = BinaryHeap::new();
+    loop {
+        let next = match pending.peek() {
+            Some(Pending { deadline, .. }) => {
+                timeouts.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            }
+            None => timeouts.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match next {
+            Ok(request) => pending.push(request),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        let now = Instant::now();
+        while pending
+            .peek()
+            .is_some_and(|request| request.deadline <= now)
+        {
+            let Some(Pending { slot, .. }) = pending.pop() else {
+                break;
+            };
+            if let Some(request) = take(&slot) {
+                expired.fetch_add(1, atomic::Ordering::Relaxed);
+                respond_default(request);
+            }
+        }
+    }
+    for Pending { slot, .. } in pending {
+        if let Some(request) = take(&slot) {
+            respond_default(request);
+        }
+    }
+}
+
+fn respond_default(request: PermissionRequest) {
+    let response = request.default_response();
+    if let Err(e) = request.respond(response) {
+        eprintln!("{e}");
+    }
+}
+
+/// Reads permission events on one thread and decides on them on a pool
+/// of workers, see the [module documentation](crate::dispatch).
+///
+/// Dropping the dispatcher shuts it down like [`PermissionDispatcher::shutdown()`].
+#[derive(Debug)]
+pub struct PermissionDispatcher {
+    /// Closed last, so late responses of the workers still reach it.
+    group: Option<Arc<Fanotify>>,
+    canceller: Canceller,
+    /// Set when stopping, in case the reader cannot be cancelled.
+    stopping: Arc<AtomicBool>,
+    expired: Arc<AtomicUsize>,
+    /// Keeps the timer running until the workers are done.
+    deadlines: Option<Sender<Pending>>,
+    reader: Option<JoinHandle<Result<(), FanotifyError>>>,
+    workers: Vec<JoinHandle<()>>,
+    timer: Option<JoinHandle<()>>,
+}
+
+impl PermissionDispatcher {
+    /// Start configuring a dispatcher, see [`DispatcherBuilder`].
+    pub fn builder() -> DispatcherBuilder {
+        DispatcherBuilder::default()
+    }
+
+    /// The group being read, Eg: to add marks once the workers are running.
+    pub fn group(&self) -> &Fanotify {
+        // Only taken when stopping.
+        self.group.as_ref().unwrap()
+    }
+
+    /// Number of requests answered with the default response
+    /// because their deadline passed.
+    pub fn expired(&self) -> usize {
+        self.expired.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Check if the reader thread stopped, after a read error
+    /// returned by [`PermissionDispatcher::shutdown()`].
+    pub fn is_finished(&self) -> bool {
+        self.reader.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Stop reading, let the workers finish the queued requests and
+    /// close the group. Returns the error that stopped the reader early,
+    /// or the one cancelling it. A reader that cannot be cancelled stops
+    /// after the next event of the group.
+    pub fn shutdown(mut self) -> Result<(), FanotifyError> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<(), FanotifyError> {
+        self.stopping.store(true, atomic::Ordering::Relaxed);
+        let cancelled = self.canceller.cancel();
+        let res = match self.reader.take().map(JoinHandle::join) {
+            Some(Ok(res)) => res,
+            Some(Err(_)) => Err(FanotifyError::Invalid(
+                "Dispatcher reader thread panicked".to_string(),
+            )),
+            None => Ok(()),
+        };
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+        self.deadlines.take();
+        if let Some(timer) = self.timer.take() {
+            let _ = timer.join();
+        }
+        self.group.take();
+        cancelled.and(res)
+    }
+}
+
+impl Drop for PermissionDispatcher {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            eprintln!("{e}");
+        }
+    }
+}
@@ -230,12 +230,15 @@ fn close_code_desc(code: i32) -> String {
 }
 
 /// Error type for all fanotify errors that can occure at runtime. <br>
-/// This can of 5 types <br>
+/// This can of 8 types <br>
 /// * [`FanotifyError::Init`]
 /// * [`FanotifyError::Mark`]
 /// * [`FanotifyError::Read`]
 /// * [`FanotifyError::Write`]
 /// * [`FanotifyError::Close`]
+/// * [`FanotifyError::Parse`]
+/// * [`FanotifyError::Invalid`]
+/// * [`FanotifyError::Cancelled`]
 pub enum FanotifyError {
     /// Error produced by [`init()`]
     Init(i32),
@@ -247,6 +250,16 @@ pub enum FanotifyError {
     Write(i32),
     /// Error produced by [`close()`]
     Close(i32),
+    /// Malformed or unsupported data found while parsing
+    /// events out of a read buffer or flags out of text.
+    /// Holds the description.
+    Parse(String),
+    /// Arguments rejected before reaching the kernel because they
+    /// do not fit the group configuration. Holds the description.
+    Invalid(String),
+    /// A blocked read was woken up by a [`crate::cancel::Canceller`]
+    /// before any event arrived.
+    Cancelled,
 }
 impl Error for FanotifyError {}
 
@@ -293,6 +306,18 @@ impl fmt::Display for FanotifyError {
                     close_code_desc(*code)
                 )
             }
+            Self::Parse(desc) => {
+                write!(f, "FanotifyParseError:\nDesciption: {}", desc)
+            }
+            Self::Invalid(desc) => {
+                write!(f, "FanotifyInvalidError:\nDesciption: {}", desc)
+            }
+            Self::Cancelled => {
+                write!(
+                    f,
+                    "FanotifyCancelledError:\nDesciption: The read was cancelled."
+                )
+            }
         }
     }
 }
@@ -340,6 +365,18 @@ impl Debug for FanotifyError {
                     close_code_desc(*code)
                 )
             }
+            Self::Parse(desc) => {
+                write!(f, "FanotifyParseError:\nDesciption: {}", desc)
+            }
+            Self::Invalid(desc) => {
+                write!(f, "FanotifyInvalidError:\nDesciption: {}", desc)
+            }
+            Self::Cancelled => {
+                write!(
+                    f,
+                    "FanotifyCancelledError:\nDesciption: The read was cancelled."
+                )
+            }
         }
     }
 }
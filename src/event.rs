@@ -0,0 +1,856 @@
+//! Parsing of variable length events out of a read buffer.
+//!
+//! Every event in the buffer starts with a [`fanotify_event_metadata`]
+//! and is followed by zero or more information records, each one
+//! starting with a [`fanotify_event_info_header`]. `event_len` is the
+//! offset to the next event and `metadata_len` the offset to the first
+//! record, so events of a group initialized with [`FAN_REPORT_FID`],
+//! [`FAN_REPORT_DFID_NAME`] or [`FAN_REPORT_PIDFD`] can differ in size.
+//!
+//! # Example
+//! ```rust
+//! # use naughtyfy::event::*;
+//! # use naughtyfy::flags::*;
+//! // Build the buffer a `FAN_REPORT_DFID_NAME` group returns
+//! // for `mkdir new_dir`.
+//! let handle = [0xaa_u8; 8];
+//! let name = b"new_dir\0";
+//! let info_len = 4 + 8 + 8 + handle.len() + name.len();
+//!
+//! let mut buf = Vec::new();
+//! buf.extend((24 + info_len as u32).to_ne_bytes());
+//! buf.extend([FANOTIFY_METADATA_VERSION as u8, 0]);
+//! buf.extend(24_u16.to_ne_bytes());
+//! buf.extend((FAN_CREATE | FAN_ONDIR).bits().to_ne_bytes());
+//! buf.extend(FAN_NOFD.to_ne_bytes());
+//! buf.extend(42_i32.to_ne_bytes());
+//! buf.extend([FAN_EVENT_INFO_TYPE_DFID_NAME, 0]);
+//! buf.extend((info_len as u16).to_ne_bytes());
+//! buf.extend([1_i32.to_ne_bytes(), 2_i32.to_ne_bytes()].concat());
+//! buf.extend((handle.len() as u32).to_ne_bytes());
+//! buf.extend(1_i32.to_ne_bytes());
+//! buf.extend(handle);
+//! buf.extend(name);
+//!
+//! // The buffer holds no descriptor.
+//! let event = unsafe { parse(&mut buf) }.next().unwrap().unwrap();
+//! assert_eq!(event.pid(), 42);
+//! match event.info().next().unwrap().unwrap() {
+//!     EventInfo::DfidName { fid, name } => {
+//!         assert_eq!(name, "new_dir");
+//!         assert_eq!(fid.fsid.val, [1, 2]);
+//!         assert_eq!(fid.handle.bytes, &handle);
+//!     }
+//!     info => panic!("unexpected {info:?}"),
+//! }
+//! ```
+
+use crate::{
+    errors::FanotifyError,
+    flags::*,
+    group::PERM_EVENTS,
+    permission::{PermissionRequest, Responder},
+    types::*,
+};
+use std::{
+    ffi::OsStr,
+    fmt,
+    fs::File,
+    mem,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::PathBuf,
+    ptr,
+};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::api::*;
+
+/// Get current platform sizeof of [`fanotify_event_metadata`].
+const FAN_EVENT_METADATA_LEN: usize = mem::size_of::<fanotify_event_metadata>();
+
+/// Get current platform sizeof of [`fanotify_event_info_header`].
+const FAN_EVENT_INFO_HEADER_LEN: usize = mem::size_of::<fanotify_event_info_header>();
+
+/// Offset of the [`file_handle`] inside [`fanotify_event_info_fid`].
+const FAN_EVENT_INFO_FID_HANDLE: usize = mem::offset_of!(fanotify_event_info_fid, handle);
+
+/// Get current platform sizeof of [`file_handle`].
+const FILE_HANDLE_LEN: usize = mem::size_of::<file_handle>();
+
+/// Reads a plain integer `T` at `offset`, the buffer has no alignment
+/// guarantee for records. Panics if out of bound, callers check length first.
+#[inline]
+fn read_at<T: Copy>(bytes: &[u8], offset: usize) -> T {
+    let field = &bytes[offset..offset + mem::size_of::<T>()];
+    // Only used with integer types for which any bit pattern is valid.
+    unsafe { ptr::read_unaligned(field.as_ptr() as *const T) }
+}
+
+/// Writes a plain integer `T` at `offset`. Panics if out of bound.
+#[inline]
+fn write_at<T: Copy>(bytes: &mut [u8], offset: usize, value: T) {
+    let field = &mut bytes[offset..offset + mem::size_of::<T>()];
+    unsafe { ptr::write_unaligned(field.as_mut_ptr() as *mut T, value) }
+}
+
+/// Validate the event at the start of `buf` and return its length.
+fn event_len(buf: &[u8]) -> Result<usize, FanotifyError> {
+    if buf.len() < FAN_EVENT_METADATA_LEN {
+        return Err(FanotifyError::Parse(format!(
+            "Truncated event: {} bytes left, metadata needs {}",
+            buf.len(),
+            FAN_EVENT_METADATA_LEN
+        )));
+    }
+    let event_len =
+        read_at::<u32>(buf, mem::offset_of!(fanotify_event_metadata, event_len)) as usize;
+    if event_len < FAN_EVENT_METADATA_LEN || event_len > buf.len() {
+        return Err(FanotifyError::Parse(format!(
+            "Invalid event_len {} with {} bytes left",
+            event_len,
+            buf.len()
+        )));
+    }
+    let vers = read_at::<u8>(buf, mem::offset_of!(fanotify_event_metadata, vers));
+    if vers as u32 != FANOTIFY_METADATA_VERSION {
+        return Err(FanotifyError::Parse(format!(
+            "Metadata version {} does not match FANOTIFY_METADATA_VERSION {}",
+            vers, FANOTIFY_METADATA_VERSION
+        )));
+    }
+    let metadata_len =
+        read_at::<u16>(buf, mem::offset_of!(fanotify_event_metadata, metadata_len)) as usize;
+    if metadata_len < FAN_EVENT_METADATA_LEN || metadata_len > event_len {
+        return Err(FanotifyError::Parse(format!(
+            "Invalid metadata_len {} for event_len {}",
+            metadata_len, event_len
+        )));
+    }
+    Ok(event_len)
+}
+
+/// Owned buffer that events are read into.
+///
+/// Storage is 8 byte aligned like the buffers the kernel expects.
+/// Returned by [`read_with_fid()`], use [`EventBuffer::events()`] to
+/// walk the events in it.
+///
+/// The buffer owns the event file descriptors (and pidfds) it holds,
+/// the ones not taken out with [`EventRef::take_fd()`] or
+/// [`EventRef::into_event()`] are closed when it is dropped.
+pub struct EventBuffer {
+    buf: Vec<u64>,
+    len: usize,
+}
+
+impl EventBuffer {
+    /// Create an empty buffer able to hold `capacity` bytes of events.
+    pub fn new(capacity: usize) -> Self {
+        EventBuffer {
+            buf: vec![0; capacity.div_ceil(mem::size_of::<u64>())],
+            len: 0,
+        }
+    }
+
+    /// Number of bytes the buffer can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buf.len() * mem::size_of::<u64>()
+    }
+
+    /// Number of bytes filled by the last read.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if the last read returned nothing.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Bytes filled by the last read.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.buf.as_ptr() as *const u8, self.len) }
+    }
+
+    #[inline]
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.buf.as_mut_ptr() as *mut u8, self.len) }
+    }
+
+    /// Pointer to the start of the storage, to be handed to `libc::read()`.
+    #[inline]
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.buf.as_mut_ptr() as *mut u8
+    }
+
+    /// Set the number of bytes filled by a read.
+    ///
+    /// # Safety
+    /// `len` must not exceed [`EventBuffer::capacity()`] and the bytes
+    /// must have been filled by a read from a fanotify file descriptor.
+    #[inline]
+    pub(crate) unsafe fn set_len(&mut self, len: usize) {
+        debug_assert!(len <= self.capacity());
+        self.len = len;
+    }
+
+    /// Change the capacity to `capacity` bytes, emptying the buffer.
+    pub(crate) fn resize(&mut self, capacity: usize) {
+        self.clear();
+        self.buf.resize(capacity.div_ceil(mem::size_of::<u64>()), 0);
+    }
+
+    /// Close the descriptors still held and empty the buffer.
+    pub fn clear(&mut self) {
+        for event in self.events() {
+            let Ok(mut event) = event else { break };
+            drop(event.take_fd());
+            drop(event.take_pidfd());
+        }
+        self.len = 0;
+    }
+
+    /// Keep the events for which `keep` returns true, closing the
+    /// descriptors of the others. Bytes after a malformed event are kept.
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&mut EventRef<'_>) -> bool) {
+        let (mut read, mut write) = (0, 0);
+        while read < self.len {
+            // Filled by a read, see `set_len()`.
+            let Some(Ok(mut event)) = unsafe { parse(&mut self.as_bytes_mut()[read..]) }.next()
+            else {
+                break;
+            };
+            let len = event.event_len() as usize;
+            let kept = keep(&mut event);
+            if !kept {
+                drop(event.take_fd());
+                drop(event.take_pidfd());
+            }
+            if kept {
+                self.as_bytes_mut().copy_within(read..read + len, write);
+                write += len;
+            }
+            read += len;
+        }
+        let len = self.len;
+        self.as_bytes_mut().copy_within(read..len, write);
+        self.len = write + len - read;
+    }
+
+    /// Iterate over the events in the buffer.
+    pub fn events(&mut self) -> EventIter<'_> {
+        // Filled by a read, see `set_len()`.
+        unsafe { parse(self.as_bytes_mut()) }
+    }
+
+    /// Event starting at byte `offset`, along with the offset of the
+    /// following one. `None` past the filled bytes.
+    pub(crate) fn event_at(
+        &mut self,
+        offset: usize,
+    ) -> Option<Result<(EventRef<'_>, usize), FanotifyError>> {
+        let bytes = self.as_bytes_mut().get_mut(offset..)?;
+        // Filled by a read, see `set_len()`.
+        let event = unsafe { parse(bytes) }.next()?;
+        Some(event.map(|event| {
+            let next = offset + event.event_len() as usize;
+            (event, next)
+        }))
+    }
+}
+
+impl Drop for EventBuffer {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl fmt::Debug for EventBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut list = f.debug_list();
+        let mut buf = self.as_bytes();
+        while !buf.is_empty() {
+            match event_len(buf) {
+                Ok(len) => {
+                    list.entry(&EventView(&buf[..len]));
+                    buf = &buf[len..];
+                }
+                Err(e) => {
+                    list.entry(&e);
+                    break;
+                }
+            }
+        }
+        list.finish()
+    }
+}
+
+/// Walk the events in `buf`, the bytes returned by a single read from the
+/// fanotify file descriptor. [`EventBuffer::events()`] is the safe way.
+///
+/// Views only take ownership of descriptors through [`EventRef::take_fd()`]
+/// and [`EventRef::into_event()`], the ones left in `buf` are not closed.
+///
+/// # Safety
+/// Every descriptor in `buf` (the `fd` of the events and their pidfds)
+/// must be open and owned by the caller, as when `buf` was filled by a
+/// read from a fanotify file descriptor. Taking them closes them on drop.
+pub unsafe fn parse(buf: &mut [u8]) -> EventIter<'_> {
+    EventIter { buf }
+}
+
+/// Iterator over the events of a read buffer, created by [`parse()`].
+///
+/// Yields an error and stops if an event is truncated or its
+/// version does not match [`FANOTIFY_METADATA_VERSION`].
+pub struct EventIter<'a> {
+    buf: &'a mut [u8],
+}
+
+impl<'a> Iterator for EventIter<'a> {
+    type Item = Result<EventRef<'a>, FanotifyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let buf = mem::take(&mut self.buf);
+        match event_len(buf) {
+            Ok(len) => {
+                let (bytes, rest) = buf.split_at_mut(len);
+                self.buf = rest;
+                Some(Ok(EventRef { bytes }))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Read only access to the fields of an event, shared by
+/// [`EventRef`] and the [`fmt::Debug`] of [`EventBuffer`].
+struct EventView<'a>(&'a [u8]);
+
+impl<'a> EventView<'a> {
+    #[inline]
+    fn field<T: Copy>(&self, offset: usize) -> T {
+        read_at(self.0, offset)
+    }
+
+    fn info(&self) -> InfoIter<'a> {
+        let metadata_len =
+            self.field::<u16>(mem::offset_of!(fanotify_event_metadata, metadata_len));
+        InfoIter {
+            buf: &self.0[metadata_len as usize..],
+        }
+    }
+
+    /// Offset of the pidfd inside the event, if it carries one.
+    fn pidfd_offset(&self) -> Option<usize> {
+        let metadata_len =
+            self.field::<u16>(mem::offset_of!(fanotify_event_metadata, metadata_len));
+        let mut offset = metadata_len as usize;
+        for info in self.info() {
+            if let Ok(EventInfo::Pidfd(_)) = info {
+                return Some(offset + mem::offset_of!(fanotify_event_info_pidfd, pidfd));
+            }
+            let len = read_at::<u16>(
+                self.0,
+                offset + mem::offset_of!(fanotify_event_info_header, len),
+            );
+            offset += len as usize;
+        }
+        None
+    }
+}
+
+impl fmt::Debug for EventView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Event")
+            .field(
+                "mask",
+                &self.field::<EventMask>(mem::offset_of!(fanotify_event_metadata, mask)),
+            )
+            .field(
+                "fd",
+                &self.field::<RawFd>(mem::offset_of!(fanotify_event_metadata, fd)),
+            )
+            .field(
+                "pid",
+                &self.field::<i32>(mem::offset_of!(fanotify_event_metadata, pid)),
+            )
+            .field("info", &self.info().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// View of a single event inside a read buffer.
+///
+/// The [`fanotify_event_metadata`] fields are decoded on access.
+/// The event file descriptor stays in the buffer until it is taken out
+/// with [`EventRef::take_fd()`] or [`EventRef::into_event()`], taking it
+/// replaces it with [`FAN_NOFD`] so it has exactly one owner.
+pub struct EventRef<'a> {
+    bytes: &'a mut [u8],
+}
+
+impl<'a> EventRef<'a> {
+    #[inline]
+    fn view(&self) -> EventView<'_> {
+        EventView(self.bytes)
+    }
+
+    /// Length of the event including all information records.
+    #[inline]
+    pub fn event_len(&self) -> u32 {
+        read_at(
+            self.bytes,
+            mem::offset_of!(fanotify_event_metadata, event_len),
+        )
+    }
+
+    /// Version of the metadata, equal to [`FANOTIFY_METADATA_VERSION`].
+    #[inline]
+    pub fn vers(&self) -> u8 {
+        read_at(self.bytes, mem::offset_of!(fanotify_event_metadata, vers))
+    }
+
+    /// Length of the metadata, the offset of the first information record.
+    #[inline]
+    pub fn metadata_len(&self) -> u16 {
+        read_at(
+            self.bytes,
+            mem::offset_of!(fanotify_event_metadata, metadata_len),
+        )
+    }
+
+    /// Bit mask describing the event.
+    #[inline]
+    pub fn mask(&self) -> EventMask {
+        read_at(self.bytes, mem::offset_of!(fanotify_event_metadata, mask))
+    }
+
+    /// Raw value of the fd field, [`FAN_NOFD`] once taken.
+    #[inline]
+    fn raw_fd(&self) -> RawFd {
+        read_at(self.bytes, mem::offset_of!(fanotify_event_metadata, fd))
+    }
+
+    /// File descriptor for the object being accessed, `None` for
+    /// [`FAN_NOFD`] or when it was already taken.
+    #[inline]
+    pub fn fd(&self) -> Option<BorrowedFd<'_>> {
+        match self.raw_fd() {
+            fd if fd >= 0 => Some(unsafe { BorrowedFd::borrow_raw(fd) }),
+            _ => None,
+        }
+    }
+
+    /// Take ownership of the event file descriptor.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use naughtyfy::event::*;
+    /// # use naughtyfy::flags::*;
+    /// # use std::os::fd::IntoRawFd;
+    /// let fd = std::fs::File::open("/dev/null").unwrap().into_raw_fd();
+    /// let mut buf = Vec::new();
+    /// buf.extend(24_u32.to_ne_bytes());
+    /// buf.extend([FANOTIFY_METADATA_VERSION as u8, 0]);
+    /// buf.extend(24_u16.to_ne_bytes());
+    /// buf.extend(FAN_OPEN.bits().to_ne_bytes());
+    /// buf.extend(fd.to_ne_bytes());
+    /// buf.extend(42_i32.to_ne_bytes());
+    ///
+    /// // The buffer owns `fd` from now on.
+    /// let mut event = unsafe { parse(&mut buf) }.next().unwrap().unwrap();
+    /// let file = event.take_fd();
+    /// assert!(file.is_some());
+    /// // Taken only once, the buffer now holds `FAN_NOFD`.
+    /// assert!(event.take_fd().is_none());
+    /// assert!(event.fd().is_none());
+    /// ```
+    pub fn take_fd(&mut self) -> Option<OwnedFd> {
+        let fd = self.raw_fd();
+        if fd < 0 {
+            return None;
+        }
+        write_at(
+            self.bytes,
+            mem::offset_of!(fanotify_event_metadata, fd),
+            FAN_NOFD,
+        );
+        Some(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
+    /// Take ownership of the pidfd reported with [`FAN_REPORT_PIDFD`].
+    pub fn take_pidfd(&mut self) -> Option<OwnedFd> {
+        let offset = self.view().pidfd_offset()?;
+        let pidfd = read_at::<i32>(self.bytes, offset);
+        if pidfd < 0 {
+            return None;
+        }
+        write_at(self.bytes, offset, FAN_NOPIDFD);
+        Some(unsafe { OwnedFd::from_raw_fd(pidfd) })
+    }
+
+    /// PID (or TID with [`FAN_REPORT_TID`]) that caused the event.
+    #[inline]
+    pub fn pid(&self) -> i32 {
+        read_at(self.bytes, mem::offset_of!(fanotify_event_metadata, pid))
+    }
+
+    /// Iterate over the information records following the metadata.
+    pub fn info(&self) -> InfoIter<'_> {
+        self.view().info()
+    }
+
+    /// Copy the event out of the buffer, taking its descriptors with it.
+    pub fn into_event(mut self) -> Event {
+        let info = self.bytes[self.metadata_len() as usize..].into();
+        Event {
+            mask: self.mask(),
+            pid: self.pid(),
+            fd: self.take_fd(),
+            pidfd: self.take_pidfd(),
+            info,
+            request: None,
+        }
+    }
+}
+
+impl<'a> From<EventRef<'a>> for Event {
+    fn from(event: EventRef<'a>) -> Self {
+        event.into_event()
+    }
+}
+
+impl fmt::Debug for EventRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.view().fmt(f)
+    }
+}
+
+/// An event owning its file descriptors.
+///
+/// The event file descriptor is `None` for [`FAN_NOFD`] (queue overflow,
+/// groups reporting file handles) and is closed when the event is dropped
+/// unless taken with [`Event::take_fd()`] or [`Event::into_file()`].
+/// Not [`Clone`], so a descriptor is never closed twice.
+///
+/// Permission events read through a [`crate::group::Fanotify`] carry a
+/// [`PermissionRequest`] holding the event file descriptor instead, see
+/// [`Event::permission()`].
+pub struct Event {
+    mask: EventMask,
+    pid: i32,
+    fd: Option<OwnedFd>,
+    pidfd: Option<OwnedFd>,
+    info: Box<[u8]>,
+    request: Option<PermissionRequest>,
+}
+
+impl Event {
+    /// Bit mask describing the event.
+    #[inline]
+    pub fn mask(&self) -> EventMask {
+        self.mask
+    }
+
+    /// PID (or TID with [`FAN_REPORT_TID`]) that caused the event.
+    #[inline]
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// File descriptor for the object being accessed,
+    /// borrowed from the [`PermissionRequest`] if the event has one.
+    pub fn fd(&self) -> Option<BorrowedFd<'_>> {
+        match &self.request {
+            Some(request) => Some(request.fd()),
+            None => self.fd.as_ref().map(|fd| fd.as_fd()),
+        }
+    }
+
+    /// Take ownership of the event file descriptor.
+    /// `None` when it is held by a [`PermissionRequest`].
+    #[inline]
+    pub fn take_fd(&mut self) -> Option<OwnedFd> {
+        self.fd.take()
+    }
+
+    /// Turn the event into the [`File`] being accessed.
+    /// `None` when the descriptor is held by a [`PermissionRequest`].
+    pub fn into_file(mut self) -> Option<File> {
+        self.take_fd().map(File::from)
+    }
+
+    /// Pidfd of the process that caused the event, reported with [`FAN_REPORT_PIDFD`].
+    #[inline]
+    pub fn pidfd(&self) -> Option<BorrowedFd<'_>> {
+        self.pidfd.as_ref().map(|fd| fd.as_fd())
+    }
+
+    /// Take ownership of the pidfd.
+    #[inline]
+    pub fn take_pidfd(&mut self) -> Option<OwnedFd> {
+        self.pidfd.take()
+    }
+
+    /// Path of the object being accessed, resolved through `/proc/self/fd`.
+    pub fn path(&self) -> Result<PathBuf, std::io::Error> {
+        match self.fd() {
+            Some(fd) => Fd::path_from_rawfd(fd.as_raw_fd()),
+            None => Err(std::io::Error::from_raw_os_error(libc::EBADF)),
+        }
+    }
+
+    /// Iterate over the information records of the event.
+    ///
+    /// A [`EventInfo::Pidfd`] record holds the number of the descriptor
+    /// returned by [`Event::pidfd()`].
+    pub fn info(&self) -> InfoIter<'_> {
+        InfoIter { buf: &self.info }
+    }
+
+    /// Request answering the permission event, `None` for other events
+    /// and once taken with [`Event::take_permission()`].
+    #[inline]
+    pub fn permission(&self) -> Option<&PermissionRequest> {
+        self.request.as_ref()
+    }
+
+    /// Take the request to answer it, answering consumes the request.
+    /// Dropping the event unanswered sends the default response of the group.
+    #[inline]
+    pub fn take_permission(&mut self) -> Option<PermissionRequest> {
+        self.request.take()
+    }
+
+    /// Event on `fd` outside of a group, as rebuilt in a [`crate::worker`] process.
+    pub(crate) fn with_fd(mask: EventMask, pid: i32, fd: Option<OwnedFd>) -> Event {
+        Event {
+            mask,
+            pid,
+            fd,
+            pidfd: None,
+            info: Box::default(),
+            request: None,
+        }
+    }
+
+    /// Take the request, keeping a duplicate of its descriptor in the event
+    /// so the event can be inspected while the request is answered elsewhere.
+    /// The event has no descriptor if it cannot be duplicated.
+    pub(crate) fn split_permission(&mut self) -> Option<PermissionRequest> {
+        let request = self.request.take()?;
+        self.fd = request.fd().try_clone_to_owned().ok();
+        Some(request)
+    }
+
+    /// Move the descriptor of a permission event into a request of `responder`.
+    pub(crate) fn attach(&mut self, responder: &Responder) {
+        if !self.mask.intersects(PERM_EVENTS) {
+            return;
+        }
+        if let Some(fd) = self.fd.take() {
+            self.request = Some(responder.request(fd, self.mask & PERM_EVENTS));
+        }
+    }
+}
+
+impl fmt::Debug for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Event")
+            .field("mask", &self.mask)
+            .field("fd", &self.fd)
+            .field("pid", &self.pid)
+            .field("info", &self.info().collect::<Vec<_>>())
+            .field("permission", &self.request)
+            .finish()
+    }
+}
+
+/// Iterator over the information records of an event,
+/// created by [`EventRef::info()`].
+pub struct InfoIter<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for InfoIter<'a> {
+    type Item = Result<EventInfo<'a>, FanotifyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let record = if self.buf.len() < FAN_EVENT_INFO_HEADER_LEN {
+            Err(FanotifyError::Parse(format!(
+                "Truncated info record: {} bytes left, header needs {}",
+                self.buf.len(),
+                FAN_EVENT_INFO_HEADER_LEN
+            )))
+        } else {
+            let len =
+                read_at::<u16>(self.buf, mem::offset_of!(fanotify_event_info_header, len)) as usize;
+            if len < FAN_EVENT_INFO_HEADER_LEN || len > self.buf.len() {
+                Err(FanotifyError::Parse(format!(
+                    "Invalid info record len {} with {} bytes left",
+                    len,
+                    self.buf.len()
+                )))
+            } else {
+                let (record, rest) = self.buf.split_at(len);
+                self.buf = rest;
+                EventInfo::parse(record)
+            }
+        };
+        if record.is_err() {
+            self.buf = &[];
+        }
+        Some(record)
+    }
+}
+
+/// Opaque handle identifying a filesystem object, see [`file_handle`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileHandle<'a> {
+    /// Filesystem specific type of the handle.
+    pub handle_type: i32,
+    /// Handle data, can be passed to open_by_handle_at(2)
+    /// together with [`FileHandle::handle_type`].
+    pub bytes: &'a [u8],
+}
+
+/// Object identification carried by the file handle records,
+/// see [`fanotify_event_info_fid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FidInfo<'a> {
+    /// Filesystem containing the object.
+    pub fsid: __kernel_fsid_t,
+    /// Handle of the object inside the filesystem.
+    pub handle: FileHandle<'a>,
+}
+
+/// Typed information record attached to an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventInfo<'a> {
+    /// [`FAN_EVENT_INFO_TYPE_FID`], the object correlated to the event.
+    Fid(FidInfo<'a>),
+    /// [`FAN_EVENT_INFO_TYPE_DFID`], the directory correlated to the event.
+    Dfid(FidInfo<'a>),
+    /// [`FAN_EVENT_INFO_TYPE_DFID_NAME`], a directory and an entry in it
+    /// (`.` for the directory itself).
+    DfidName { fid: FidInfo<'a>, name: &'a OsStr },
+    /// [`FAN_EVENT_INFO_TYPE_OLD_DFID_NAME`], where a [`FAN_RENAME`] moved from.
+    OldDfidName { fid: FidInfo<'a>, name: &'a OsStr },
+    /// [`FAN_EVENT_INFO_TYPE_NEW_DFID_NAME`], where a [`FAN_RENAME`] moved to.
+    NewDfidName { fid: FidInfo<'a>, name: &'a OsStr },
+    /// [`FAN_EVENT_INFO_TYPE_PIDFD`], pidfd of the process that caused
+    /// the event, [`FAN_NOPIDFD`] or [`FAN_EPIDFD`].
+    Pidfd(i32),
+    /// [`FAN_EVENT_INFO_TYPE_ERROR`], error reported by [`FAN_FS_ERROR`].
+    Error { error: i32, error_count: u32 },
+    /// Record type not known to this version of the library.
+    Unknown { info_type: u8, data: &'a [u8] },
+}
+
+impl<'a> EventInfo<'a> {
+    /// Decode a complete record, header included.
+    fn parse(record: &'a [u8]) -> Result<Self, FanotifyError> {
+        let info_type = read_at::<u8>(
+            record,
+            mem::offset_of!(fanotify_event_info_header, info_type),
+        );
+        let info = match info_type {
+            FAN_EVENT_INFO_TYPE_FID => EventInfo::Fid(Self::parse_fid(record)?.0),
+            FAN_EVENT_INFO_TYPE_DFID => EventInfo::Dfid(Self::parse_fid(record)?.0),
+            FAN_EVENT_INFO_TYPE_DFID_NAME => {
+                let (fid, name) = Self::parse_fid_name(record)?;
+                EventInfo::DfidName { fid, name }
+            }
+            FAN_EVENT_INFO_TYPE_OLD_DFID_NAME => {
+                let (fid, name) = Self::parse_fid_name(record)?;
+                EventInfo::OldDfidName { fid, name }
+            }
+            FAN_EVENT_INFO_TYPE_NEW_DFID_NAME => {
+                let (fid, name) = Self::parse_fid_name(record)?;
+                EventInfo::NewDfidName { fid, name }
+            }
+            FAN_EVENT_INFO_TYPE_PIDFD => {
+                Self::check_len(record, mem::size_of::<fanotify_event_info_pidfd>())?;
+                EventInfo::Pidfd(read_at(
+                    record,
+                    mem::offset_of!(fanotify_event_info_pidfd, pidfd),
+                ))
+            }
+            FAN_EVENT_INFO_TYPE_ERROR => {
+                Self::check_len(record, mem::size_of::<fanotify_event_info_error>())?;
+                EventInfo::Error {
+                    error: read_at(record, mem::offset_of!(fanotify_event_info_error, error)),
+                    error_count: read_at(
+                        record,
+                        mem::offset_of!(fanotify_event_info_error, error_count),
+                    ),
+                }
+            }
+            info_type => EventInfo::Unknown {
+                info_type,
+                data: &record[FAN_EVENT_INFO_HEADER_LEN..],
+            },
+        };
+        Ok(info)
+    }
+
+    #[inline]
+    fn check_len(record: &[u8], needed: usize) -> Result<(), FanotifyError> {
+        if record.len() < needed {
+            return Err(FanotifyError::Parse(format!(
+                "Truncated info record of type {}: {} bytes, needs {}",
+                record[0],
+                record.len(),
+                needed
+            )));
+        }
+        Ok(())
+    }
+
+    /// Decode fsid and file handle, returns them with the bytes following the handle.
+    fn parse_fid(record: &'a [u8]) -> Result<(FidInfo<'a>, &'a [u8]), FanotifyError> {
+        Self::check_len(record, FAN_EVENT_INFO_FID_HANDLE + FILE_HANDLE_LEN)?;
+        let fsid =
+            read_at::<__kernel_fsid_t>(record, mem::offset_of!(fanotify_event_info_fid, fsid));
+        let handle_bytes = read_at::<u32>(
+            record,
+            FAN_EVENT_INFO_FID_HANDLE + mem::offset_of!(file_handle, handle_bytes),
+        ) as usize;
+        let handle_type = read_at::<i32>(
+            record,
+            FAN_EVENT_INFO_FID_HANDLE + mem::offset_of!(file_handle, handle_type),
+        );
+        let start = FAN_EVENT_INFO_FID_HANDLE + FILE_HANDLE_LEN;
+        Self::check_len(record, start + handle_bytes)?;
+        let fid = FidInfo {
+            fsid,
+            handle: FileHandle {
+                handle_type,
+                bytes: &record[start..start + handle_bytes],
+            },
+        };
+        Ok((fid, &record[start + handle_bytes..]))
+    }
+
+    /// Decode a file handle followed by a null terminated name.
+    fn parse_fid_name(record: &'a [u8]) -> Result<(FidInfo<'a>, &'a OsStr), FanotifyError> {
+        let (fid, rest) = Self::parse_fid(record)?;
+        let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        Ok((fid, OsStr::from_bytes(&rest[..end])))
+    }
+}
@@ -0,0 +1,163 @@
+//! Keeping the listener out of its own events.
+//!
+//! A permission listener opening a file under its own mark (reading its
+//! configuration, hashing a binary, writing logs) raises a permission event
+//! only it could answer, and blocks on it forever. Groups therefore skip
+//! the events caused by their own process, any of its threads included,
+//! and answer the permission ones with [`FAN_ALLOW`]. This applies to all
+//! reads through a [`Fanotify`] group ([`Fanotify::read()`],
+//! [`Fanotify::reader()`] and everything built on them) and can be turned
+//! off with [`FanotifyBuilder::exclude_self()`]. [`read()`] and the other
+//! free functions of [`crate::api`] never skip events.
+//!
+//! Files the listener touches all the time, like its log and state
+//! directories, are better left out by the kernel itself with
+//! [`Fanotify::exclude_paths()`].
+//!
+//! # Example
+//! This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+//! ```rust
+//! # use naughtyfy::flags::*;
+//! # use naughtyfy::group::*;
+//! # use std::time::Duration;
+//! let dir = std::env::temp_dir().canonicalize().unwrap();
+//! let dir = dir.join(format!("naughtyfy-exclude-{}", std::process::id()));
+//! std::fs::create_dir(&dir).unwrap();
+//!
+//! match Fanotify::builder().class(Class::Content).build() {
+//!     Ok(group) => {
+//!         group
+//!             .mark(FAN_MARK_ADD, FAN_OPEN_PERM | FAN_EVENT_ON_CHILD, AT_FDCWD, dir.as_path())
+//!             .unwrap();
+//!         let config = dir.join("config");
+//!         let writer = std::thread::spawn(move || std::fs::write(config, b""));
+//!         // The event of our own thread is allowed and skipped.
+//!         if let Some(event) = group.events().timeout(Duration::from_millis(200)).next() {
+//!             panic!("Unexpected event {event:?}");
+//!         }
+//!         writer.join().unwrap().unwrap();
+//!     }
+//!     Err(e) => {
+//!         // This can fail for multiple reason, most common being privileges.
+//!         eprintln!("Cannot get fd due to {e}");
+//!     }
+//! }
+//! std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::{
+    api,
+    errors::FanotifyError,
+    event::EventBuffer,
+    flags::*,
+    group::{Fanotify, FID_EVENTS, PERM_EVENTS},
+    types::*,
+};
+use std::{
+    os::fd::{AsRawFd, OwnedFd},
+    path::Path,
+    sync::{Arc, Weak},
+};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::{api::*, group::*};
+
+/// Check if `pid`, as reported in an event, is this process or one of its
+/// threads (groups initialized with [`FAN_REPORT_TID`] report thread ids).
+pub fn is_self(pid: i32) -> bool {
+    pid == std::process::id() as i32
+        || (pid > 0 && Path::new(&format!("/proc/self/task/{pid}")).exists())
+}
+
+/// Skips the events of this process in the buffers of a group.
+#[derive(Debug, Clone)]
+pub(crate) struct SelfExclusion {
+    group: Weak<OwnedFd>,
+    pid: i32,
+    /// Thread ids need a lookup, process ids are compared.
+    report_tid: bool,
+}
+
+impl SelfExclusion {
+    pub(crate) fn new(group: &Arc<OwnedFd>, flags: InitFlags) -> Self {
+        SelfExclusion {
+            group: Arc::downgrade(group),
+            pid: std::process::id() as i32,
+            report_tid: flags.contains(FAN_REPORT_TID),
+        }
+    }
+
+    fn matches(&self, pid: i32) -> bool {
+        match self.report_tid {
+            true => is_self(pid),
+            false => pid == self.pid,
+        }
+    }
+
+    /// Remove the events of this process from `buffer`,
+    /// allowing the permission ones.
+    pub(crate) fn filter(&self, buffer: &mut EventBuffer) {
+        buffer.retain(|event| {
+            if !self.matches(event.pid()) {
+                return true;
+            }
+            if event.mask().intersects(PERM_EVENTS) {
+                if let (Some(fd), Some(group)) = (event.fd(), self.group.upgrade()) {
+                    let response = fanotify_response::new(&fd.as_raw_fd(), FAN_ALLOW);
+                    if let Err(e) = api::write(&group, &response) {
+                        eprintln!("{e}");
+                    }
+                }
+            }
+            false
+        });
+    }
+}
+
+impl Fanotify {
+    /// Ignore every event the group can report on `paths`, Eg: the log and
+    /// state directories of the listener. The events on the entries of a
+    /// directory are ignored too, not those deeper in the tree.
+    ///
+    /// Places [`FAN_MARK_IGNORE_SURV`] marks, which need Linux 6.0.
+    /// Stops at the first path that cannot be marked, see [`Fanotify::mark()`].
+    ///
+    /// # Example
+    /// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+    /// ```rust
+    /// # use naughtyfy::group::*;
+    /// match Fanotify::builder().class(Class::Content).build() {
+    ///     Ok(group) => {
+    ///         let logs = std::env::temp_dir();
+    ///         group.exclude_paths([logs.as_path()]).unwrap();
+    ///     }
+    ///     Err(e) => {
+    ///         // This can fail for multiple reason, most common being privileges.
+    ///         eprintln!("Cannot get fd due to {e}");
+    ///     }
+    /// }
+    /// ```
+    pub fn exclude_paths<P, I>(&self, paths: I) -> Result<(), FanotifyError>
+    where
+        P: AsRef<Path>,
+        I: IntoIterator<Item = P>,
+    {
+        let mut mask = FAN_ACCESS | FAN_MODIFY | FAN_CLOSE | FAN_OPEN | FAN_OPEN_EXEC;
+        if self.class().allows_permission() && !self.reports_fid() {
+            mask |= PERM_EVENTS;
+        }
+        if self.reports_fid() {
+            mask |= FID_EVENTS;
+        }
+        for path in paths {
+            let path = path.as_ref();
+            let mask = match path.is_dir() {
+                true => mask | FAN_ONDIR | FAN_EVENT_ON_CHILD,
+                false => mask,
+            };
+            self.mark(FAN_MARK_ADD | FAN_MARK_IGNORE_SURV, mask, AT_FDCWD, path)?;
+        }
+        Ok(())
+    }
+}
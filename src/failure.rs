@@ -0,0 +1,222 @@
+//! What happens to unanswered permission events when the listener fails.
+//!
+//! Once a group is closed the kernel allows every event still waiting for
+//! a response, and a [`PermissionRequest`] dropped by a panicking thread
+//! gets the default response of the group. A [`FailurePolicy`] makes the
+//! outcome explicit. Groups keep track of the event file descriptors of
+//! their outstanding requests, so the policy answers exactly those:
+//!
+//! * [`FailurePolicy::Open`] allows the requests dropped while their
+//!   thread panics, and the outstanding ones when the group is dropped.
+//! * [`FailurePolicy::Closed`] denies the requests dropped while their
+//!   thread panics. Dropping the group denies the outstanding requests and
+//!   the events still queued, instead of letting the kernel allow them.
+//!
+//! Panics in other threads, caught or not, leave the group alone. Without
+//! a policy (the default) the kernel allows the leftovers once the group
+//! is closed.
+//!
+//! # Example
+//! This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+//! ```rust
+//! # use naughtyfy::failure::*;
+//! # use naughtyfy::flags::*;
+//! # use naughtyfy::group::*;
+//! let dir = std::env::temp_dir().canonicalize().unwrap();
+//! let dir = dir.join(format!("naughtyfy-failure-{}", std::process::id()));
+//! std::fs::create_dir(&dir).unwrap();
+//! std::fs::write(dir.join("file"), b"").unwrap();
+//!
+//! // The accesses come from a thread of this process.
+//! let builder = Fanotify::builder().exclude_self(false);
+//! match builder.class(Class::Content).failure_policy(FailurePolicy::Closed).build() {
+//!     Ok(group) => {
+//!         group
+//!             .mark(FAN_MARK_ADD, FAN_OPEN_PERM | FAN_EVENT_ON_CHILD, AT_FDCWD, dir.as_path())
+//!             .unwrap();
+//!         let file = dir.join("file");
+//!         let reader = std::thread::spawn(move || std::fs::read(file));
+//!
+//!         let request = group.events().next().unwrap().unwrap().take_permission().unwrap();
+//!         let decider = std::thread::spawn(move || {
+//!             let _request = request;
+//!             panic!("Decision failed");
+//!         });
+//!         assert!(decider.join().is_err());
+//!         let err = reader.join().unwrap().unwrap_err();
+//!         assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+//!
+//!         // Unrelated to the requests, the next access is answered as usual.
+//!         assert!(std::panic::catch_unwind(|| panic!("Unrelated")).is_err());
+//!         let file = dir.join("file");
+//!         let reader = std::thread::spawn(move || std::fs::read(file));
+//!         let mut event = group.events().next().unwrap().unwrap();
+//!         event.take_permission().unwrap().allow().unwrap();
+//!         assert!(reader.join().unwrap().is_ok());
+//!     }
+//!     Err(e) => {
+//!         // This can fail for multiple reason, most common being privileges.
+//!         eprintln!("Cannot get fd due to {e}");
+//!     }
+//! }
+//! std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::{
+    api, cancel::wait_readable, event::EventBuffer, flags::*, group::PERM_EVENTS, types::*,
+};
+use std::{
+    collections::HashSet,
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    time::Duration,
+};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::{api::*, group::*, permission::PermissionRequest};
+
+/// How a group answers its outstanding permission requests when the
+/// listener fails, see the [module documentation](crate::failure).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FailurePolicy {
+    /// Allow on panic and drop.
+    Open,
+    /// Deny on panic and drop, and deny the events still queued on drop.
+    Closed,
+}
+
+impl FailurePolicy {
+    /// Response written for the outstanding requests.
+    pub fn response(self) -> Response {
+        match self {
+            FailurePolicy::Open => FAN_ALLOW,
+            FailurePolicy::Closed => FAN_DENY,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    policy: Option<FailurePolicy>,
+    /// Event descriptors of the requests not answered yet.
+    outstanding: HashSet<RawFd>,
+}
+
+/// Outstanding requests of a group, shared with its readers.
+#[derive(Debug)]
+pub(crate) struct Tracker {
+    group: Weak<OwnedFd>,
+    state: Mutex<State>,
+}
+
+impl Tracker {
+    pub(crate) fn new(group: &Arc<OwnedFd>) -> Arc<Self> {
+        Arc::new(Tracker {
+            group: Arc::downgrade(group),
+            state: Mutex::default(),
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn policy(&self) -> Option<FailurePolicy> {
+        self.state().policy
+    }
+
+    pub(crate) fn set_policy(&self, policy: Option<FailurePolicy>) {
+        self.state().policy = policy;
+    }
+
+    /// Start tracking the event descriptor of a new request.
+    pub(crate) fn insert(&self, fd: RawFd) {
+        self.state().outstanding.insert(fd);
+    }
+
+    /// Stop tracking `fd` before answering it. `false` if the policy
+    /// already answered it.
+    pub(crate) fn remove(&self, fd: RawFd) -> bool {
+        self.state().outstanding.remove(&fd)
+    }
+
+    /// Check if `fd` still waits for an answer.
+    pub(crate) fn contains(&self, fd: RawFd) -> bool {
+        self.state().outstanding.contains(&fd)
+    }
+
+    /// Answer the outstanding requests with the response of the policy.
+    fn answer(&self) {
+        let mut errors = Vec::new();
+        {
+            // Held while writing, so a request that finds its descriptor
+            // answered does not close it before the response is sent.
+            let mut state = self.state();
+            let Some(policy) = state.policy else {
+                return;
+            };
+            let Some(group) = self.group.upgrade() else {
+                return;
+            };
+            for fd in state.outstanding.drain() {
+                let response = fanotify_response::new(&fd, policy.response());
+                if let Err(e) = api::write(&group, &response) {
+                    errors.push(e);
+                }
+            }
+        }
+        for e in errors {
+            eprintln!("{e}");
+        }
+    }
+
+    /// Called when the group is dropped, before it is closed.
+    pub(crate) fn shutdown(&self) {
+        self.answer();
+        if self.policy() != Some(FailurePolicy::Closed) {
+            return;
+        }
+        let Some(group) = self.group.upgrade() else {
+            return;
+        };
+        // Deny what is still queued, the kernel would allow it on close.
+        let mut buffer = EventBuffer::new(api::FAN_EVENT_BUFFER_LEN);
+        while let Ok(true) = wait_readable(&*group, Some(Duration::ZERO), None) {
+            if api::read_into(&*group, &mut buffer).is_err() || buffer.is_empty() {
+                break;
+            }
+            deny_all(&self.group, &mut buffer);
+        }
+    }
+}
+
+/// Deny the permission events of `buffer` and remove them.
+fn deny_all(group: &Weak<OwnedFd>, buffer: &mut EventBuffer) {
+    let Some(group) = group.upgrade() else {
+        return;
+    };
+    buffer.retain(|event| {
+        if !event.mask().intersects(PERM_EVENTS) {
+            return true;
+        }
+        if let Some(fd) = event.fd() {
+            let response = fanotify_response::new(&fd.as_raw_fd(), FAN_DENY);
+            if let Err(e) = api::write(&group, &response) {
+                eprintln!("{e}");
+            }
+        }
+        false
+    });
+}
+
+/// Applies the policy of a group when it is dropped. Declared before the
+/// descriptor in [`Fanotify`], so it runs while the group is still open.
+#[derive(Debug)]
+pub(crate) struct Guard(pub(crate) Arc<Tracker>);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.0.shutdown();
+    }
+}
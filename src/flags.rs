@@ -4,71 +4,427 @@
 extern crate libc;
 // For documentaton linking
 #[allow(unused_imports)]
-use crate::api::read_with_fid;
+use crate::api::{init, mark, read_with_fid};
+#[allow(unused_imports)]
+use crate::event::EventRef;
+#[allow(unused_imports)]
+use crate::types::{fanotify_event_metadata, fanotify_response, fanotify_response_info_audit_rule};
 #[allow(unused_imports)]
 use libc::{EAGAIN, EEXIST, EINVAL, ENOTDIR};
+
+use crate::errors::FanotifyError;
+use std::{fmt, ops, str::FromStr};
+
+/// Defines a typed set of flags over the raw integer passed to the kernel.
+///
+/// `flags` are the single bit constants used to print a set with the
+/// kernel names (without `prefix`), `aliases` are only accepted when parsing.
+macro_rules! fan_flags {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident($bits:ty);
+        prefix = $prefix:literal;
+        flags = [$($flag:ident),* $(,)?];
+        aliases = [$($alias:ident),* $(,)?];
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+        #[repr(transparent)]
+        pub struct $name($bits);
+
+        impl $name {
+            const NAMES: &'static [(&'static str, $name)] = &[$((stringify!($flag), $flag)),*];
+            const ALIASES: &'static [(&'static str, $name)] = &[$((stringify!($alias), $alias)),*];
+
+            /// Set without any flag.
+            #[inline]
+            pub const fn empty() -> Self {
+                $name(0)
+            }
+
+            /// Set holding exactly `bits`, including bits without a name.
+            #[inline]
+            pub const fn from_bits_retain(bits: $bits) -> Self {
+                $name(bits)
+            }
+
+            /// Raw value as passed to the kernel.
+            #[inline]
+            pub const fn bits(self) -> $bits {
+                self.0
+            }
+
+            /// Check if no flag is set.
+            #[inline]
+            pub const fn is_empty(self) -> bool {
+                self.0 == 0
+            }
+
+            /// Check if every flag of `other` is set.
+            #[inline]
+            pub const fn contains(self, other: Self) -> bool {
+                self.0 & other.0 == other.0
+            }
+
+            /// Check if any flag of `other` is set.
+            #[inline]
+            pub const fn intersects(self, other: Self) -> bool {
+                self.0 & other.0 != 0
+            }
+
+            /// Flags set in `self` or `other`.
+            #[inline]
+            pub const fn union(self, other: Self) -> Self {
+                $name(self.0 | other.0)
+            }
+
+            /// Flags set in both `self` and `other`.
+            #[inline]
+            pub const fn intersection(self, other: Self) -> Self {
+                $name(self.0 & other.0)
+            }
+
+            /// Flags set in `self` but not in `other`.
+            #[inline]
+            pub const fn difference(self, other: Self) -> Self {
+                $name(self.0 & !other.0)
+            }
+
+            /// Set the flags of `other`.
+            #[inline]
+            pub fn insert(&mut self, other: Self) {
+                self.0 |= other.0;
+            }
+
+            /// Clear the flags of `other`.
+            #[inline]
+            pub fn remove(&mut self, other: Self) {
+                self.0 &= !other.0;
+            }
+
+            /// Iterate over the set bits, lowest first, one flag at a time.
+            pub fn iter(self) -> impl Iterator<Item = Self> {
+                let mut rest = self.0;
+                std::iter::from_fn(move || {
+                    if rest == 0 {
+                        return None;
+                    }
+                    let bit = rest & rest.wrapping_neg();
+                    rest &= !bit;
+                    Some($name(bit))
+                })
+            }
+
+            /// Kernel name of a single flag, without the `
+            #[doc = $prefix]
+            /// ` prefix.
+            pub fn name(self) -> Option<&'static str> {
+                Self::NAMES
+                    .iter()
+                    .find(|(_, flag)| flag.0 != 0 && *flag == self)
+                    .map(|(name, _)| name.strip_prefix($prefix).unwrap_or(name))
+            }
+        }
+
+        impl From<$name> for $bits {
+            #[inline]
+            fn from(flags: $name) -> $bits {
+                flags.0
+            }
+        }
+
+        impl ops::BitOr for $name {
+            type Output = Self;
+            #[inline]
+            fn bitor(self, rhs: Self) -> Self {
+                self.union(rhs)
+            }
+        }
+
+        impl ops::BitOrAssign for $name {
+            #[inline]
+            fn bitor_assign(&mut self, rhs: Self) {
+                self.insert(rhs);
+            }
+        }
+
+        impl ops::BitAnd for $name {
+            type Output = Self;
+            #[inline]
+            fn bitand(self, rhs: Self) -> Self {
+                self.intersection(rhs)
+            }
+        }
+
+        impl ops::BitAndAssign for $name {
+            #[inline]
+            fn bitand_assign(&mut self, rhs: Self) {
+                self.0 &= rhs.0;
+            }
+        }
+
+        impl ops::BitXor for $name {
+            type Output = Self;
+            #[inline]
+            fn bitxor(self, rhs: Self) -> Self {
+                $name(self.0 ^ rhs.0)
+            }
+        }
+
+        impl ops::Sub for $name {
+            type Output = Self;
+            #[inline]
+            fn sub(self, rhs: Self) -> Self {
+                self.difference(rhs)
+            }
+        }
+
+        impl ops::SubAssign for $name {
+            #[inline]
+            fn sub_assign(&mut self, rhs: Self) {
+                self.remove(rhs);
+            }
+        }
+
+        impl ops::Not for $name {
+            type Output = Self;
+            #[inline]
+            fn not(self) -> Self {
+                $name(!self.0)
+            }
+        }
+
+        /// Kernel names joined by `|` (Eg: `OPEN|CLOSE_WRITE`),
+        /// bits without a name are printed in hex and the empty set as `0`.
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                if self.is_empty() {
+                    return f.write_str("0");
+                }
+                for (i, flag) in self.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("|")?;
+                    }
+                    match flag.name() {
+                        Some(name) => f.write_str(name)?,
+                        None => write!(f, "{:#x}", flag.0)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        /// Parses names joined by `|`, with or without the `
+        #[doc = $prefix]
+        /// ` prefix. Numbers are accepted in decimal or `0x` hex.
+        impl FromStr for $name {
+            type Err = FanotifyError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let mut flags = $name::empty();
+                for token in s.split('|').map(str::trim).filter(|t| !t.is_empty()) {
+                    let named = Self::NAMES
+                        .iter()
+                        .chain(Self::ALIASES)
+                        .find(|(name, _)| *name == token || name.strip_prefix($prefix) == Some(token));
+                    let flag = match named {
+                        Some((_, flag)) => *flag,
+                        None => match token.strip_prefix("0x") {
+                            Some(hex) => <$bits>::from_str_radix(hex, 16),
+                            None => token.parse::<$bits>(),
+                        }
+                        .map($name)
+                        .map_err(|_| {
+                            FanotifyError::Parse(format!(
+                                "Unknown {} flag {:?}",
+                                stringify!($name),
+                                token
+                            ))
+                        })?,
+                    };
+                    flags.insert(flag);
+                }
+                Ok(flags)
+            }
+        }
+    };
+}
+
+fan_flags! {
+    /// Set of events, the `mask` of [`mark()`] and of
+    /// [`fanotify_event_metadata`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use naughtyfy::flags::*;
+    /// let mask = FAN_OPEN | FAN_CLOSE_WRITE;
+    /// assert!(mask.contains(FAN_OPEN));
+    /// assert_eq!(mask.to_string(), "CLOSE_WRITE|OPEN");
+    /// assert_eq!("OPEN|FAN_CLOSE_WRITE".parse::<EventMask>().unwrap(), mask);
+    /// assert_eq!(mask.iter().collect::<Vec<_>>(), [FAN_CLOSE_WRITE, FAN_OPEN]);
+    /// ```
+    pub struct EventMask(u64);
+    prefix = "FAN_";
+    flags = [
+        FAN_ACCESS,
+        FAN_MODIFY,
+        FAN_ATTRIB,
+        FAN_CLOSE_WRITE,
+        FAN_CLOSE_NOWRITE,
+        FAN_OPEN,
+        FAN_MOVED_FROM,
+        FAN_MOVED_TO,
+        FAN_CREATE,
+        FAN_DELETE,
+        FAN_DELETE_SELF,
+        FAN_MOVE_SELF,
+        FAN_OPEN_EXEC,
+        FAN_Q_OVERFLOW,
+        FAN_FS_ERROR,
+        FAN_OPEN_PERM,
+        FAN_ACCESS_PERM,
+        FAN_OPEN_EXEC_PERM,
+        FAN_EVENT_ON_CHILD,
+        FAN_RENAME,
+        FAN_ONDIR,
+    ];
+    aliases = [FAN_CLOSE, FAN_MOVE];
+}
+
+fan_flags! {
+    /// Flags of a fanotify group, the `flags` of [`init()`].
+    pub struct InitFlags(u32);
+    prefix = "FAN_";
+    flags = [
+        FAN_CLOEXEC,
+        FAN_NONBLOCK,
+        FAN_CLASS_CONTENT,
+        FAN_CLASS_PRE_CONTENT,
+        FAN_UNLIMITED_QUEUE,
+        FAN_UNLIMITED_MARKS,
+        FAN_ENABLE_AUDIT,
+        FAN_REPORT_PIDFD,
+        FAN_REPORT_TID,
+        FAN_REPORT_FID,
+        FAN_REPORT_DIR_FID,
+        FAN_REPORT_NAME,
+        FAN_REPORT_TARGET_FID,
+    ];
+    aliases = [FAN_CLASS_NOTIF, FAN_REPORT_DFID_NAME, FAN_REPORT_DFID_NAME_TARGET];
+}
+
+fan_flags! {
+    /// File status flags of event file descriptors, the `event_f_flags` of [`init()`].
+    pub struct EventFFlags(u32);
+    prefix = "O_";
+    flags = [
+        O_WRONLY,
+        O_RDWR,
+        O_APPEND,
+        O_NONBLOCK,
+        O_DSYNC,
+        O_LARGEFILE,
+        O_NOATIME,
+        O_CLOEXEC,
+    ];
+    aliases = [O_RDONLY];
+}
+
+fan_flags! {
+    /// Flags describing the modification of a mark, the `flags` of [`mark()`].
+    pub struct MarkFlags(u32);
+    prefix = "FAN_MARK_";
+    flags = [
+        FAN_MARK_ADD,
+        FAN_MARK_REMOVE,
+        FAN_MARK_DONT_FOLLOW,
+        FAN_MARK_ONLYDIR,
+        FAN_MARK_MOUNT,
+        FAN_MARK_IGNORED_MASK,
+        FAN_MARK_IGNORED_SURV_MODIFY,
+        FAN_MARK_FLUSH,
+        FAN_MARK_FILESYSTEM,
+        FAN_MARK_EVICTABLE,
+        FAN_MARK_IGNORE,
+    ];
+    aliases = [FAN_MARK_IGNORE_SURV];
+}
+
+fan_flags! {
+    /// Answer to a permission event, the `response` of [`fanotify_response`].
+    pub struct Response(u32);
+    prefix = "FAN_";
+    flags = [FAN_ALLOW, FAN_DENY, FAN_AUDIT, FAN_INFO];
+    aliases = [];
+}
 /* the following events that user-space can register for */
 
 /// Create an event when a file or directory (but see [BUGS](https://man7.org/linux/man-pages/man2/fanotify_mark.2.html#BUGS)) is
 /// accessed (read).
-pub const FAN_ACCESS: u64 = 0x00000001; /* File was accessed */
+pub const FAN_ACCESS: EventMask = EventMask(0x00000001); /* File was accessed */
 
 /// Create an event when a file is modified (write).
-pub const FAN_MODIFY: u64 = 0x00000002; /* File was modified */
+pub const FAN_MODIFY: EventMask = EventMask(0x00000002); /* File was modified */
 
 /// Create an event when the metadata for a file or directory
 /// has changed.  An fanotify group that identifies filesystem
 /// objects by file handles is required.
-pub const FAN_ATTRIB: u64 = 0x00000004; /* Metadata changed */
+pub const FAN_ATTRIB: EventMask = EventMask(0x00000004); /* Metadata changed */
 
 /// Create an event when a writable file is closed.
-pub const FAN_CLOSE_WRITE: u64 = 0x00000008; /* Writtable file closed */
+pub const FAN_CLOSE_WRITE: EventMask = EventMask(0x00000008); /* Writtable file closed */
 
 /// Create an event when a read-only file or directory is
 /// closed.
-pub const FAN_CLOSE_NOWRITE: u64 = 0x00000010; /* Unwrittable file closed */
+pub const FAN_CLOSE_NOWRITE: EventMask = EventMask(0x00000010); /* Unwrittable file closed */
 
 /// Create an event when a file or directory is opened.
-pub const FAN_OPEN: u64 = 0x00000020; /* File was opened */
+pub const FAN_OPEN: EventMask = EventMask(0x00000020); /* File was opened */
 
 /// Create an event when a file or directory has been moved
 /// from a marked parent directory.  An fanotify group that
 /// identifies filesystem objects by file handles is required.
-pub const FAN_MOVED_FROM: u64 = 0x00000040; /* File was moved from X */
+pub const FAN_MOVED_FROM: EventMask = EventMask(0x00000040); /* File was moved from X */
 
 /// A file or directory has been moved to a watched parent
 /// directory.
-pub const FAN_MOVED_TO: u64 = 0x00000080; /* File was moved to Y */
+pub const FAN_MOVED_TO: EventMask = EventMask(0x00000080); /* File was moved to Y */
 
 /// A child file or directory was created in a watched parent.
-pub const FAN_CREATE: u64 = 0x00000100; /* Subfile was created */
+pub const FAN_CREATE: EventMask = EventMask(0x00000100); /* Subfile was created */
 
 /// A child file or directory was deleted in a watched parent.
-pub const FAN_DELETE: u64 = 0x00000200; /* Subfile was deleted */
+pub const FAN_DELETE: EventMask = EventMask(0x00000200); /* Subfile was deleted */
 
 /// A watched file or directory was deleted.
-pub const FAN_DELETE_SELF: u64 = 0x00000400; /* Self was deleted */
+pub const FAN_DELETE_SELF: EventMask = EventMask(0x00000400); /* Self was deleted */
 
 /// A watched file or directory was moved.
-pub const FAN_MOVE_SELF: u64 = 0x00000800; /* Self was moved */
+pub const FAN_MOVE_SELF: EventMask = EventMask(0x00000800); /* Self was moved */
 
 /// A file was opened with the intent to be executed.  See
 /// NOTES in [fanotify_mark(2)](https://man7.org/linux/man-pages/man2/fanotify_mark.2.html#NOTES) for additional details.
-pub const FAN_OPEN_EXEC: u64 = 0x00001000; /* File was opened for exec */
+pub const FAN_OPEN_EXEC: EventMask = EventMask(0x00001000); /* File was opened for exec */
 
 /// The event queue exceeded the limit of 16384 entries.  This
 /// limit can be overridden by specifying the
 /// [`FAN_UNLIMITED_QUEUE`] flag when calling `fanotify_init()`.
-pub const FAN_Q_OVERFLOW: u64 = 0x00004000; /* Event queued overflowed */
+pub const FAN_Q_OVERFLOW: EventMask = EventMask(0x00004000); /* Event queued overflowed */
 
 /// Represents filesystem error
-pub const FAN_FS_ERROR: u32 = 0x00008000; /* Filesystem error */
+pub const FAN_FS_ERROR: EventMask = EventMask(0x00008000); /* Filesystem error */
 
 /// Create an event when a permission to open a file or
 /// directory is requested.  An fanotify file descriptor
 /// created with [`FAN_CLASS_PRE_CONTENT`] or [`FAN_CLASS_CONTENT`] is
 /// required.
-pub const FAN_OPEN_PERM: u64 = 0x00010000; /* File open in perm check */
+pub const FAN_OPEN_PERM: EventMask = EventMask(0x00010000); /* File open in perm check */
 
 /// An application wants to read a file or directory, for
 /// example using [read(2)](https://man7.org/linux/man-pages/man2/read.2.html)
@@ -76,7 +432,7 @@ pub const FAN_OPEN_PERM: u64 = 0x00010000; /* File open in perm check */
 /// write a response (as described below) that determines
 /// whether the permission to access the filesystem object
 /// shall be granted.
-pub const FAN_ACCESS_PERM: u64 = 0x00020000; /* File accessed in perm check */
+pub const FAN_ACCESS_PERM: EventMask = EventMask(0x00020000); /* File accessed in perm check */
 
 /// An application wants to open a file for execution.  The
 /// reader must write a response that determines whether the
@@ -84,7 +440,7 @@ pub const FAN_ACCESS_PERM: u64 = 0x00020000; /* File accessed in perm check */
 /// shall be granted.  See NOTES in
 /// [fanotify_mark(2)](https://man7.org/linux/man-pages/man2/fanotify_mark.2.html#NOTES) for
 /// additional details.
-pub const FAN_OPEN_EXEC_PERM: u64 = 0x00040000; /* File open/exec in perm check */
+pub const FAN_OPEN_EXEC_PERM: EventMask = EventMask(0x00040000); /* File open/exec in perm check */
 
 /// Events for the immediate children of marked directories
 /// shall be created.  The flag has no effect when marking
@@ -98,10 +454,10 @@ pub const FAN_OPEN_EXEC_PERM: u64 = 0x00040000; /* File open/exec in perm check
 /// and [`FAN_MOVE_SELF`] are not generated for children of marked
 /// directories.  To monitor complete directory trees it is
 /// necessary to mark the relevant mount or filesystem.
-pub const FAN_EVENT_ON_CHILD: u64 = 0x08000000; /* Interested in child events */
+pub const FAN_EVENT_ON_CHILD: EventMask = EventMask(0x08000000); /* Interested in child events */
 
 /// Create an event when a file is renamed.
-pub const FAN_RENAME: u64 = 0x10000000; /* File was renamed */
+pub const FAN_RENAME: EventMask = EventMask(0x10000000); /* File was renamed */
 
 /// Create events for directories—for example, when
 /// [opendir(3)](https://man7.org/linux/man-pages/man3/opendir.3.html),
@@ -115,28 +471,28 @@ pub const FAN_RENAME: u64 = 0x10000000; /* File was renamed */
 /// create events when subdirectory entries are modified
 /// (i.e., [mkdir(2)](https://man7.org/linux/man-pages/man2/mkdir.2.html)/
 /// [rmdir(2)](https://man7.org/linux/man-pages/man2/rmdir.2.html)).
-pub const FAN_ONDIR: u64 = 0x40000000; /* Event occurred against dir */
+pub const FAN_ONDIR: EventMask = EventMask(0x40000000); /* Event occurred against dir */
 
 /* helper events */
 /// Convenience macro - A file is closed ([`FAN_CLOSE_WRITE`]|[`FAN_CLOSE_NOWRITE`]).
-pub const FAN_CLOSE: u64 = FAN_CLOSE_WRITE | FAN_CLOSE_NOWRITE; /* close */
+pub const FAN_CLOSE: EventMask = FAN_CLOSE_WRITE.union(FAN_CLOSE_NOWRITE); /* close */
 
 /// Convenience macro - A file or directory has been moved
 /// ([`FAN_MOVED_FROM`]|[`FAN_MOVED_TO`]).
-pub const FAN_MOVE: u64 = FAN_MOVED_FROM | FAN_MOVED_TO; /* moves */
+pub const FAN_MOVE: EventMask = FAN_MOVED_FROM.union(FAN_MOVED_TO); /* moves */
 
 /* flags used for fanotify_init() */
 /// Set the close-on-exec flag (`FD_CLOEXEC`) on the new file
 /// descriptor.  See the description of the `O_CLOEXEC` flag in
 /// [open(2)](https://man7.org/linux/man-pages/man2/open.2.html).
-pub const FAN_CLOEXEC: u32 = 0x00000001;
+pub const FAN_CLOEXEC: InitFlags = InitFlags(0x00000001);
 
 /// Enable the nonblocking flag (`O_NONBLOCK`) for the file
 /// descriptor.  Reading from the file descriptor will not
 /// block.  Instead, if no data is available,
 /// [read(2)](https://man7.org/linux/man-pages/man2/read.2.html)
 /// fails with the error [`EAGAIN`].
-pub const FAN_NONBLOCK: u32 = 0x00000002;
+pub const FAN_NONBLOCK: InitFlags = InitFlags(0x00000002);
 
 /* These are NOT bitwise flags.  Both bits are used together.  */
 
@@ -144,7 +500,7 @@ pub const FAN_NONBLOCK: u32 = 0x00000002;
 /// specified.  This value only allows the receipt of events
 /// notifying that a file has been accessed.  Permission
 /// decisions before the file is accessed are not possible.
-pub const FAN_CLASS_NOTIF: u32 = 0x00000000;
+pub const FAN_CLASS_NOTIF: InitFlags = InitFlags(0x00000000);
 
 /// This value allows the receipt of events notifying that a
 /// file has been accessed and events for permission decisions
@@ -152,7 +508,7 @@ pub const FAN_CLASS_NOTIF: u32 = 0x00000000;
 /// listeners that need to access files when they already
 /// contain their final content.  This notification class
 /// might be used by malware detection programs, for example.
-pub const FAN_CLASS_CONTENT: u32 = 0x00000004;
+pub const FAN_CLASS_CONTENT: InitFlags = InitFlags(0x00000004);
 
 /// This value allows the receipt of events notifying that a
 /// file has been accessed and events for permission decisions
@@ -160,143 +516,224 @@ pub const FAN_CLASS_CONTENT: u32 = 0x00000004;
 /// listeners that need to access files before they contain
 /// their final data.  This notification class might be used
 /// by hierarchical storage managers, for example.
-pub const FAN_CLASS_PRE_CONTENT: u32 = 0x00000008;
+pub const FAN_CLASS_PRE_CONTENT: InitFlags = InitFlags(0x00000008);
 
 /* Deprecated - do not use this in programs and do not add new flags here! */
 #[deprecated(note = "do not use this in programs!")]
 /// Deprecated, Do not use.
-pub const FAN_ALL_CLASS_BITS: u32 = FAN_CLASS_NOTIF | FAN_CLASS_CONTENT | FAN_CLASS_PRE_CONTENT;
+pub const FAN_ALL_CLASS_BITS: InitFlags = FAN_CLASS_NOTIF
+    .union(FAN_CLASS_CONTENT)
+    .union(FAN_CLASS_PRE_CONTENT);
 
 /// Remove the limit of 16384 events for the event queue.  Use
 /// of this flag requires the `CAP_SYS_ADMIN` capability.
-pub const FAN_UNLIMITED_QUEUE: u32 = 0x00000010;
+pub const FAN_UNLIMITED_QUEUE: InitFlags = InitFlags(0x00000010);
 
 /// Remove the limit of 8192 marks.  Use of this flag requires
 /// the `CAP_SYS_ADMIN` capability.
-pub const FAN_UNLIMITED_MARKS: u32 = 0x00000020;
+pub const FAN_UNLIMITED_MARKS: InitFlags = InitFlags(0x00000020);
 
 /// Enable generation of audit log records about access
 /// mediation performed by permission events.  The permission
 /// event response has to be marked with the [`FAN_AUDIT`] flag
 /// for an audit log record to be generated.
-pub const FAN_ENABLE_AUDIT: u32 = 0x00000040;
+pub const FAN_ENABLE_AUDIT: InitFlags = InitFlags(0x00000040);
 
 /// Allow the file operation.
-pub const FAN_ALLOW: u32 = 0x01;
+pub const FAN_ALLOW: Response = Response(0x01);
 
 /// Deny the file operation.
-pub const FAN_DENY: u32 = 0x02;
+pub const FAN_DENY: Response = Response(0x02);
 
 /// Bit mask to create audit record for result
-pub const FAN_AUDIT: u32 = 0x10;
+pub const FAN_AUDIT: Response = Response(0x10);
+
+/// Bit mask indicating an information record follows the response,
+/// Eg: [`fanotify_response_info_audit_rule`]. Since Linux 6.3.
+pub const FAN_INFO: Response = Response(0x20);
+
+/// Number of high bits of a [`Response`] holding the errno of [`FAN_DENY_ERRNO()`].
+pub const FAN_ERRNO_BITS: u32 = 8;
+/// Position of the errno in a [`Response`], above the verdict and flags.
+pub const FAN_ERRNO_SHIFT: u32 = 32 - FAN_ERRNO_BITS;
+/// Mask of the errno once shifted down by [`FAN_ERRNO_SHIFT`].
+pub const FAN_ERRNO_MASK: u32 = (1 << FAN_ERRNO_BITS) - 1;
+
+/// Deny the file operation, failing it with `errno` instead of `EPERM`.
+///
+/// Only accepted from [`FAN_CLASS_PRE_CONTENT`] groups, for one of
+/// `EPERM`, `EIO`, `EBUSY`, `ETXTBSY`, `EAGAIN`, `ENOSPC` and `EDQUOT`.
+/// Since Linux 6.14.
+#[allow(non_snake_case)]
+pub const fn FAN_DENY_ERRNO(errno: i32) -> Response {
+    Response(FAN_DENY.0 | ((errno as u32 & FAN_ERRNO_MASK) << FAN_ERRNO_SHIFT))
+}
+
+impl Response {
+    /// Errno set by [`FAN_DENY_ERRNO()`], `0` if none.
+    #[inline]
+    pub const fn errno(self) -> i32 {
+        ((self.0 >> FAN_ERRNO_SHIFT) & FAN_ERRNO_MASK) as i32
+    }
+
+    /// The response without the errno of [`FAN_DENY_ERRNO()`].
+    #[inline]
+    pub const fn without_errno(self) -> Self {
+        Response(self.0 & !(FAN_ERRNO_MASK << FAN_ERRNO_SHIFT))
+    }
+}
+
+/* Response information record types */
+
+/// No information record follows the response.
+pub const FAN_RESPONSE_INFO_NONE: u8 = 0;
+
+/// A [`fanotify_response_info_audit_rule`] record follows the response.
+pub const FAN_RESPONSE_INFO_AUDIT_RULE: u8 = 1;
 
 /// Indicates a queue overflow.
 pub const FAN_NOFD: i32 = -1;
 
 /// This value allows only read access.
-pub const O_RDONLY: u32 = 00000000;
+pub const O_RDONLY: EventFFlags = EventFFlags(libc::O_RDONLY as u32);
 
 /// This value allows only write access.
-pub const O_WRONLY: u32 = 1;
+pub const O_WRONLY: EventFFlags = EventFFlags(libc::O_WRONLY as u32);
 
 /// This value allows read and write access.
-pub const O_RDWR: u32 = 2;
+pub const O_RDWR: EventFFlags = EventFFlags(libc::O_RDWR as u32);
 
 /// The file is opened in append mode.
-pub const O_APPEND: u32 = 2000;
+pub const O_APPEND: EventFFlags = EventFFlags(libc::O_APPEND as u32);
 
 /// When possible, the file is opened in nonblocking mode.
-pub const O_NONBLOCK: u32 = 4000;
+pub const O_NONBLOCK: EventFFlags = EventFFlags(libc::O_NONBLOCK as u32);
 
 /// Write operations on the file will complete according to
 /// the requirements of synchronized I/O data integrity
 /// completion.
-pub const O_DSYNC: u32 = 10000; /* direct disk access hint */
+pub const O_DSYNC: EventFFlags = EventFFlags(libc::O_DSYNC as u32); /* direct disk access hint */
 
 /// Enable support for files exceeding 2 GB.  Failing to set
 /// this flag will result in an EOVERFLOW error when trying to
 /// open a large file which is monitored by an fanotify group
 /// on a 32-bit system.
-pub const O_LARGEFILE: u32 = 0x40000;
+pub const O_LARGEFILE: EventFFlags = EventFFlags(libc::O_LARGEFILE as u32);
 
 /// Do not update the file last access time (st_atime in the
 /// inode) when the file is [read(2)](https://man7.org/linux/man-pages/man2/read.2.html).
-pub const O_NOATIME: u32 = 1000000;
+pub const O_NOATIME: EventFFlags = EventFFlags(libc::O_NOATIME as u32);
 
 /// Enable the close-on-exec flag for the new file descriptor.
-pub const O_CLOEXEC: u32 = 2000000; /* set close_on_exec */
+pub const O_CLOEXEC: EventFFlags = EventFFlags(libc::O_CLOEXEC as u32); /* set close_on_exec */
 
 /// Special value used to indicate openat should use the current working directory
 pub const AT_FDCWD: i32 = -100;
 
 /* Flags to determine fanotify event format */
 /// Report pidfd for event->pid
-pub const FAN_REPORT_PIDFD: u32 = 0x00000080; /* Report pidfd for event->pid */
+pub const FAN_REPORT_PIDFD: InitFlags = InitFlags(0x00000080); /* Report pidfd for event->pid */
 
 /// Reports thred id instead of PID in event metadata
-pub const FAN_REPORT_TID: u32 = 0x00000100; /* event->pid is thread id */
+pub const FAN_REPORT_TID: InitFlags = InitFlags(0x00000100); /* event->pid is thread id */
 
 /// Report unique file id instead of file descriptor in metadata.
 /// Use [`read_with_fid()`] to read events if fnaotify is initialized with this flag
-pub const FAN_REPORT_FID: u32 = 0x00000200; /* Report unique file id */
+pub const FAN_REPORT_FID: InitFlags = InitFlags(0x00000200); /* Report unique file id */
 
 /// Report unique directory id instead of file descriptor in metadata.
 /// Use [`read_with_fid()`] to read events if fnaotify is initialized with this flag
-pub const FAN_REPORT_DIR_FID: u32 = 0x00000400; /* Report unique directory id */
+pub const FAN_REPORT_DIR_FID: InitFlags = InitFlags(0x00000400); /* Report unique directory id */
 
 /// Report events with name
-pub const FAN_REPORT_NAME: u32 = 0x00000800; /* Report events with name */
+pub const FAN_REPORT_NAME: InitFlags = InitFlags(0x00000800); /* Report events with name */
 
 /// Report dirent target id
-pub const FAN_REPORT_TARGET_FID: u32 = 0x00001000; /* Report dirent target id  */
+pub const FAN_REPORT_TARGET_FID: InitFlags = InitFlags(0x00001000); /* Report dirent target id  */
 
 /// Convenience macro - [`FAN_REPORT_NAME`] requires [`FAN_REPORT_DIR_FID`]
-pub const FAN_REPORT_DFID_NAME: u32 = FAN_REPORT_DIR_FID | FAN_REPORT_NAME;
+pub const FAN_REPORT_DFID_NAME: InitFlags = FAN_REPORT_DIR_FID.union(FAN_REPORT_NAME);
 
 /// Convenience macro - [`FAN_REPORT_TARGET_FID`] requires all other FID flags
 /// ([`FAN_REPORT_DFID_NAME`], [`FAN_REPORT_FID`] , [`FAN_REPORT_TARGET_FID`])
-pub const FAN_REPORT_DFID_NAME_TARGET: u32 =
-    FAN_REPORT_DFID_NAME | FAN_REPORT_FID | FAN_REPORT_TARGET_FID;
+pub const FAN_REPORT_DFID_NAME_TARGET: InitFlags = FAN_REPORT_DFID_NAME
+    .union(FAN_REPORT_FID)
+    .union(FAN_REPORT_TARGET_FID);
+
+/* Info record types attached to an event (fanotify_event_info_header.info_type) */
+/// Record identifies the object correlated to the event by file handle.
+pub const FAN_EVENT_INFO_TYPE_FID: u8 = 1;
+
+/// Record identifies a directory by file handle followed by the
+/// name of an entry in it.
+pub const FAN_EVENT_INFO_TYPE_DFID_NAME: u8 = 2;
+
+/// Record identifies the directory correlated to the event by file handle.
+pub const FAN_EVENT_INFO_TYPE_DFID: u8 = 3;
+
+/// Record carries a pidfd for the process that caused the event.
+/// Reported when fanotify is initialized with [`FAN_REPORT_PIDFD`].
+pub const FAN_EVENT_INFO_TYPE_PIDFD: u8 = 4;
+
+/// Record carries the error reported by a [`FAN_FS_ERROR`] event.
+pub const FAN_EVENT_INFO_TYPE_ERROR: u8 = 5;
+
+/* Special info types for FAN_RENAME */
+/// Old parent directory and name of a [`FAN_RENAME`] event.
+pub const FAN_EVENT_INFO_TYPE_OLD_DFID_NAME: u8 = 10;
+
+/// New parent directory and name of a [`FAN_RENAME`] event.
+pub const FAN_EVENT_INFO_TYPE_NEW_DFID_NAME: u8 = 12;
+
+/// Value of the pidfd record when the process that caused the
+/// event could not be referred to by a pidfd.
+pub const FAN_NOPIDFD: i32 = FAN_NOFD;
+
+/// Value of the pidfd record when creating the pidfd failed.
+pub const FAN_EPIDFD: i32 = -2;
 
 /* Deprecated - do not use this in programs and do not add new flags here! */
 #[deprecated(note = "do not use this in programs!")]
 #[allow(deprecated)] // only allowing it because of 1-1 mapping
 /// Deprecated, Do not use.
-pub const FAN_ALL_INIT_FLAGS: u32 =
-    FAN_CLOEXEC | FAN_NONBLOCK | FAN_ALL_CLASS_BITS | FAN_UNLIMITED_QUEUE | FAN_UNLIMITED_MARKS;
+pub const FAN_ALL_INIT_FLAGS: InitFlags = FAN_CLOEXEC
+    .union(FAN_NONBLOCK)
+    .union(FAN_ALL_CLASS_BITS)
+    .union(FAN_UNLIMITED_QUEUE)
+    .union(FAN_UNLIMITED_MARKS);
 
 /* flags used for fanotify_modify_mark() */
 
 /// The events in mask will be added to the mark mask (or to
 /// the ignore mask).  mask must be nonempty or the error
 /// [`EINVAL`] will occur.
-pub const FAN_MARK_ADD: u32 = 0x00000001;
+pub const FAN_MARK_ADD: MarkFlags = MarkFlags(0x00000001);
 
 /// The events in argument mask will be removed from the mark
 /// mask (or from the ignore mask).  mask must be nonempty or
 /// the error [`EINVAL`] will occur.
-pub const FAN_MARK_REMOVE: u32 = 0x00000002;
+pub const FAN_MARK_REMOVE: MarkFlags = MarkFlags(0x00000002);
 
 /// If pathname is a symbolic link, mark the link itself,
 /// rather than the file to which it refers.  (By default,
 /// `fanotify_mark()` dereferences pathname if it is a symbolic
 /// link.)
-pub const FAN_MARK_DONT_FOLLOW: u32 = 0x00000004;
+pub const FAN_MARK_DONT_FOLLOW: MarkFlags = MarkFlags(0x00000004);
 
 /// Marks a directory filesystem object for events.
 /// If the filesystem object to be marked is not a directory,
 /// the error [`ENOTDIR`] shall be raised.
-pub const FAN_MARK_ONLYDIR: u32 = 0x00000008;
+pub const FAN_MARK_ONLYDIR: MarkFlags = MarkFlags(0x00000008);
 
 /* FAN_MARK_MOUNT is		0x00000010 */
 /// The events in mask shall be added to or removed from the
 /// ignore mask.
-pub const FAN_MARK_IGNORED_MASK: u32 = 0x00000020;
+pub const FAN_MARK_IGNORED_MASK: MarkFlags = MarkFlags(0x00000020);
 
 /// The ignore mask shall survive modify events.  If this flag
 /// is not set, the ignore mask is cleared when a modify event
 /// occurs for the ignored file or directory.
-pub const FAN_MARK_IGNORED_SURV_MODIFY: u32 = 0x00000040;
+pub const FAN_MARK_IGNORED_SURV_MODIFY: MarkFlags = MarkFlags(0x00000040);
 
 /// Remove either all marks for filesystems, all marks for
 /// mounts, or all marks for directories and files from the
@@ -308,7 +745,7 @@ pub const FAN_MARK_IGNORED_SURV_MODIFY: u32 = 0x00000040;
 /// and at most one of, the flags [`FAN_MARK_MOUNT`] or
 /// [`FAN_MARK_FILESYSTEM`] can be used in conjunction with
 /// [`FAN_MARK_FLUSH`].  mask is ignored.
-pub const FAN_MARK_FLUSH: u32 = 0x00000080;
+pub const FAN_MARK_FLUSH: MarkFlags = MarkFlags(0x00000080);
 
 /* FAN_MARK_FILESYSTEM is	0x00000100 */
 /// When an inode mark is created with this flag, the inode object
@@ -331,14 +768,14 @@ pub const FAN_MARK_FLUSH: u32 = 0x00000080;
 /// an ignore mask on the directory. Evictable inode marks allow using
 /// this method for a large number of directories without the concern
 /// of pinning all inodes and exhausting the system's memory.
-pub const FAN_MARK_EVICTABLE: u32 = 0x00000200;
+pub const FAN_MARK_EVICTABLE: MarkFlags = MarkFlags(0x00000200);
 
 /// This bit is mutually exclusive with [`FAN_MARK_IGNORED_MASK`] bit.
 /// When using FAN_MARK_IGNORE for the first time, mark starts using
 /// independent event flags in ignore mask.  After that, trying to
 /// update the ignore mask with the old [`FAN_MARK_IGNORED_MASK`] API
 /// will result in [`EEXIST`] error.
-pub const FAN_MARK_IGNORE: u32 = 0x00000400;
+pub const FAN_MARK_IGNORE: MarkFlags = MarkFlags(0x00000400);
 
 /* These are NOT bitwise flags.  Both bits can be used togther.  */
 
@@ -352,34 +789,37 @@ pub const FAN_MARK_IGNORE: u32 = 0x00000400;
 /// provided as a mask when flags contains [`FAN_MARK_MOUNT`].
 /// Attempting to do so will result in the error EINVAL being
 /// returned.
-pub const FAN_MARK_MOUNT: u32 = 0x00000010;
+pub const FAN_MARK_MOUNT: MarkFlags = MarkFlags(0x00000010);
 
 /// Mark the filesystem specified by pathname.  The filesystem
 /// containing pathname will be marked.  All the contained
 /// files and directories of the filesystem from any mount
 /// point will be monitored.
-pub const FAN_MARK_FILESYSTEM: u32 = 0x00000100;
+pub const FAN_MARK_FILESYSTEM: MarkFlags = MarkFlags(0x00000100);
 
 /// Convenience macro - [`FAN_MARK_IGNORE`] requires [`FAN_MARK_IGNORED_SURV_MODIFY`]
 /// for non-inode mark types.
-pub const FAN_MARK_IGNORE_SURV: u32 = FAN_MARK_IGNORE | FAN_MARK_IGNORED_SURV_MODIFY;
+pub const FAN_MARK_IGNORE_SURV: MarkFlags = FAN_MARK_IGNORE.union(FAN_MARK_IGNORED_SURV_MODIFY);
 
 /* Deprecated - do not use this in programs and do not add new flags here! */
 /// Deprecated, Do not use.
 #[deprecated(note = "do not use this in programs!")]
-pub const FAN_ALL_MARK_FLAGS: u32 = FAN_MARK_ADD
-    | FAN_MARK_REMOVE
-    | FAN_MARK_DONT_FOLLOW
-    | FAN_MARK_ONLYDIR
-    | FAN_MARK_MOUNT
-    | FAN_MARK_IGNORED_MASK
-    | FAN_MARK_IGNORED_SURV_MODIFY
-    | FAN_MARK_FLUSH;
+pub const FAN_ALL_MARK_FLAGS: MarkFlags = FAN_MARK_ADD
+    .union(FAN_MARK_REMOVE)
+    .union(FAN_MARK_DONT_FOLLOW)
+    .union(FAN_MARK_ONLYDIR)
+    .union(FAN_MARK_MOUNT)
+    .union(FAN_MARK_IGNORED_MASK)
+    .union(FAN_MARK_IGNORED_SURV_MODIFY)
+    .union(FAN_MARK_FLUSH);
 
 /* Deprecated - do not use this in programs and do not add new flags here! */
 /// Deprecated, Do not use.
 #[deprecated(note = "do not use this in programs!")]
-pub const FAN_ALL_EVENTS: u64 = FAN_ACCESS | FAN_MODIFY | FAN_CLOSE | FAN_OPEN;
+pub const FAN_ALL_EVENTS: EventMask = FAN_ACCESS
+    .union(FAN_MODIFY)
+    .union(FAN_CLOSE)
+    .union(FAN_OPEN);
 
 /*
  * All events which require a permission response from userspace
@@ -387,15 +827,17 @@ pub const FAN_ALL_EVENTS: u64 = FAN_ACCESS | FAN_MODIFY | FAN_CLOSE | FAN_OPEN;
 /* Deprecated - do not use this in programs and do not add new flags here! */
 /// Deprecated, Do not use.
 #[deprecated(note = "do not use this in programs!")]
-pub const FAN_ALL_PERM_EVENTS: u64 = FAN_OPEN_PERM | FAN_ACCESS_PERM;
+pub const FAN_ALL_PERM_EVENTS: EventMask = FAN_OPEN_PERM.union(FAN_ACCESS_PERM);
 
 /* Deprecated - do not use this in programs and do not add new flags here! */
 /// Deprecated, Do not use.
 #[deprecated(note = "do not use this in programs!")]
 #[allow(deprecated)] // only allowing it because of 1-1 mapping
-pub const FAN_ALL_OUTGOING_EVENTS: u64 = FAN_ALL_EVENTS | FAN_ALL_PERM_EVENTS | FAN_Q_OVERFLOW;
+pub const FAN_ALL_OUTGOING_EVENTS: EventMask = FAN_ALL_EVENTS
+    .union(FAN_ALL_PERM_EVENTS)
+    .union(FAN_Q_OVERFLOW);
 
-/// Compare [`fanotify_event_metadata.vers`] to verify
+/// Compare [`EventRef::vers()`] to verify
 /// that the structures returned at run time match the
 /// structures defined at compile time.  In case of a
 /// mismatch, the application should abandon trying to use the
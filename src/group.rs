@@ -0,0 +1,966 @@
+//! Owned fanotify group.
+//!
+//! [`Fanotify`] owns the file descriptor returned by [`init()`] and
+//! remembers the flags it was created with, so [`Fanotify::mark()`] and
+//! [`Fanotify::respond()`] can refuse arguments that do not fit the
+//! group before the kernel does.
+//!
+//! # Example
+//! This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+//! ```rust
+//! # use naughtyfy::flags::*;
+//! # use naughtyfy::group::*;
+//! # use naughtyfy::errors::*;
+//! match Fanotify::new(FAN_CLASS_NOTIF | FAN_CLOEXEC, O_RDONLY) {
+//!     Ok(group) => {
+//!         assert_eq!(group.class(), Class::Notif);
+//!         // Permission events need a content class.
+//!         let m = group.mark(FAN_MARK_ADD, FAN_OPEN_PERM, AT_FDCWD, "/tmp");
+//!         assert!(matches!(m, Err(FanotifyError::Invalid(_))));
+//!     }
+//!     Err(e) => {
+//!         // This can fail for multiple reason, most common being privileges.
+//!         eprintln!("Cannot get fd due to {e}");
+//!     }
+//! }
+//! ```
+
+use crate::{
+    api,
+    cancel::{wait_readable, Canceller},
+    errors::FanotifyError,
+    event::{Event, EventBuffer},
+    exclude::SelfExclusion,
+    failure::{FailurePolicy, Guard, Tracker},
+    flags::*,
+    permission::Responder,
+    reader::{BufferSize, EventReader},
+    types::*,
+};
+use std::ops::ControlFlow;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::{api::*, permission::PermissionRequest};
+
+/// Events that need a permission response from the listener.
+pub(crate) const PERM_EVENTS: EventMask = FAN_OPEN_PERM
+    .union(FAN_ACCESS_PERM)
+    .union(FAN_OPEN_EXEC_PERM);
+
+/// Events that can only be reported by groups identifying
+/// filesystem objects by file handles.
+pub(crate) const FID_EVENTS: EventMask = FAN_ATTRIB
+    .union(FAN_CREATE)
+    .union(FAN_DELETE)
+    .union(FAN_DELETE_SELF)
+    .union(FAN_MOVE)
+    .union(FAN_MOVE_SELF)
+    .union(FAN_RENAME);
+
+/// Init flags that make the group identify objects by file handles.
+const FID_REPORT_FLAGS: InitFlags = FAN_REPORT_FID.union(FAN_REPORT_DIR_FID);
+
+/// Notification class of a group, decides if permission events can be
+/// marked and the order listeners receive events in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    /// [`FAN_CLASS_NOTIF`], notification events only.
+    Notif,
+    /// [`FAN_CLASS_CONTENT`], permission decisions on final content.
+    Content,
+    /// [`FAN_CLASS_PRE_CONTENT`], permission decisions before content is final.
+    PreContent,
+}
+
+impl Class {
+    /// Class encoded in `flags` passed to [`init()`].
+    pub fn from_flags(flags: InitFlags) -> Self {
+        if flags.contains(FAN_CLASS_PRE_CONTENT) {
+            Class::PreContent
+        } else if flags.contains(FAN_CLASS_CONTENT) {
+            Class::Content
+        } else {
+            Class::Notif
+        }
+    }
+
+    /// Bits of the class to be passed to [`init()`].
+    pub fn bits(self) -> InitFlags {
+        match self {
+            Class::Notif => FAN_CLASS_NOTIF,
+            Class::Content => FAN_CLASS_CONTENT,
+            Class::PreContent => FAN_CLASS_PRE_CONTENT,
+        }
+    }
+
+    /// Check if groups of this class can receive permission events.
+    pub fn allows_permission(self) -> bool {
+        self != Class::Notif
+    }
+}
+
+/// A fanotify group owning its file descriptor.
+///
+/// Events are read into a buffer sized by the [`BufferSize`] of the
+/// group, [`BufferSize::default()`] unless set with
+/// [`FanotifyBuilder::buffer_size()`] or [`Fanotify::set_buffer_size()`].
+#[derive(Debug)]
+pub struct Fanotify {
+    /// Dropped first, to answer requests while `fd` is open.
+    guard: Guard,
+    /// Shared weakly with the [`PermissionRequest`]s of the group.
+    fd: Arc<OwnedFd>,
+    flags: InitFlags,
+    event_f_flags: EventFFlags,
+    class: Class,
+    buffer_size: BufferSize,
+    /// Size of the next read, follows `buffer_size` when adaptive.
+    buffer_len: AtomicUsize,
+    default_response: Response,
+    exclude_self: bool,
+}
+
+impl Fanotify {
+    /// Initializes a new fanotify group, see [`init()`] for the meaning
+    /// of `flags` and `event_f_flags`.
+    pub fn new(flags: InitFlags, event_f_flags: EventFFlags) -> Result<Self, FanotifyError> {
+        let fd = api::init(flags, event_f_flags)?;
+        Ok(Fanotify::with_flags(fd, flags, event_f_flags))
+    }
+
+    fn with_flags(fd: OwnedFd, flags: InitFlags, event_f_flags: EventFFlags) -> Self {
+        let buffer_size = BufferSize::default();
+        let fd = Arc::new(fd);
+        Fanotify {
+            guard: Guard(Tracker::new(&fd)),
+            fd,
+            flags,
+            event_f_flags,
+            class: Class::from_flags(flags),
+            buffer_size,
+            buffer_len: AtomicUsize::new(buffer_size.initial()),
+            default_response: FAN_ALLOW,
+            exclude_self: true,
+        }
+    }
+
+    /// Start configuring a new group, see [`FanotifyBuilder`].
+    pub fn builder() -> FanotifyBuilder {
+        FanotifyBuilder::default()
+    }
+
+    /// Flags the group was initialized with.
+    #[inline]
+    pub fn flags(&self) -> InitFlags {
+        self.flags
+    }
+
+    /// File status flags set on event file descriptors.
+    #[inline]
+    pub fn event_f_flags(&self) -> EventFFlags {
+        self.event_f_flags
+    }
+
+    /// Notification class of the group.
+    #[inline]
+    pub fn class(&self) -> Class {
+        self.class
+    }
+
+    /// Sizing of the buffer events are read into.
+    #[inline]
+    pub fn buffer_size(&self) -> BufferSize {
+        self.buffer_size
+    }
+
+    /// Change the sizing of the buffer events are read into, the next
+    /// read uses [`BufferSize::initial()`].
+    /// Returns [`FanotifyError::Invalid`] if [`BufferSize::check()`] fails.
+    pub fn set_buffer_size(&mut self, buffer_size: BufferSize) -> Result<(), FanotifyError> {
+        buffer_size.check()?;
+        self.buffer_size = buffer_size;
+        *self.buffer_len.get_mut() = buffer_size.initial();
+        Ok(())
+    }
+
+    /// Weak reference to the descriptor, for helpers that must not keep
+    /// the group open.
+    pub(crate) fn downgrade(&self) -> Weak<OwnedFd> {
+        Arc::downgrade(&self.fd)
+    }
+
+    /// Check if the group identifies filesystem objects by file handles.
+    #[inline]
+    pub fn reports_fid(&self) -> bool {
+        self.flags.intersects(FID_REPORT_FLAGS)
+    }
+
+    /// Adds, removes, or modifies a mark, see [`mark()`].
+    ///
+    /// Returns [`FanotifyError::Invalid`] without calling the kernel when
+    /// * `flags` does not hold exactly one of [`FAN_MARK_ADD`],
+    ///   [`FAN_MARK_REMOVE`] and [`FAN_MARK_FLUSH`].
+    /// * `mask` is empty when adding or removing.
+    /// * `mask` has permission events and the group is [`Class::Notif`]
+    ///   or identifies objects by file handles.
+    /// * `mask` has events needing file handles ([`FAN_CREATE`], [`FAN_ATTRIB`] ...)
+    ///   and the group does not report them or `flags` has [`FAN_MARK_MOUNT`].
+    pub fn mark<P: ?Sized + Path>(
+        &self,
+        flags: MarkFlags,
+        mask: EventMask,
+        dirfd: i32,
+        path: &P,
+    ) -> Result<(), FanotifyError> {
+        self.check_mark(flags, mask)?;
+        api::mark(&self.fd, flags, mask, dirfd, path)
+    }
+
+    fn check_mark(&self, flags: MarkFlags, mask: EventMask) -> Result<(), FanotifyError> {
+        let action = flags & (FAN_MARK_ADD | FAN_MARK_REMOVE | FAN_MARK_FLUSH);
+        if action.bits().count_ones() != 1 {
+            return Err(FanotifyError::Invalid(
+                "Mark flags must include exactly one of FAN_MARK_ADD, \
+                FAN_MARK_REMOVE and FAN_MARK_FLUSH"
+                    .to_string(),
+            ));
+        }
+        if action == FAN_MARK_FLUSH {
+            return Ok(());
+        }
+        if mask.is_empty() {
+            return Err(FanotifyError::Invalid(
+                "Mask must be nonempty when adding or removing a mark".to_string(),
+            ));
+        }
+        if mask.intersects(PERM_EVENTS) {
+            if !self.class.allows_permission() {
+                return Err(FanotifyError::Invalid(
+                    "Permission events need a group initialized with \
+                    FAN_CLASS_CONTENT or FAN_CLASS_PRE_CONTENT"
+                        .to_string(),
+                ));
+            }
+            if self.reports_fid() {
+                return Err(FanotifyError::Invalid(
+                    "Permission events cannot be reported to a group \
+                    identifying objects by file handles"
+                        .to_string(),
+                ));
+            }
+        }
+        if mask.intersects(FID_EVENTS) {
+            if !self.reports_fid() {
+                return Err(FanotifyError::Invalid(
+                    "Directory entry and attribute events need a group \
+                    initialized with FAN_REPORT_FID or FAN_REPORT_DIR_FID"
+                        .to_string(),
+                ));
+            }
+            if flags.contains(FAN_MARK_MOUNT) {
+                return Err(FanotifyError::Invalid(
+                    "Directory entry and attribute events cannot be \
+                    used with FAN_MARK_MOUNT"
+                        .to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Response sent for a [`PermissionRequest`] dropped unanswered.
+    #[inline]
+    pub fn default_response(&self) -> Response {
+        self.default_response
+    }
+
+    /// Change the response sent for a [`PermissionRequest`] dropped
+    /// unanswered, readers created before keep the previous one.
+    /// Returns [`FanotifyError::Invalid`] for a response the group
+    /// cannot send, see [`Fanotify::respond()`].
+    pub fn set_default_response(&mut self, response: Response) -> Result<(), FanotifyError> {
+        check_response(self.flags, &fanotify_response::new(&FAN_NOFD, response))?;
+        self.default_response = response;
+        Ok(())
+    }
+
+    /// How outstanding requests are answered on panic and drop,
+    /// see [`crate::failure`].
+    #[inline]
+    pub fn failure_policy(&self) -> Option<FailurePolicy> {
+        self.guard.0.policy()
+    }
+
+    /// Change how outstanding requests are answered on panic and drop,
+    /// `None` leaves them to the kernel. See [`crate::failure`].
+    ///
+    /// Returns [`FanotifyError::Invalid`] for a group of [`Class::Notif`].
+    pub fn set_failure_policy(
+        &mut self,
+        policy: Option<FailurePolicy>,
+    ) -> Result<(), FanotifyError> {
+        if policy.is_some() && !self.class.allows_permission() {
+            return Err(FanotifyError::Invalid(
+                "Failure policies need a group initialized with \
+                FAN_CLASS_CONTENT or FAN_CLASS_PRE_CONTENT"
+                    .to_string(),
+            ));
+        }
+        self.guard.0.set_policy(policy);
+        Ok(())
+    }
+
+    /// Check if events caused by this process are skipped, see [`crate::exclude`].
+    #[inline]
+    pub fn excludes_self(&self) -> bool {
+        self.exclude_self
+    }
+
+    /// Skip the events caused by this process or not, see [`crate::exclude`].
+    /// Readers created before keep the previous setting.
+    pub fn set_exclude_self(&mut self, exclude_self: bool) {
+        self.exclude_self = exclude_self;
+    }
+
+    /// Create a reader reusing one buffer sized by [`Fanotify::buffer_size()`],
+    /// pass the group to [`EventReader::read()`].
+    ///
+    /// Owned events of permission groups taken from the reader, like those
+    /// of [`Fanotify::events()`], carry a [`PermissionRequest`]. Events of
+    /// [`EventReader::read()`] are borrowed and answered with [`Fanotify::respond()`].
+    pub fn reader(&self) -> EventReader {
+        let mut reader = EventReader::with_size(self.buffer_size);
+        if self.exclude_self {
+            reader.exclude = Some(SelfExclusion::new(&self.fd, self.flags));
+        }
+        if self.class.allows_permission() {
+            reader.responder = Some(Responder::new(
+                &self.fd,
+                self.flags,
+                self.default_response,
+                &self.guard.0,
+            ));
+        }
+        reader
+    }
+
+    /// Keep reading events and call `process_event` on each of them until it
+    /// returns [`ControlFlow::Break`], see [`run_until()`].
+    /// Reads reuse the buffer of a single [`Fanotify::reader()`].
+    pub fn run_until<E, F>(&self, process_event: F) -> Result<E, FanotifyError>
+    where
+        F: FnMut(&Event) -> ControlFlow<E>,
+    {
+        api::run_until_with(&mut self.reader(), &*self.fd, process_event)
+    }
+
+    /// Iterate over the events of the group, reading more whenever
+    /// the ones of the last read are used up. See [`Events`].
+    ///
+    /// # Example
+    /// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+    /// ```rust
+    /// # use naughtyfy::flags::*;
+    /// # use naughtyfy::group::*;
+    /// // Our own events are wanted here.
+    /// match Fanotify::builder().nonblocking().exclude_self(false).build() {
+    ///     Ok(group) => {
+    ///         let tmp = std::env::temp_dir().canonicalize().unwrap();
+    ///         group
+    ///             .mark(FAN_MARK_ADD, FAN_OPEN | FAN_EVENT_ON_CHILD, AT_FDCWD, tmp.as_path())
+    ///             .unwrap();
+    ///         let file = tmp.join(format!("naughtyfy-events-{}", std::process::id()));
+    ///         std::fs::write(&file, b"").unwrap();
+    ///
+    ///         // Nonblocking, so the iterator ends once the queue is empty.
+    ///         let opened = group
+    ///             .events()
+    ///             .filter_map(Result::ok)
+    ///             .filter(|event| event.pid() == std::process::id() as i32)
+    ///             .any(|event| event.path().is_ok_and(|path| path == file));
+    ///         assert!(opened);
+    ///         std::fs::remove_file(&file).unwrap();
+    ///     }
+    ///     Err(e) => {
+    ///         // This can fail for multiple reason, most common being privileges.
+    ///         eprintln!("Cannot get fd due to {e}");
+    ///     }
+    /// }
+    /// ```
+    pub fn events(&self) -> Events<'_> {
+        Events {
+            group: self,
+            reader: self.reader(),
+            timeout: None,
+            canceller: None,
+            cancelled: false,
+        }
+    }
+
+    /// Read the pending events of the group.
+    /// Walk them with [`EventBuffer::events()`].
+    /// Allocates a new buffer on every call, see [`Fanotify::reader()`].
+    ///
+    /// The buffer is [`Fanotify::buffer_size()`] bytes, when adaptive the
+    /// size of the next read follows how much of this one was filled.
+    /// It is empty when every event read was skipped, see [`crate::exclude`].
+    pub fn read(&self) -> Result<EventBuffer, FanotifyError> {
+        let len = self.buffer_len.load(Ordering::Relaxed);
+        let mut buffer = EventBuffer::new(len);
+        api::read_into(&*self.fd, &mut buffer)?;
+        self.buffer_len
+            .store(self.buffer_size.next(len, buffer.len()), Ordering::Relaxed);
+        if self.exclude_self {
+            SelfExclusion::new(&self.fd, self.flags).filter(&mut buffer);
+        }
+        Ok(buffer)
+    }
+
+    /// Same as [`Fanotify::read()`] but waits at most `timeout` for events,
+    /// the returned buffer is empty if none arrived in time.
+    pub fn read_timeout(&self, timeout: Duration) -> Result<EventBuffer, FanotifyError> {
+        match wait_readable(self, Some(timeout), None)? {
+            true => self.read(),
+            false => Ok(EventBuffer::new(0)),
+        }
+    }
+
+    /// Same as [`Fanotify::read_timeout()`] but returns
+    /// [`FanotifyError::Cancelled`] once `canceller` fires.
+    /// A `timeout` of `None` waits until an event arrives or cancellation.
+    pub fn read_cancellable(
+        &self,
+        canceller: &Canceller,
+        timeout: Option<Duration>,
+    ) -> Result<EventBuffer, FanotifyError> {
+        match wait_readable(self, timeout, Some(canceller))? {
+            true => self.read(),
+            false => Ok(EventBuffer::new(0)),
+        }
+    }
+
+    /// Answer a permission event, see [`write()`].
+    ///
+    /// Returns [`FanotifyError::Invalid`] without calling the kernel when
+    /// * the group is [`Class::Notif`].
+    /// * the response is neither [`FAN_ALLOW`] nor [`FAN_DENY`].
+    /// * it asks for [`FAN_AUDIT`] on a group initialized without [`FAN_ENABLE_AUDIT`].
+    /// * it has [`FAN_INFO`] set without an audit rule record.
+    /// * it is a [`FAN_DENY_ERRNO()`] from a group other than [`Class::PreContent`]
+    ///   or with an errno the kernel does not accept.
+    ///
+    /// Older kernels refuse [`FAN_INFO`] and [`FAN_DENY_ERRNO()`] with
+    /// [`FanotifyError::Write`] (`EINVAL`), see [`Fanotify::supports_response()`].
+    pub fn respond(&self, response: &fanotify_response) -> Result<isize, FanotifyError> {
+        check_response(self.flags, response)?;
+        api::write(&self.fd, response)
+    }
+
+    /// Check if the running kernel accepts `response` from this group,
+    /// Eg: [`FAN_INFO`] needs Linux 6.3 and [`FAN_DENY_ERRNO()`] Linux 6.14.
+    ///
+    /// The response is written for a descriptor no event has, the kernel
+    /// validates it and then fails to find the event (`ENOENT`), an
+    /// unsupported response is refused with `EINVAL` first. Responses the
+    /// group cannot send (see [`Fanotify::respond()`]) return `Ok(false)`.
+    /// A [`FAN_INFO`] response is probed with its record, an empty
+    /// audit rule unless set.
+    ///
+    /// # Example
+    /// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+    /// ```rust
+    /// # use naughtyfy::flags::*;
+    /// # use naughtyfy::group::*;
+    /// # use naughtyfy::types::*;
+    /// match Fanotify::builder().class(Class::PreContent).build() {
+    ///     Ok(group) => {
+    ///         let errno = group.supports_response(FAN_DENY_ERRNO(libc::EIO)).unwrap();
+    ///         println!("FAN_DENY_ERRNO supported: {errno}");
+    ///         // FAN_AUDIT needs FAN_ENABLE_AUDIT.
+    ///         assert!(!group.supports_response(FAN_ALLOW | FAN_AUDIT).unwrap());
+    ///     }
+    ///     Err(e) => {
+    ///         // This can fail for multiple reason, most common being privileges.
+    ///         eprintln!("Cannot get fd due to {e}");
+    ///     }
+    /// }
+    /// ```
+    pub fn supports_response(&self, response: Response) -> Result<bool, FanotifyError> {
+        let mut probe = fanotify_response::new(&libc::c_int::MAX, response);
+        if response.contains(FAN_INFO) {
+            probe.audit_rule = fanotify_response_info_audit_rule::new(0, 2, 2);
+        }
+        if check_response(self.flags, &probe).is_err() {
+            return Ok(false);
+        }
+        match api::write(&self.fd, &probe) {
+            Ok(_) | Err(FanotifyError::Write(libc::ENOENT)) => Ok(true),
+            Err(FanotifyError::Write(libc::EINVAL)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Errnos the kernel accepts in [`FAN_DENY_ERRNO()`].
+const DENY_ERRNOS: [i32; 7] = [
+    libc::EPERM,
+    libc::EIO,
+    libc::EBUSY,
+    libc::ETXTBSY,
+    libc::EAGAIN,
+    libc::ENOSPC,
+    libc::EDQUOT,
+];
+
+/// `response` without [`FAN_AUDIT`] if a group initialized with `flags`
+/// cannot send it, the access is still allowed or denied.
+pub(crate) fn without_audit(flags: InitFlags, response: Response) -> Response {
+    match flags.contains(FAN_ENABLE_AUDIT) {
+        true => response,
+        false => response - FAN_AUDIT,
+    }
+}
+
+/// Check `response` can be sent by a group initialized with `flags`,
+/// see [`Fanotify::respond()`].
+pub(crate) fn check_response(
+    flags: InitFlags,
+    response: &fanotify_response,
+) -> Result<(), FanotifyError> {
+    let class = Class::from_flags(flags);
+    if !class.allows_permission() {
+        return Err(FanotifyError::Invalid(
+            "Permission responses need a group initialized with \
+            FAN_CLASS_CONTENT or FAN_CLASS_PRE_CONTENT"
+                .to_string(),
+        ));
+    }
+    let verdict = response.response.without_errno() - FAN_AUDIT - FAN_INFO;
+    if verdict != FAN_ALLOW && verdict != FAN_DENY {
+        return Err(FanotifyError::Invalid(format!(
+            "Response {} must be either FAN_ALLOW or FAN_DENY",
+            response.response
+        )));
+    }
+    let errno = response.response.errno();
+    if errno != 0 {
+        if verdict != FAN_DENY {
+            return Err(FanotifyError::Invalid(
+                "An errno can only be returned with FAN_DENY".to_string(),
+            ));
+        }
+        if class != Class::PreContent {
+            return Err(FanotifyError::Invalid(
+                "FAN_DENY_ERRNO needs a group initialized with FAN_CLASS_PRE_CONTENT".to_string(),
+            ));
+        }
+        if !DENY_ERRNOS.contains(&errno) {
+            return Err(FanotifyError::Invalid(format!(
+                "Errno {errno} cannot be returned with FAN_DENY_ERRNO"
+            )));
+        }
+    }
+    if response.response.contains(FAN_AUDIT) && !flags.contains(FAN_ENABLE_AUDIT) {
+        return Err(FanotifyError::Invalid(
+            "FAN_AUDIT needs a group initialized with FAN_ENABLE_AUDIT".to_string(),
+        ));
+    }
+    if response.response.contains(FAN_INFO)
+        && (response.audit_rule.hdr.info_type != FAN_RESPONSE_INFO_AUDIT_RULE
+            || response.audit_rule.hdr.len as usize
+                != std::mem::size_of::<fanotify_response_info_audit_rule>())
+    {
+        return Err(FanotifyError::Invalid(
+            "FAN_INFO needs an audit rule record, see fanotify_response::with_audit_rule()"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Iterator over the events of a [`Fanotify`] group, created by
+/// [`Fanotify::events()`].
+///
+/// Reads into a single reused buffer whenever the events of the last
+/// read are used up. A read interrupted by a signal (`EINTR`) is retried.
+/// For a [`FAN_NONBLOCK`] group the iterator returns `None` once no event
+/// is pending (`EAGAIN`), calling `next()` again later reads again.
+/// Other read errors and malformed events are yielded as `Err`.
+///
+/// With [`Events::timeout()`] the iterator also ends when no event arrives
+/// in time. With [`Events::cancel_on()`] it yields
+/// [`FanotifyError::Cancelled`] once the canceller fires and then ends.
+#[derive(Debug)]
+pub struct Events<'a> {
+    group: &'a Fanotify,
+    reader: EventReader,
+    timeout: Option<Duration>,
+    canceller: Option<Canceller>,
+    cancelled: bool,
+}
+
+impl Events<'_> {
+    /// End the iteration when no event arrives within `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stop waiting for events once `canceller` fires.
+    pub fn cancel_on(mut self, canceller: &Canceller) -> Self {
+        self.canceller = Some(canceller.clone());
+        self
+    }
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<Event, FanotifyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.reader.next_event() {
+                return Some(event);
+            }
+            if self.cancelled {
+                return None;
+            }
+            if self.timeout.is_some() || self.canceller.is_some() {
+                match wait_readable(self.group, self.timeout, self.canceller.as_ref()) {
+                    Ok(true) => {}
+                    Ok(false) => return None,
+                    Err(FanotifyError::Cancelled) => {
+                        self.cancelled = true;
+                        return Some(Err(FanotifyError::Cancelled));
+                    }
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            match self.reader.fill(&self.group.fd) {
+                Ok(0) => return None,
+                Ok(_) => continue,
+                Err(FanotifyError::Read(libc::EINTR)) => continue,
+                Err(FanotifyError::Read(libc::EAGAIN)) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Builder for [`Fanotify`] groups.
+///
+/// Collects the `flags` and `event_f_flags` of [`init()`] and checks that
+/// they can be combined before asking the kernel. Without any call the
+/// group is [`Class::Notif`] and event file descriptors are [`O_RDONLY`].
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::group::*;
+/// # use naughtyfy::errors::*;
+/// # use naughtyfy::reader::*;
+/// // File handles are only reported to notification groups.
+/// let res = Fanotify::builder().class(Class::Content).report_fid().build();
+/// assert!(matches!(res, Err(FanotifyError::Invalid(_))));
+/// // Buffers are at least 4096 bytes.
+/// let res = Fanotify::builder().buffer_size(BufferSize::Fixed(512)).build();
+/// assert!(matches!(res, Err(FanotifyError::Invalid(_))));
+///
+/// let res = Fanotify::builder()
+///     .cloexec()
+///     .nonblocking()
+///     .report_dfid_name()
+///     .build();
+/// match res {
+///     Ok(group) => {
+///         assert!(group.reports_fid());
+///         assert_eq!(group.flags(), FAN_CLOEXEC | FAN_NONBLOCK | FAN_REPORT_DFID_NAME);
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FanotifyBuilder {
+    class: Class,
+    flags: InitFlags,
+    event_f_flags: EventFFlags,
+    buffer_size: BufferSize,
+    default_response: Option<Response>,
+    exclude_self: bool,
+    failure_policy: Option<FailurePolicy>,
+}
+
+impl Default for FanotifyBuilder {
+    fn default() -> Self {
+        FanotifyBuilder {
+            class: Class::Notif,
+            flags: InitFlags::empty(),
+            event_f_flags: O_RDONLY,
+            buffer_size: BufferSize::default(),
+            default_response: None,
+            exclude_self: true,
+            failure_policy: None,
+        }
+    }
+}
+
+impl FanotifyBuilder {
+    /// Notification class of the group.
+    pub fn class(mut self, class: Class) -> Self {
+        self.class = class;
+        self
+    }
+
+    /// Set [`FAN_CLOEXEC`].
+    pub fn cloexec(mut self) -> Self {
+        self.flags |= FAN_CLOEXEC;
+        self
+    }
+
+    /// Set [`FAN_NONBLOCK`].
+    pub fn nonblocking(mut self) -> Self {
+        self.flags |= FAN_NONBLOCK;
+        self
+    }
+
+    /// Set [`FAN_UNLIMITED_QUEUE`].
+    pub fn unlimited_queue(mut self) -> Self {
+        self.flags |= FAN_UNLIMITED_QUEUE;
+        self
+    }
+
+    /// Set [`FAN_UNLIMITED_MARKS`].
+    pub fn unlimited_marks(mut self) -> Self {
+        self.flags |= FAN_UNLIMITED_MARKS;
+        self
+    }
+
+    /// Set [`FAN_ENABLE_AUDIT`].
+    pub fn enable_audit(mut self) -> Self {
+        self.flags |= FAN_ENABLE_AUDIT;
+        self
+    }
+
+    /// Set [`FAN_REPORT_TID`].
+    pub fn report_tid(mut self) -> Self {
+        self.flags |= FAN_REPORT_TID;
+        self
+    }
+
+    /// Set [`FAN_REPORT_PIDFD`].
+    pub fn report_pidfd(mut self) -> Self {
+        self.flags |= FAN_REPORT_PIDFD;
+        self
+    }
+
+    /// Set [`FAN_REPORT_FID`].
+    pub fn report_fid(mut self) -> Self {
+        self.flags |= FAN_REPORT_FID;
+        self
+    }
+
+    /// Set [`FAN_REPORT_DIR_FID`].
+    pub fn report_dir_fid(mut self) -> Self {
+        self.flags |= FAN_REPORT_DIR_FID;
+        self
+    }
+
+    /// Set [`FAN_REPORT_DFID_NAME`].
+    pub fn report_dfid_name(mut self) -> Self {
+        self.flags |= FAN_REPORT_DFID_NAME;
+        self
+    }
+
+    /// Set [`FAN_REPORT_DFID_NAME_TARGET`].
+    pub fn report_dfid_name_target(mut self) -> Self {
+        self.flags |= FAN_REPORT_DFID_NAME_TARGET;
+        self
+    }
+
+    /// File status flags of event file descriptors, the `event_f_flags`
+    /// of [`init()`]. Replaces the default [`O_RDONLY`].
+    pub fn event_open_flags(mut self, event_f_flags: EventFFlags) -> Self {
+        self.event_f_flags = event_f_flags;
+        self
+    }
+
+    /// Sizing of the buffer events are read into.
+    /// Replaces the default [`BufferSize::default()`].
+    pub fn buffer_size(mut self, buffer_size: BufferSize) -> Self {
+        self.buffer_size = buffer_size;
+        self
+    }
+
+    /// Response sent for a [`PermissionRequest`] dropped unanswered.
+    /// Replaces the default [`FAN_ALLOW`].
+    pub fn default_response(mut self, response: Response) -> Self {
+        self.default_response = Some(response);
+        self
+    }
+
+    /// How outstanding requests are answered on panic and drop,
+    /// see [`crate::failure`]. None unless set.
+    pub fn failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.failure_policy = Some(policy);
+        self
+    }
+
+    /// Skip the events caused by this process, on unless set to `false`.
+    /// See [`crate::exclude`].
+    pub fn exclude_self(mut self, exclude_self: bool) -> Self {
+        self.exclude_self = exclude_self;
+        self
+    }
+
+    /// Check the configuration and initialize the group.
+    ///
+    /// Returns [`FanotifyError::Invalid`] without calling the kernel when
+    /// * file handles are requested for a class other than [`Class::Notif`].
+    /// * [`FAN_REPORT_NAME`] is set without [`FAN_REPORT_DIR_FID`].
+    /// * [`FAN_REPORT_TARGET_FID`] is set without [`FAN_REPORT_FID`] and [`FAN_REPORT_DFID_NAME`].
+    /// * [`FAN_REPORT_PIDFD`] is combined with [`FAN_REPORT_TID`].
+    /// * the access mode of the event open flags is not one of
+    ///   [`O_RDONLY`], [`O_WRONLY`] and [`O_RDWR`].
+    /// * the buffer size fails [`BufferSize::check()`].
+    /// * the default response cannot be sent by the group, see [`Fanotify::respond()`].
+    /// * a failure policy is set for a [`Class::Notif`] group.
+    pub fn build(self) -> Result<Fanotify, FanotifyError> {
+        self.check()?;
+        let mut group = Fanotify::new(self.flags | self.class.bits(), self.event_f_flags)?;
+        group.set_buffer_size(self.buffer_size)?;
+        if let Some(response) = self.default_response {
+            group.set_default_response(response)?;
+        }
+        group.set_exclude_self(self.exclude_self);
+        group.set_failure_policy(self.failure_policy)?;
+        Ok(group)
+    }
+
+    fn check(&self) -> Result<(), FanotifyError> {
+        let fid_flags = FID_REPORT_FLAGS | FAN_REPORT_NAME | FAN_REPORT_TARGET_FID;
+        if self.flags.intersects(fid_flags) && self.class != Class::Notif {
+            return Err(FanotifyError::Invalid(format!(
+                "File handles can only be reported to FAN_CLASS_NOTIF groups, not {:?}",
+                self.class
+            )));
+        }
+        if self.flags.contains(FAN_REPORT_NAME) && !self.flags.contains(FAN_REPORT_DIR_FID) {
+            return Err(FanotifyError::Invalid(
+                "FAN_REPORT_NAME requires FAN_REPORT_DIR_FID".to_string(),
+            ));
+        }
+        if self.flags.contains(FAN_REPORT_TARGET_FID)
+            && !self.flags.contains(FAN_REPORT_DFID_NAME_TARGET)
+        {
+            return Err(FanotifyError::Invalid(
+                "FAN_REPORT_TARGET_FID requires FAN_REPORT_FID and FAN_REPORT_DFID_NAME"
+                    .to_string(),
+            ));
+        }
+        if self.flags.contains(FAN_REPORT_PIDFD | FAN_REPORT_TID) {
+            return Err(FanotifyError::Invalid(
+                "FAN_REPORT_PIDFD cannot be combined with FAN_REPORT_TID".to_string(),
+            ));
+        }
+        if self.event_f_flags.contains(O_WRONLY | O_RDWR) {
+            return Err(FanotifyError::Invalid(
+                "Event open flags must use one of O_RDONLY, O_WRONLY and O_RDWR".to_string(),
+            ));
+        }
+        self.buffer_size.check()?;
+        if self.failure_policy.is_some() && self.class == Class::Notif {
+            return Err(FanotifyError::Invalid(
+                "Failure policies need FAN_CLASS_CONTENT or FAN_CLASS_PRE_CONTENT".to_string(),
+            ));
+        }
+        match self.default_response {
+            Some(response) => check_response(
+                self.flags | self.class.bits(),
+                &fanotify_response::new(&FAN_NOFD, response),
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Flags of the fanotify group behind `fd` as shown in `/proc/self/fdinfo`.
+pub(crate) fn fdinfo_flags(fd: &OwnedFd) -> Option<(InitFlags, EventFFlags)> {
+    let info = std::fs::read_to_string(format!("/proc/self/fdinfo/{}", fd.as_raw_fd())).ok()?;
+    let line = info
+        .lines()
+        .find_map(|line| line.strip_prefix("fanotify flags:"))?;
+    let (flags, event_f_flags) = line.split_once(" event-flags:")?;
+    Some((
+        InitFlags::from_bits_retain(u32::from_str_radix(flags.trim(), 16).ok()?),
+        EventFFlags::from_bits_retain(u32::from_str_radix(event_f_flags.trim(), 16).ok()?),
+    ))
+}
+
+/// Wrap a descriptor returned by [`init()`].
+///
+/// The flags are recovered from `/proc/self/fdinfo`, when that is not
+/// possible the group is assumed to be [`Class::Notif`] without flags.
+impl From<OwnedFd> for Fanotify {
+    fn from(fd: OwnedFd) -> Self {
+        let (flags, event_f_flags) = fdinfo_flags(&fd).unwrap_or_default();
+        Fanotify::with_flags(fd, flags, event_f_flags)
+    }
+}
+
+/// Take the descriptor out of the group. The group is handed back while
+/// a [`PermissionRequest`] holds the descriptor to answer.
+impl TryFrom<Fanotify> for OwnedFd {
+    type Error = Fanotify;
+
+    fn try_from(group: Fanotify) -> Result<Self, Fanotify> {
+        let Fanotify {
+            guard,
+            fd,
+            flags,
+            event_f_flags,
+            class,
+            buffer_size,
+            buffer_len,
+            default_response,
+            exclude_self,
+        } = group;
+        match Arc::try_unwrap(fd) {
+            Ok(fd) => {
+                // The descriptor lives on, the policy must not answer or drain it.
+                guard.0.set_policy(None);
+                Ok(fd)
+            }
+            Err(fd) => Err(Fanotify {
+                guard,
+                fd,
+                flags,
+                event_f_flags,
+                class,
+                buffer_size,
+                buffer_len,
+                default_response,
+                exclude_self,
+            }),
+        }
+    }
+}
+
+impl AsFd for Fanotify {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for Fanotify {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
@@ -24,8 +24,8 @@
 //!         let m = mark(fd, FAN_MARK_ADD | FAN_MARK_MOUNT, FAN_ACCESS, AT_FDCWD, "./");
 //!         let events = read(fd).unwrap();
 //!         for event in events {
+//!             // The event fd is closed when `event` is dropped.
 //!             println!("{:#?}",event);
-//!             close(event.fd);
 //!         }
 //!     }
 //!     Err(e) => {
@@ -36,6 +36,24 @@
 //! ```
 
 pub mod api;
+pub mod cache;
+pub mod cancel;
+pub mod clamd;
+pub mod dispatch;
 pub mod errors;
+pub mod event;
+pub mod exclude;
+pub mod failure;
 pub mod flags;
+pub mod group;
+#[cfg(feature = "mio")]
+pub mod mio;
+pub mod multiplex;
+pub mod permission;
+pub mod policy;
+pub mod reader;
+pub mod scan;
+#[cfg(feature = "tokio")]
+pub mod tokio;
 pub mod types;
+pub mod worker;
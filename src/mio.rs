@@ -0,0 +1,130 @@
+//! Readiness based reading of a fanotify group with mio.
+//!
+//! Enabled by the `mio` feature. A [`FAN_NONBLOCK`] [`Fanotify`] group
+//! implements [`Source`], so it can be registered with a [`mio::Poll`]
+//! next to sockets and pipes. Once it is reported readable, call
+//! [`Fanotify::drain()`] to read every pending event, as needed with
+//! edge-triggered readiness.
+//!
+//! # Example
+//! This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+//! ```rust
+//! # use naughtyfy::flags::*;
+//! # use naughtyfy::group::*;
+//! # use mio::{Events, Interest, Poll, Token};
+//! // Our own events are wanted here.
+//! match Fanotify::builder().nonblocking().exclude_self(false).build() {
+//!     Ok(mut group) => {
+//!         let tmp = std::env::temp_dir().canonicalize().unwrap();
+//!         group
+//!             .mark(FAN_MARK_ADD, FAN_OPEN | FAN_EVENT_ON_CHILD, AT_FDCWD, tmp.as_path())
+//!             .unwrap();
+//!         let mut poll = Poll::new().unwrap();
+//!         poll.registry()
+//!             .register(&mut group, Token(0), Interest::READABLE)
+//!             .unwrap();
+//!
+//!         let file = tmp.join(format!("naughtyfy-mio-{}", std::process::id()));
+//!         std::fs::write(&file, b"").unwrap();
+//!
+//!         let mut reader = group.reader();
+//!         let mut events = Events::with_capacity(8);
+//!         let mut received = Vec::new();
+//!         'outer: loop {
+//!             poll.poll(&mut events, None).unwrap();
+//!             for ready in events.iter() {
+//!                 assert_eq!(ready.token(), Token(0));
+//!                 group.drain(&mut reader, &mut received).unwrap();
+//!                 if received
+//!                     .iter()
+//!                     .any(|event| event.path().is_ok_and(|path| path == file))
+//!                 {
+//!                     break 'outer;
+//!                 }
+//!             }
+//!         }
+//!         std::fs::remove_file(&file).unwrap();
+//!     }
+//!     Err(e) => {
+//!         // This can fail for multiple reason, most common being privileges.
+//!         eprintln!("Cannot get fd due to {e}");
+//!     }
+//! }
+//! ```
+
+use crate::{errors::FanotifyError, event::Event, flags::*, group::Fanotify, reader::EventReader};
+use ::mio::{event::Source, unix::SourceFd, Interest, Registry, Token};
+use std::{io, os::fd::AsRawFd};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::api::*;
+
+impl Fanotify {
+    /// Read every pending event of the group into `events` until the
+    /// kernel has none left (`EAGAIN`). Returns the number of events added.
+    ///
+    /// Reads go through `reader`, see [`Fanotify::reader()`]. A read
+    /// interrupted by a signal (`EINTR`) is retried. On any other error
+    /// the events read so far stay in `events`.
+    /// Returns [`FanotifyError::Invalid`] for a group initialized without
+    /// [`FAN_NONBLOCK`], since the last read would block.
+    pub fn drain(
+        &self,
+        reader: &mut EventReader,
+        events: &mut Vec<Event>,
+    ) -> Result<usize, FanotifyError> {
+        check_nonblocking(self)?;
+        let len = events.len();
+        loop {
+            match reader.fill(self) {
+                Ok(0) => break,
+                Ok(_) => {
+                    while let Some(event) = reader.next_event() {
+                        events.push(event?);
+                    }
+                }
+                Err(FanotifyError::Read(libc::EINTR)) => continue,
+                Err(FanotifyError::Read(libc::EAGAIN)) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(events.len() - len)
+    }
+}
+
+fn check_nonblocking(group: &Fanotify) -> Result<(), FanotifyError> {
+    if !group.flags().contains(FAN_NONBLOCK) {
+        return Err(FanotifyError::Invalid(
+            "Readiness based reading needs a group initialized with FAN_NONBLOCK".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Registration is refused with [`io::ErrorKind::InvalidInput`] for
+/// a group initialized without [`FAN_NONBLOCK`].
+impl Source for Fanotify {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        check_nonblocking(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        SourceFd(&self.as_raw_fd()).register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        SourceFd(&self.as_raw_fd()).deregister(registry)
+    }
+}
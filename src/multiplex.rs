@@ -0,0 +1,320 @@
+//! Waiting on several groups from one thread.
+//!
+//! [`Multiplexer`] registers [`FAN_NONBLOCK`] groups, and any other
+//! descriptor, on one [epoll](https://man7.org/linux/man-pages/man7/epoll.7.html)
+//! instance and returns their events tagged with the [`SourceId`] they
+//! came from.
+//!
+//! Groups able to receive permission events ([`Class::Content`] and
+//! [`Class::PreContent`]) are served first and drained completely on every
+//! wait, other groups get one read each. A busy notification group can
+//! therefore never delay a permission decision by more than one read.
+//!
+//! # Example
+//! This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+//! ```rust
+//! # use naughtyfy::flags::*;
+//! # use naughtyfy::group::*;
+//! # use naughtyfy::multiplex::*;
+//! let dir = std::env::temp_dir().canonicalize().unwrap();
+//! let dir = dir.join(format!("naughtyfy-mux-{}", std::process::id()));
+//! std::fs::create_dir(&dir).unwrap();
+//!
+//! // The accesses come from a thread of this process.
+//! let builder = Fanotify::builder().nonblocking().exclude_self(false);
+//! let perm = builder.clone().class(Class::Content).build();
+//! let dirs = builder.report_dfid_name().build();
+//! match (perm, dirs) {
+//!     (Ok(perm), Ok(dirs)) => {
+//!         perm.mark(FAN_MARK_ADD, FAN_OPEN_PERM | FAN_EVENT_ON_CHILD, AT_FDCWD, dir.as_path())
+//!             .unwrap();
+//!         dirs.mark(FAN_MARK_ADD, FAN_CREATE, AT_FDCWD, dir.as_path()).unwrap();
+//!
+//!         let mut mux = Multiplexer::new().unwrap();
+//!         let perm = mux.add(perm).unwrap();
+//!         let dirs = mux.add(dirs).unwrap();
+//!
+//!         // Blocks in open() until the permission event is answered.
+//!         let file = dir.join("file");
+//!         let writer = std::thread::spawn(move || std::fs::write(file, b"").unwrap());
+//!
+//!         let (mut allowed, mut created) = (false, false);
+//!         let mut ready = Vec::new();
+//!         while !(allowed && created) {
+//!             mux.wait(&mut ready, None).unwrap();
+//!             for item in ready.drain(..) {
+//!                 match item {
+//!                     Ready::Event(source, mut event) if source == perm => {
+//!                         event.take_permission().unwrap().allow().unwrap();
+//!                         allowed = true;
+//!                     }
+//!                     Ready::Event(source, event) => {
+//!                         assert_eq!(source, dirs);
+//!                         created |= event.mask().contains(FAN_CREATE);
+//!                     }
+//!                     Ready::Fd(_) => unreachable!(),
+//!                 }
+//!             }
+//!         }
+//!         writer.join().unwrap();
+//!     }
+//!     (Err(e), _) | (_, Err(e)) => {
+//!         // This can fail for multiple reason, most common being privileges.
+//!         eprintln!("Cannot get fd due to {e}");
+//!     }
+//! }
+//! std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::{
+    cancel::poll_timeout,
+    errors::FanotifyError,
+    event::Event,
+    flags::*,
+    group::{Class, Fanotify},
+    reader::EventReader,
+};
+use std::{
+    io::Error,
+    os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd},
+    time::{Duration, Instant},
+};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::api::*;
+
+/// Most descriptors reported by a single `epoll_wait()`.
+const MAX_READY: usize = 64;
+
+/// Identifies a group or descriptor registered with a [`Multiplexer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceId(usize);
+
+/// What a [`Multiplexer`] found ready.
+#[derive(Debug)]
+pub enum Ready {
+    /// An event read from the group registered as [`SourceId`].
+    Event(SourceId, Event),
+    /// The descriptor registered with [`Multiplexer::add_fd()`]
+    /// as [`SourceId`] is readable, it is up to the caller to read it.
+    Fd(SourceId),
+}
+
+#[derive(Debug)]
+enum Source {
+    Group {
+        group: Fanotify,
+        reader: EventReader,
+    },
+    Fd(RawFd),
+}
+
+impl Source {
+    fn raw_fd(&self) -> RawFd {
+        match self {
+            Source::Group { group, .. } => group.as_raw_fd(),
+            Source::Fd(fd) => *fd,
+        }
+    }
+
+    /// Permission groups are served before everything else.
+    fn is_permission(&self) -> bool {
+        matches!(self, Source::Group { group, .. } if group.class() != Class::Notif)
+    }
+}
+
+/// Waits on several fanotify groups and other descriptors at once.
+/// See the [module documentation](crate::multiplex).
+#[derive(Debug)]
+pub struct Multiplexer {
+    epoll: OwnedFd,
+    sources: Vec<Option<Source>>,
+}
+
+impl Multiplexer {
+    /// Create a multiplexer without any source.
+    /// Returns [`FanotifyError::Init`] if the epoll instance cannot be created.
+    pub fn new() -> Result<Self, FanotifyError> {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(FanotifyError::Init(
+                Error::last_os_error().raw_os_error().unwrap_or_default(),
+            ));
+        }
+        Ok(Multiplexer {
+            epoll: unsafe { OwnedFd::from_raw_fd(fd) },
+            sources: Vec::new(),
+        })
+    }
+
+    fn register(&mut self, source: Source) -> Result<SourceId, FanotifyError> {
+        let id = self
+            .sources
+            .iter()
+            .position(Option::is_none)
+            .unwrap_or(self.sources.len());
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: id as u64,
+        };
+        let res = unsafe {
+            libc::epoll_ctl(
+                self.epoll.as_raw_fd(),
+                libc::EPOLL_CTL_ADD,
+                source.raw_fd(),
+                &mut event,
+            )
+        };
+        if res < 0 {
+            return Err(FanotifyError::Mark(
+                Error::last_os_error().raw_os_error().unwrap_or_default(),
+            ));
+        }
+        if id == self.sources.len() {
+            self.sources.push(Some(source));
+        } else {
+            self.sources[id] = Some(source);
+        }
+        Ok(SourceId(id))
+    }
+
+    /// Register `group`, its events are read with a [`Fanotify::reader()`].
+    ///
+    /// Returns [`FanotifyError::Invalid`] for a group initialized without
+    /// [`FAN_NONBLOCK`] and [`FanotifyError::Mark`] if epoll refuses it.
+    pub fn add(&mut self, group: Fanotify) -> Result<SourceId, FanotifyError> {
+        if !group.flags().contains(FAN_NONBLOCK) {
+            return Err(FanotifyError::Invalid(
+                "Multiplexed groups must be initialized with FAN_NONBLOCK".to_string(),
+            ));
+        }
+        let reader = group.reader();
+        self.register(Source::Group { group, reader })
+    }
+
+    /// Register another descriptor, reported as [`Ready::Fd`] while readable.
+    /// Eg: a [`crate::cancel::Canceller`] to stop a thread blocked in
+    /// [`Multiplexer::wait()`].
+    ///
+    /// The descriptor stays owned by the caller and must be removed
+    /// with [`Multiplexer::remove()`] before it is closed.
+    pub fn add_fd<F: AsFd + ?Sized>(&mut self, fd: &F) -> Result<SourceId, FanotifyError> {
+        self.register(Source::Fd(fd.as_fd().as_raw_fd()))
+    }
+
+    /// Deregister a source, handing back the group if it was one.
+    pub fn remove(&mut self, id: SourceId) -> Option<Fanotify> {
+        let source = self.sources.get_mut(id.0)?.take()?;
+        unsafe {
+            libc::epoll_ctl(
+                self.epoll.as_raw_fd(),
+                libc::EPOLL_CTL_DEL,
+                source.raw_fd(),
+                std::ptr::null_mut(),
+            );
+        }
+        match source {
+            Source::Group { group, .. } => Some(group),
+            Source::Fd(_) => None,
+        }
+    }
+
+    /// The group registered as `id`.
+    pub fn group(&self, id: SourceId) -> Option<&Fanotify> {
+        match self.sources.get(id.0)? {
+            Some(Source::Group { group, .. }) => Some(group),
+            _ => None,
+        }
+    }
+
+    /// Wait until a source is ready and push what it has into `ready`,
+    /// events of permission groups first. Returns the number of items added.
+    ///
+    /// Returns `Ok(0)` only when `timeout` elapsed first, `None` waits
+    /// forever. Events skipped by the group (see [`crate::exclude`]) do not
+    /// end the wait.
+    /// A wait interrupted by a signal (`EINTR`) resumes with the time left.
+    /// Read errors stop the wait, items already added stay in `ready`.
+    pub fn wait(
+        &mut self,
+        ready: &mut Vec<Ready>,
+        timeout: Option<Duration>,
+    ) -> Result<usize, FanotifyError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let added = self.wait_once(ready, deadline)?;
+            // Every event read may have been filtered out, wait for more.
+            if added > 0 || deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Ok(added);
+            }
+        }
+    }
+
+    /// One wait of [`Multiplexer::wait()`], `Ok(0)` if it timed out or
+    /// the sources that were ready had nothing left once filtered.
+    fn wait_once(
+        &mut self,
+        ready: &mut Vec<Ready>,
+        deadline: Option<Instant>,
+    ) -> Result<usize, FanotifyError> {
+        let mut events = [libc::epoll_event { events: 0, u64: 0 }; MAX_READY];
+        let count = loop {
+            let millis = match deadline {
+                Some(deadline) => poll_timeout(deadline.saturating_duration_since(Instant::now())),
+                None => -1,
+            };
+            let res = unsafe {
+                libc::epoll_wait(
+                    self.epoll.as_raw_fd(),
+                    events.as_mut_ptr(),
+                    MAX_READY as libc::c_int,
+                    millis,
+                )
+            };
+            if res >= 0 {
+                break res as usize;
+            }
+            match Error::last_os_error().raw_os_error().unwrap_or_default() {
+                libc::EINTR => continue,
+                errno => return Err(FanotifyError::Read(errno)),
+            }
+        };
+
+        let mut ids: Vec<SourceId> = events[..count]
+            .iter()
+            .map(|event| SourceId(event.u64 as usize))
+            .collect();
+        ids.sort_by_key(|id| match &self.sources[id.0] {
+            Some(source) => !source.is_permission(),
+            None => true,
+        });
+
+        let len = ready.len();
+        for id in ids {
+            let Some(source) = &mut self.sources[id.0] else {
+                continue;
+            };
+            let drain = source.is_permission();
+            match source {
+                Source::Group { group, reader } => loop {
+                    match reader.fill(&*group) {
+                        Ok(0) => break,
+                        Ok(_) => {}
+                        Err(FanotifyError::Read(libc::EINTR)) => continue,
+                        Err(FanotifyError::Read(libc::EAGAIN)) => break,
+                        Err(e) => return Err(e),
+                    }
+                    while let Some(event) = reader.next_event() {
+                        ready.push(Ready::Event(id, event?));
+                    }
+                    if !drain {
+                        break;
+                    }
+                },
+                Source::Fd(_) => ready.push(Ready::Fd(id)),
+            }
+        }
+        Ok(ready.len() - len)
+    }
+}
@@ -0,0 +1,232 @@
+//! Permission events that must be answered.
+//!
+//! The process accessing a file marked with [`FAN_OPEN_PERM`],
+//! [`FAN_ACCESS_PERM`] or [`FAN_OPEN_EXEC_PERM`] is blocked until the
+//! group writes a response, a single forgotten response hangs it.
+//!
+//! Events of such groups read with [`Fanotify::events()`] (or any reader
+//! from [`Fanotify::reader()`]) carry a [`PermissionRequest`]. It is not
+//! [`Clone`] and answering consumes it, if it is dropped unanswered the
+//! group's default response ([`FanotifyBuilder::default_response()`],
+//! [`FAN_ALLOW`] unless set) is sent and a warning is printed.
+//!
+//! # Example
+//! This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+//! ```rust
+//! # use naughtyfy::flags::*;
+//! # use naughtyfy::group::*;
+//! let dir = std::env::temp_dir().canonicalize().unwrap();
+//! let dir = dir.join(format!("naughtyfy-permission-{}", std::process::id()));
+//! std::fs::create_dir(&dir).unwrap();
+//! std::fs::write(dir.join("secret"), b"").unwrap();
+//!
+//! // The accesses come from a thread of this process.
+//! let builder = Fanotify::builder().exclude_self(false);
+//! match builder.class(Class::Content).build() {
+//!     Ok(group) => {
+//!         group
+//!             .mark(FAN_MARK_ADD, FAN_OPEN_PERM | FAN_EVENT_ON_CHILD, AT_FDCWD, dir.as_path())
+//!             .unwrap();
+//!         let secret = dir.join("secret");
+//!         let reader = std::thread::spawn(move || std::fs::read(secret));
+//!
+//!         for event in group.events() {
+//!             let mut event = event.unwrap();
+//!             let request = event.take_permission().unwrap();
+//!             if request.path().unwrap().ends_with("secret") {
+//!                 request.deny().unwrap();
+//!                 break;
+//!             }
+//!             request.allow().unwrap();
+//!         }
+//!         let err = reader.join().unwrap().unwrap_err();
+//!         assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+//!     }
+//!     Err(e) => {
+//!         // This can fail for multiple reason, most common being privileges.
+//!         eprintln!("Cannot get fd due to {e}");
+//!     }
+//! }
+//! std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::{api, errors::FanotifyError, failure::Tracker, flags::*, group, types::*};
+use std::{
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
+    path::PathBuf,
+    sync::{Arc, Weak},
+};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::{api::*, event::Event, group::*};
+
+/// What a reader needs to attach [`PermissionRequest`]s to the events of
+/// a group. The group descriptor is weak, once the group is closed the
+/// kernel allows every pending event by itself.
+#[derive(Debug, Clone)]
+pub(crate) struct Responder {
+    pub(crate) group: Weak<OwnedFd>,
+    pub(crate) flags: InitFlags,
+    pub(crate) default: Response,
+    /// Outstanding requests, answered by the failure policy of the group.
+    pub(crate) tracker: Arc<Tracker>,
+}
+
+impl Responder {
+    pub(crate) fn new(
+        group: &Arc<OwnedFd>,
+        flags: InitFlags,
+        default: Response,
+        tracker: &Arc<Tracker>,
+    ) -> Self {
+        Responder {
+            group: Arc::downgrade(group),
+            flags,
+            default,
+            tracker: tracker.clone(),
+        }
+    }
+
+    /// Token answering the event `fd` reported with `mask`.
+    pub(crate) fn request(&self, fd: OwnedFd, mask: EventMask) -> PermissionRequest {
+        self.tracker.insert(fd.as_raw_fd());
+        PermissionRequest {
+            responder: self.clone(),
+            fd: Some(fd),
+            mask,
+        }
+    }
+}
+
+/// Token for a permission event that must be answered.
+///
+/// Holds the event file descriptor until [`PermissionRequest::allow()`],
+/// [`PermissionRequest::deny()`] or [`PermissionRequest::respond()`]
+/// sends the response and consumes it. Dropped unanswered, the default
+/// response of the group is sent and a warning is printed.
+#[derive(Debug)]
+pub struct PermissionRequest {
+    responder: Responder,
+    /// `None` once answered.
+    fd: Option<OwnedFd>,
+    mask: EventMask,
+}
+
+impl PermissionRequest {
+    /// File descriptor of the object being accessed.
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        // Only taken by `send()`, which consumes the token.
+        self.fd.as_ref().unwrap().as_fd()
+    }
+
+    /// Permission events of the event, Eg: [`FAN_OPEN_PERM`].
+    #[inline]
+    pub fn mask(&self) -> EventMask {
+        self.mask
+    }
+
+    /// Path of the object being accessed, resolved through `/proc/self/fd`.
+    pub fn path(&self) -> Result<PathBuf, std::io::Error> {
+        Fd::path_from_rawfd(self.fd().as_raw_fd())
+    }
+
+    /// Flags the group was initialized with.
+    #[inline]
+    pub(crate) fn flags(&self) -> InitFlags {
+        self.responder.flags
+    }
+
+    /// Response sent if the token is dropped unanswered.
+    #[inline]
+    pub fn default_response(&self) -> Response {
+        self.responder.default
+    }
+
+    /// Let the access go through.
+    pub fn allow(self) -> Result<isize, FanotifyError> {
+        self.respond(FAN_ALLOW)
+    }
+
+    /// Make the access fail with `EPERM`.
+    pub fn deny(self) -> Result<isize, FanotifyError> {
+        self.respond(FAN_DENY)
+    }
+
+    /// Fail the access with `errno`, see [`FAN_DENY_ERRNO()`].
+    pub fn deny_errno(self, errno: i32) -> Result<isize, FanotifyError> {
+        self.respond(FAN_DENY_ERRNO(errno))
+    }
+
+    /// Send `response`, see [`Fanotify::respond()`] for the accepted values.
+    ///
+    /// An invalid `response` is returned as [`FanotifyError::Invalid`] and
+    /// the default response is sent in its place. Returns
+    /// [`FanotifyError::Write`] with `EBADF` if the group was closed, the
+    /// kernel then already allowed the access. Returns the number of bytes
+    /// written, as [`api::write()`].
+    pub fn respond(self, response: Response) -> Result<isize, FanotifyError> {
+        let fd = self.fd().as_raw_fd();
+        self.respond_with(fanotify_response::new(&fd, response))
+    }
+
+    /// Send `response` along with the audit rule that made the decision,
+    /// see [`fanotify_response::with_audit_rule()`].
+    pub fn respond_with_audit_rule(
+        self,
+        response: Response,
+        audit_rule: fanotify_response_info_audit_rule,
+    ) -> Result<isize, FanotifyError> {
+        let fd = self.fd().as_raw_fd();
+        self.respond_with(fanotify_response::new(&fd, response).with_audit_rule(audit_rule))
+    }
+
+    fn respond_with(mut self, response: fanotify_response) -> Result<isize, FanotifyError> {
+        group::check_response(self.responder.flags, &response)?;
+        self.send(&response)
+    }
+
+    fn send(&mut self, response: &fanotify_response) -> Result<isize, FanotifyError> {
+        // Closed once the kernel got the response.
+        let Some(fd) = self.fd.take() else {
+            return Ok(0);
+        };
+        if !self.responder.tracker.remove(fd.as_raw_fd()) {
+            // Answered by the failure policy of the group.
+            return Err(FanotifyError::Write(libc::ENOENT));
+        }
+        let group = self
+            .responder
+            .group
+            .upgrade()
+            .ok_or(FanotifyError::Write(libc::EBADF))?;
+        api::write(&group, response)
+    }
+}
+
+impl Drop for PermissionRequest {
+    fn drop(&mut self) {
+        let answered = match &self.fd {
+            Some(fd) => !self.responder.tracker.contains(fd.as_raw_fd()),
+            None => true,
+        };
+        if answered {
+            return;
+        }
+        // The thread answering it panicked, the policy of the group decides.
+        let default = match self.responder.tracker.policy() {
+            Some(policy) if std::thread::panicking() => policy.response(),
+            _ => self.responder.default,
+        };
+        eprintln!(
+            "Permission request {} for {:?} dropped unanswered, sending {}",
+            self.mask,
+            self.path(),
+            default
+        );
+        let response = fanotify_response::new(&self.fd().as_raw_fd(), default);
+        if let Err(e) = self.send(&response) {
+            eprintln!("{e}");
+        }
+    }
+}
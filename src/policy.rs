@@ -0,0 +1,719 @@
+//! Rule based decisions on permission events.
+//!
+//! A [`Policy`] is a list of [`Rule`]s, each matching on the path being
+//! accessed (prefix or glob), the event, and the process behind it: its
+//! executable, uid, gid and the executables of its ancestors. The matching
+//! rule decides an [`Action`], either the first one in order or the most
+//! specific one, see [`Evaluation`].
+//!
+//! Process details are read from `/proc` only when a rule needs them.
+//! A process that exited before it could be inspected matches no rule
+//! asking about it.
+//!
+//! Rules in [`Mode::DryRun`] are left out of the decision, the verdict
+//! they would have given is recorded instead, see [`Record`]. A whole
+//! policy in dry-run allows every access. An [`Enforcer`] switches modes
+//! while the policy is answering events.
+//!
+//! # Example
+//! ```rust
+//! # use naughtyfy::flags::*;
+//! # use naughtyfy::policy::*;
+//! let policy = Policy::new(Evaluation::MostSpecific, Action::Allow)
+//!     .rule(Rule::deny().path_prefix("/etc"))
+//!     .rule(Rule::allow().path_glob("/etc/*.conf").events(FAN_OPEN_PERM))
+//!     .rule(Rule::audit().path_glob("/home/**/.ssh/*"));
+//!
+//! let pid = std::process::id() as i32;
+//! let access = |path: &str| Access::new(path, FAN_OPEN_PERM, pid);
+//! assert_eq!(policy.evaluate(&access("/etc/shadow")), Action::Deny);
+//! assert_eq!(policy.evaluate(&access("/etc/resolv.conf")), Action::Allow);
+//! assert_eq!(policy.evaluate(&access("/home/user/.ssh/id_ed25519")), Action::Audit);
+//! assert_eq!(policy.evaluate(&access("/usr/bin/ls")), Action::Allow);
+//! ```
+//!
+//! With a [`crate::dispatch::PermissionDispatcher`], the policy decides
+//! on the worker threads:
+//! ```rust,no_run
+//! # use naughtyfy::dispatch::*;
+//! # use naughtyfy::group::*;
+//! # use naughtyfy::policy::*;
+//! let policy = Policy::new(Evaluation::FirstMatch, Action::Allow)
+//!     .rule(Rule::deny().path_prefix("/tmp/secret").exe_prefix("/usr/bin"));
+//! let group = Fanotify::builder().class(Class::Content).build().unwrap();
+//! let dispatcher = PermissionDispatcher::builder()
+//!     .spawn(group, move |event| policy.response(event))
+//!     .unwrap();
+//! ```
+//!
+//! # Policy files
+//! Policies can be shipped as text, read with [`Policy::load()`] or
+//! [`str::parse()`]. Each line is a directive, `#` starts a comment:
+//!
+//! * `default <action>`: action when no rule matches, required.
+//! * `evaluation first-match|most-specific`: see [`Evaluation`],
+//!   `first-match` if not given.
+//! * `mode enforce|dry-run`: see [`Mode`], `enforce` if not given.
+//! * `allow|deny|audit <key>=<value>...`: a [`Rule`], with its conditions:
+//!   `path` (prefix), `glob`, `exe`, `exe-glob`, `ancestor`,
+//!   `ancestor-glob`, `event` (names of [`EventMask`] joined by `|`),
+//!   `user` and `group` (names or ids). `mode=dry-run` only records
+//!   the action of the rule.
+//!
+//! Values holding whitespace go in double quotes, with `\"` and `\\`
+//! escapes. [`validate()`] reports the rules that can never apply.
+//! ```rust
+//! # use naughtyfy::policy::*;
+//! let policy: Policy = r#"
+//!     ## Only the package manager writes to /usr.
+//!     evaluation most-specific
+//!     default allow
+//!     deny path=/usr event=OPEN_PERM user=root
+//!     allow path=/usr exe-glob=/usr/bin/dpkg* user=0 event=OPEN_PERM
+//!     audit glob="/home/*/My Documents/**"
+//! "#
+//! .parse()
+//! .unwrap();
+//! assert_eq!(policy.rules().len(), 3);
+//! assert!(validate(&policy).is_empty());
+//!
+//! let err = "default allow\ndeny path=etc".parse::<Policy>().unwrap_err();
+//! assert!(err.to_string().contains("2:11: Expected an absolute path"));
+//! ```
+
+use crate::{errors::FanotifyError, event::Event, flags::*};
+use std::{
+    cell::OnceCell,
+    fmt,
+    os::fd::OwnedFd,
+    path::{Path, PathBuf},
+};
+
+mod enforce;
+mod format;
+pub use enforce::{Enforcer, Record};
+pub use format::{validate, Issue, IssueKind};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::{api::*, permission::PermissionRequest};
+
+/// Decision of a [`Rule`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Let the access go through.
+    Allow,
+    /// Make the access fail with `EPERM`.
+    Deny,
+    /// Let the access go through and log it with [`FAN_AUDIT`] if the
+    /// group was initialized with [`FAN_ENABLE_AUDIT`], others only let
+    /// it through.
+    Audit,
+}
+
+impl Action {
+    /// Response written to the group for this action.
+    pub fn response(self) -> Response {
+        match self {
+            Action::Allow => FAN_ALLOW,
+            Action::Deny => FAN_DENY,
+            Action::Audit => FAN_ALLOW | FAN_AUDIT,
+        }
+    }
+}
+
+/// Name used in policy files, Eg: `deny`.
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Action::Allow => "allow",
+            Action::Deny => "deny",
+            Action::Audit => "audit",
+        })
+    }
+}
+
+/// Whether the verdict of a [`Rule`] or a [`Policy`] is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Mode {
+    /// Answer with the verdict.
+    #[default]
+    Enforce,
+    /// Answer as if the rule was not there (with [`FAN_ALLOW`] for a
+    /// policy) and record the verdict if it differs, to see what a rule
+    /// would change before enforcing it. See [`Record`].
+    DryRun,
+}
+
+/// Name used in policy files, Eg: `dry-run`.
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Enforce => "enforce",
+            Mode::DryRun => "dry-run",
+        })
+    }
+}
+
+/// How a [`Policy`] picks the rule deciding on an access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Evaluation {
+    /// The first matching rule, in the order they were added.
+    #[default]
+    FirstMatch,
+    /// The matching rule with the most conditions, ties are broken by the
+    /// longest literal path (prefix or glob without wildcards), then by order.
+    MostSpecific,
+}
+
+/// Shell like pattern over a whole path.
+///
+/// `?` matches one character and `*` any number of characters other than
+/// `/`, `**` matches across `/` and `[...]` a character of the set (with
+/// ranges like `a-z`, negated by a leading `!`). Matching takes time
+/// proportional to the pattern length times the path length.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Glob {
+    pattern: String,
+}
+
+impl Glob {
+    /// Check the pattern, returns [`FanotifyError::Invalid`] for an
+    /// unterminated `[`.
+    pub fn new(pattern: &str) -> Result<Self, FanotifyError> {
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            if c == '[' && !chars.by_ref().skip(1).any(|c| c == ']') {
+                return Err(FanotifyError::Invalid(format!(
+                    "Unterminated [ in glob {pattern}"
+                )));
+            }
+        }
+        Ok(Glob {
+            pattern: pattern.to_string(),
+        })
+    }
+
+    /// The pattern as written.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Check if `path` matches the whole pattern.
+    pub fn matches<P: AsRef<Path>>(&self, path: P) -> bool {
+        let path = path.as_ref().to_string_lossy();
+        let pattern: Vec<char> = self.pattern.chars().collect();
+        let path: Vec<char> = path.chars().collect();
+        glob_match(&pattern, &path)
+    }
+
+    /// Characters before the first wildcard.
+    fn literal_len(&self) -> usize {
+        self.pattern
+            .find(['*', '?', '['])
+            .unwrap_or(self.pattern.len())
+    }
+}
+
+fn glob_match(pattern: &[char], path: &[char]) -> bool {
+    // `matched[i * width + j]` is set when `pattern[i..]` matches `path[j..]`.
+    // Filled from the ends, so each cell is computed once from cells already
+    // known and a pattern with many stars stays linear in the path length.
+    let width = path.len() + 1;
+    let mut matched = vec![false; (pattern.len() + 1) * width];
+    matched[pattern.len() * width + path.len()] = true;
+    for i in (0..pattern.len()).rev() {
+        let class = (pattern[i] == '[').then(|| class_end(pattern, i)).flatten();
+        // For `**`, whether the rest matches after skipping any characters.
+        let mut skipped = false;
+        for j in (0..=path.len()).rev() {
+            let value = {
+                let at = |i: usize, j: usize| matched[i * width + j];
+                let c = path.get(j);
+                let in_segment = c.is_some_and(|c| *c != '/');
+                match pattern[i] {
+                    '*' if pattern.get(i + 1) == Some(&'*') => {
+                        skipped |= at(i + 2, j);
+                        // `/**/` also matches a single `/`.
+                        skipped || (pattern.get(i + 2) == Some(&'/') && at(i + 3, j))
+                    }
+                    '*' => at(i + 1, j) || (in_segment && at(i, j + 1)),
+                    '?' => in_segment && at(i + 1, j + 1),
+                    '[' => match (class, c) {
+                        (Some((start, end)), Some(c)) => {
+                            in_class(&pattern[start..end], *c) != (start == i + 2)
+                                && in_segment
+                                && at(end + 1, j + 1)
+                        }
+                        _ => false,
+                    },
+                    p => c == Some(&p) && at(i + 1, j + 1),
+                }
+            };
+            matched[i * width + j] = value;
+        }
+    }
+    matched[0]
+}
+
+/// Bounds of the set of the `[` at `open`, `None` if it is not closed.
+fn class_end(pattern: &[char], open: usize) -> Option<(usize, usize)> {
+    // `]` right after `[` (or `[!`) is part of the set.
+    let start = if pattern.get(open + 1) == Some(&'!') {
+        open + 2
+    } else {
+        open + 1
+    };
+    let end = pattern.get(start + 1..)?.iter().position(|c| *c == ']')?;
+    Some((start, end + start + 1))
+}
+
+/// Check if `c` is in `set`, the inside of a `[..]`.
+fn in_class(set: &[char], c: char) -> bool {
+    let mut i = 0;
+    while i < set.len() {
+        if set.get(i + 1) == Some(&'-') && i + 2 < set.len() {
+            if (set[i]..=set[i + 2]).contains(&c) {
+                return true;
+            }
+            i += 3;
+        } else {
+            if set[i] == c {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+/// Paths matched by a [`Rule`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathMatch {
+    /// The path or anything below it, compared component wise.
+    Prefix(PathBuf),
+    /// Paths matching the [`Glob`].
+    Glob(Glob),
+}
+
+impl PathMatch {
+    /// Check if `path` is matched.
+    pub fn matches<P: AsRef<Path>>(&self, path: P) -> bool {
+        match self {
+            PathMatch::Prefix(prefix) => path.as_ref().starts_with(prefix),
+            PathMatch::Glob(glob) => glob.matches(path),
+        }
+    }
+
+    /// Length of the literal part, used by [`Evaluation::MostSpecific`].
+    fn literal_len(&self) -> usize {
+        match self {
+            PathMatch::Prefix(prefix) => prefix.as_os_str().len(),
+            PathMatch::Glob(glob) => glob.literal_len(),
+        }
+    }
+}
+
+/// A set of conditions and the [`Action`] taken when all of them hold.
+/// A rule without condition matches every access.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rule {
+    action: Action,
+    path: Option<PathMatch>,
+    events: Option<EventMask>,
+    exe: Option<PathMatch>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    ancestor: Option<PathMatch>,
+    mode: Mode,
+    /// Line of the policy file the rule comes from.
+    line: Option<usize>,
+}
+
+impl Rule {
+    /// Rule taking `action`, without condition.
+    pub fn new(action: Action) -> Self {
+        Rule {
+            action,
+            path: None,
+            events: None,
+            exe: None,
+            uid: None,
+            gid: None,
+            ancestor: None,
+            mode: Mode::Enforce,
+            line: None,
+        }
+    }
+
+    /// Rule allowing the access.
+    pub fn allow() -> Self {
+        Rule::new(Action::Allow)
+    }
+
+    /// Rule denying the access.
+    pub fn deny() -> Self {
+        Rule::new(Action::Deny)
+    }
+
+    /// Rule allowing and auditing the access.
+    pub fn audit() -> Self {
+        Rule::new(Action::Audit)
+    }
+
+    /// Action taken by the rule.
+    #[inline]
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    /// Whether the action of the rule is applied, see [`Rule::dry_run()`].
+    #[inline]
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Only record the action of the rule, the access is decided by the
+    /// enforced rules, see [`Mode::DryRun`].
+    pub fn dry_run(mut self) -> Self {
+        self.mode = Mode::DryRun;
+        self
+    }
+
+    /// Line of the policy file the rule was read from, see [`Policy::load()`].
+    #[inline]
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    /// Match the accessed path.
+    pub fn path(mut self, path: PathMatch) -> Self {
+        self.path = Some(path);
+        self
+    }
+
+    /// Match accesses to `prefix` or anything below it.
+    pub fn path_prefix<P: Into<PathBuf>>(self, prefix: P) -> Self {
+        self.path(PathMatch::Prefix(prefix.into()))
+    }
+
+    /// Match accessed paths against a [`Glob`].
+    ///
+    /// # Panics
+    /// If the pattern is invalid, see [`Glob::new()`].
+    pub fn path_glob(self, pattern: &str) -> Self {
+        self.path(PathMatch::Glob(Glob::new(pattern).unwrap()))
+    }
+
+    /// Match events reported with any of `events`, Eg: [`FAN_OPEN_EXEC_PERM`].
+    pub fn events(mut self, events: EventMask) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Match the executable of the accessing process.
+    pub fn exe(mut self, exe: PathMatch) -> Self {
+        self.exe = Some(exe);
+        self
+    }
+
+    /// Match processes running an executable under `prefix`.
+    pub fn exe_prefix<P: Into<PathBuf>>(self, prefix: P) -> Self {
+        self.exe(PathMatch::Prefix(prefix.into()))
+    }
+
+    /// Match the effective user id of the accessing process.
+    pub fn uid(mut self, uid: u32) -> Self {
+        self.uid = Some(uid);
+        self
+    }
+
+    /// Match the effective group id of the accessing process.
+    pub fn gid(mut self, gid: u32) -> Self {
+        self.gid = Some(gid);
+        self
+    }
+
+    /// Match processes with an ancestor (parent, grandparent, ...)
+    /// running a matching executable, Eg: anything started by a shell.
+    pub fn ancestor(mut self, exe: PathMatch) -> Self {
+        self.ancestor = Some(exe);
+        self
+    }
+
+    /// Number of conditions, then length of the literal path.
+    fn specificity(&self) -> (usize, usize) {
+        let conditions = [
+            self.path.is_some(),
+            self.events.is_some(),
+            self.exe.is_some(),
+            self.uid.is_some(),
+            self.gid.is_some(),
+            self.ancestor.is_some(),
+        ];
+        let literal = self.path.as_ref().map_or(0, PathMatch::literal_len);
+        (conditions.iter().filter(|set| **set).count(), literal)
+    }
+
+    /// Check if every condition holds for `access`.
+    pub fn matches(&self, access: &Access) -> bool {
+        if let Some(path) = &self.path {
+            if !path.matches(&access.path) {
+                return false;
+            }
+        }
+        if let Some(events) = self.events {
+            if !access.mask.intersects(events) {
+                return false;
+            }
+        }
+        if let Some(exe) = &self.exe {
+            if !access.exe().is_some_and(|path| exe.matches(path)) {
+                return false;
+            }
+        }
+        if let Some(uid) = self.uid {
+            if access.ids().map(|ids| ids.0) != Some(uid) {
+                return false;
+            }
+        }
+        if let Some(gid) = self.gid {
+            if access.ids().map(|ids| ids.1) != Some(gid) {
+                return false;
+            }
+        }
+        if let Some(ancestor) = &self.ancestor {
+            if !access.ancestors().iter().any(|path| ancestor.matches(path)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// An access to decide on: the path, the event and the process behind it.
+///
+/// Details of the process are read from `/proc/<pid>` on first use
+/// and kept for the other rules.
+#[derive(Debug)]
+pub struct Access {
+    path: PathBuf,
+    mask: EventMask,
+    pid: i32,
+    exe: OnceCell<Option<PathBuf>>,
+    ids: OnceCell<Option<(u32, u32)>>,
+    ancestors: OnceCell<Vec<PathBuf>>,
+}
+
+impl Access {
+    /// Access to `path` reported with `mask` for process `pid`.
+    pub fn new<P: Into<PathBuf>>(path: P, mask: EventMask, pid: i32) -> Self {
+        Access {
+            path: path.into(),
+            mask,
+            pid,
+            exe: OnceCell::new(),
+            ids: OnceCell::new(),
+            ancestors: OnceCell::new(),
+        }
+    }
+
+    /// Access reported by `event`, the path is empty if it cannot be resolved.
+    pub fn from_event(event: &Event) -> Self {
+        Access::new(event.path().unwrap_or_default(), event.mask(), event.pid())
+    }
+
+    /// Path being accessed.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Events reported for the access.
+    #[inline]
+    pub fn mask(&self) -> EventMask {
+        self.mask
+    }
+
+    /// Process (or thread with [`FAN_REPORT_TID`]) accessing the path.
+    #[inline]
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// Executable of the process.
+    pub fn exe(&self) -> Option<&Path> {
+        self.exe.get_or_init(|| exe_of(self.pid)).as_deref()
+    }
+
+    /// Effective user and group id of the process.
+    pub fn ids(&self) -> Option<(u32, u32)> {
+        *self.ids.get_or_init(|| {
+            let status = std::fs::read_to_string(format!("/proc/{}/status", self.pid)).ok()?;
+            let effective = |key: &str| {
+                status
+                    .lines()
+                    .find_map(|line| line.strip_prefix(key))?
+                    .split_whitespace()
+                    .nth(1)?
+                    .parse()
+                    .ok()
+            };
+            Some((effective("Uid:")?, effective("Gid:")?))
+        })
+    }
+
+    /// Executables of the ancestors of the process, parent first.
+    pub fn ancestors(&self) -> &[PathBuf] {
+        self.ancestors.get_or_init(|| {
+            let mut ancestors = Vec::new();
+            let mut pid = self.pid;
+            while let Some(parent) = parent_of(pid) {
+                if parent <= 0 {
+                    break;
+                }
+                if let Some(exe) = exe_of(parent) {
+                    ancestors.push(exe);
+                }
+                pid = parent;
+            }
+            ancestors
+        })
+    }
+}
+
+fn exe_of(pid: i32) -> Option<PathBuf> {
+    std::fs::read_link(format!("/proc/{pid}/exe")).ok()
+}
+
+fn parent_of(pid: i32) -> Option<i32> {
+    let status = std::fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("PPid:"))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Ordered [`Rule`]s deciding on permission events.
+/// See the [module documentation](crate::policy).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Policy {
+    rules: Vec<Rule>,
+    evaluation: Evaluation,
+    default: Action,
+    mode: Mode,
+}
+
+impl Policy {
+    /// Empty policy evaluated with `evaluation`, taking `default`
+    /// when no rule matches.
+    pub fn new(evaluation: Evaluation, default: Action) -> Self {
+        Policy {
+            rules: Vec::new(),
+            evaluation,
+            default,
+            mode: Mode::Enforce,
+        }
+    }
+
+    /// Only record the verdicts and allow every access, whatever the
+    /// mode of the rules. See [`Mode::DryRun`].
+    pub fn dry_run(mut self) -> Self {
+        self.mode = Mode::DryRun;
+        self
+    }
+
+    /// Add `rule` after the others.
+    pub fn rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Add `rule` after the others.
+    pub fn push(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+
+    /// Rules in order.
+    #[inline]
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// How the deciding rule is picked.
+    #[inline]
+    pub fn evaluation(&self) -> Evaluation {
+        self.evaluation
+    }
+
+    /// Action taken when no rule matches.
+    #[inline]
+    pub fn default_action(&self) -> Action {
+        self.default
+    }
+
+    /// Whether the verdicts are applied, see [`Policy::dry_run()`].
+    #[inline]
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The rule deciding on `access`, `None` if none matches.
+    /// Modes are not taken into account.
+    pub fn matching(&self, access: &Access) -> Option<&Rule> {
+        self.matching_index(access, |_| true)
+            .map(|index| &self.rules[index])
+    }
+
+    /// Index of the rule deciding on `access` among the rules `kept`.
+    fn matching_index(&self, access: &Access, kept: impl Fn(usize) -> bool) -> Option<usize> {
+        let mut matching =
+            (0..self.rules.len()).filter(|i| kept(*i) && self.rules[*i].matches(access));
+        match self.evaluation {
+            Evaluation::FirstMatch => matching.next(),
+            // `max_by_key` keeps the last of equal keys, so reverse first.
+            Evaluation::MostSpecific => matching.rev().max_by_key(|i| self.rules[*i].specificity()),
+        }
+    }
+
+    /// Mode of the decision taken by `rule`, `None` for the default action.
+    fn mode_of(&self, rule: Option<usize>) -> Mode {
+        enforce::effective(self.mode, rule.map(|index| self.rules[index].mode))
+    }
+
+    /// Decide on `access` with the modes of the policy, without answering.
+    pub fn decide(&self, access: &Access) -> Record {
+        Record::new(self, access, |rule| self.mode_of(rule))
+    }
+
+    /// Action taken for `access`.
+    pub fn evaluate(&self, access: &Access) -> Action {
+        self.matching(access).map_or(self.default, Rule::action)
+    }
+
+    /// Response for the access reported by `event`,
+    /// Eg: to decide in a [`crate::dispatch::PermissionDispatcher`].
+    /// Dry-run decisions are printed to stderr.
+    pub fn response(&self, event: &Event) -> Response {
+        let record = self.decide(&Access::from_event(event));
+        enforce::print(&record);
+        enforce::for_event(event, record.response())
+    }
+
+    /// Decide on `event` and answer its [`PermissionRequest`], dry-run
+    /// decisions are printed to stderr. [`FanotifyError::Invalid`] if the
+    /// event has no request (not a permission event, or already taken).
+    pub fn respond(&self, event: &mut Event) -> Result<Record, FanotifyError> {
+        enforce::respond(event, |access| self.decide(access), enforce::print)
+    }
+
+    /// Decide on `event`, read with the free functions of [`crate::api`],
+    /// and answer it on `group` with [`write()`], dry-run decisions are
+    /// printed to stderr. [`FanotifyError::Invalid`] if `event` is not a
+    /// permission event.
+    pub fn answer(&self, group: &OwnedFd, event: &Event) -> Result<Record, FanotifyError> {
+        enforce::answer(group, event, |access| self.decide(access), enforce::print)
+    }
+}
@@ -0,0 +1,420 @@
+//! Dry-run decisions and switching modes at runtime.
+
+use super::{Access, Action, Mode, Policy};
+use crate::{
+    api,
+    errors::FanotifyError,
+    event::Event,
+    flags::*,
+    group::{fdinfo_flags, without_audit, PERM_EVENTS},
+    types::*,
+};
+use std::{
+    fmt,
+    os::fd::OwnedFd,
+    path::{Path, PathBuf},
+    sync::atomic::{self, AtomicBool},
+};
+
+/// Decision on an access: the verdict of the policy, the rule it comes
+/// from and whether it was applied.
+///
+/// Dry-run rules take part in the verdict but not in the action applied,
+/// which comes from the enforced rules only:
+/// ```rust
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::policy::*;
+/// let policy = Policy::new(Evaluation::FirstMatch, Action::Allow)
+///     .rule(Rule::allow().path_prefix("/etc").dry_run())
+///     .rule(Rule::deny().path_prefix("/etc/shadow"));
+///
+/// let access = Access::new("/etc/shadow", FAN_OPEN_PERM, std::process::id() as i32);
+/// let record = policy.decide(&access);
+/// assert_eq!((record.action(), record.mode(), record.rule()), (Action::Allow, Mode::DryRun, Some(0)));
+/// assert_eq!(record.applied(), Action::Deny);
+/// assert_eq!(record.response(), FAN_DENY);
+///
+/// // Same with a more specific dry-run rule.
+/// let policy = Policy::new(Evaluation::MostSpecific, Action::Allow)
+///     .rule(Rule::deny().path_prefix("/etc/shadow"))
+///     .rule(Rule::allow().path_prefix("/etc").events(FAN_OPEN_PERM).dry_run());
+/// assert_eq!(policy.decide(&access).rule(), Some(1));
+/// assert_eq!(policy.decide(&access).response(), FAN_DENY);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    action: Action,
+    mode: Mode,
+    applied: Action,
+    rule: Option<usize>,
+    line: Option<usize>,
+    path: PathBuf,
+    mask: EventMask,
+    pid: i32,
+    exe: Option<PathBuf>,
+}
+
+impl Record {
+    pub(super) fn new(
+        policy: &Policy,
+        access: &Access,
+        mode: impl Fn(Option<usize>) -> Mode,
+    ) -> Self {
+        let rule = policy.matching_index(access, |_| true);
+        let action = rule.map_or(policy.default, |index| policy.rules[index].action);
+        let applied = match mode(rule) {
+            Mode::Enforce => action,
+            Mode::DryRun => {
+                match policy.matching_index(access, |index| mode(Some(index)) == Mode::Enforce) {
+                    Some(index) => policy.rules[index].action,
+                    None if mode(None) == Mode::Enforce => policy.default,
+                    // The whole policy is in dry-run.
+                    None => Action::Allow,
+                }
+            }
+        };
+        Record {
+            action,
+            mode: mode(rule),
+            applied,
+            rule,
+            line: rule.and_then(|index| policy.rules[index].line),
+            path: access.path().to_path_buf(),
+            mask: access.mask(),
+            pid: access.pid(),
+            exe: access.exe().map(Path::to_path_buf),
+        }
+    }
+
+    /// Verdict of the policy, applied or not.
+    #[inline]
+    pub fn action(&self) -> Action {
+        self.action
+    }
+
+    /// [`Mode::DryRun`] if the verdict comes from a dry-run rule or policy.
+    #[inline]
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Action applied to the access, the verdict of the enforced rules
+    /// (or the default action) in dry-run, [`Action::Allow`] if the whole
+    /// policy is in dry-run.
+    #[inline]
+    pub fn applied(&self) -> Action {
+        self.applied
+    }
+
+    /// Index of the matching rule in [`Policy::rules()`],
+    /// `None` for the default action.
+    #[inline]
+    pub fn rule(&self) -> Option<usize> {
+        self.rule
+    }
+
+    /// Line of the policy file holding the matching rule.
+    #[inline]
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    /// Path being accessed.
+    #[inline]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Events reported for the access.
+    #[inline]
+    pub fn mask(&self) -> EventMask {
+        self.mask
+    }
+
+    /// Process accessing the path.
+    #[inline]
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// Executable of the process, if it could be read.
+    #[inline]
+    pub fn exe(&self) -> Option<&Path> {
+        self.exe.as_deref()
+    }
+
+    /// Response written for the access, the one of [`Record::applied()`].
+    pub fn response(&self) -> Response {
+        self.applied.response()
+    }
+
+    /// Check if the verdict differs from the action applied.
+    fn differs(&self) -> bool {
+        self.action != self.applied
+    }
+}
+
+/// Eg: `dry-run deny OPEN_PERM "/etc/shadow" by 42 "/usr/bin/cat" (rule on line 3)`.
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {:?} by {} {:?} (",
+            self.mode,
+            self.action,
+            self.mask,
+            self.path,
+            self.pid,
+            self.exe.as_deref().unwrap_or(Path::new("?"))
+        )?;
+        match (self.line, self.rule) {
+            (Some(line), _) => write!(f, "rule on line {line})"),
+            (None, Some(rule)) => write!(f, "rule {})", rule + 1),
+            (None, None) => f.write_str("default)"),
+        }
+    }
+}
+
+/// Mode of a decision taken by a rule in `rule` mode (`None` for the
+/// default action) of a policy in `policy` mode.
+pub(super) fn effective(policy: Mode, rule: Option<Mode>) -> Mode {
+    match (policy, rule) {
+        (Mode::DryRun, _) | (_, Some(Mode::DryRun)) => Mode::DryRun,
+        _ => Mode::Enforce,
+    }
+}
+
+/// Default recorder, prints the dry-run decisions to stderr.
+pub(super) fn print(record: &Record) {
+    if record.differs() {
+        eprintln!("{record}");
+    }
+}
+
+/// `response` for `event`, without [`FAN_AUDIT`] if the group of its
+/// request cannot send it.
+pub(super) fn for_event(event: &Event, response: Response) -> Response {
+    match event.permission() {
+        Some(request) => without_audit(request.flags(), response),
+        None => response,
+    }
+}
+
+/// Decide on `event` and answer its [`crate::permission::PermissionRequest`].
+pub(super) fn respond(
+    event: &mut Event,
+    decide: impl FnOnce(&Access) -> Record,
+    record: impl FnOnce(&Record),
+) -> Result<Record, FanotifyError> {
+    let access = Access::from_event(event);
+    let request = event.take_permission().ok_or_else(|| {
+        FanotifyError::Invalid("Event has no permission request to answer".to_string())
+    })?;
+    let decision = decide(&access);
+    record(&decision);
+    let response = without_audit(request.flags(), decision.response());
+    request.respond(response)?;
+    Ok(decision)
+}
+
+/// Decide on `event` and answer it on `group` with [`api::write()`].
+pub(super) fn answer(
+    group: &OwnedFd,
+    event: &Event,
+    decide: impl FnOnce(&Access) -> Record,
+    record: impl FnOnce(&Record),
+) -> Result<Record, FanotifyError> {
+    let fd = match event.fd() {
+        Some(fd) if event.mask().intersects(PERM_EVENTS) => fd,
+        _ => {
+            return Err(FanotifyError::Invalid(
+                "Event is not a permission event to answer".to_string(),
+            ))
+        }
+    };
+    let decision = decide(&Access::from_event(event));
+    record(&decision);
+    let mut response = decision.response();
+    if response.contains(FAN_AUDIT) {
+        if let Some((flags, _)) = fdinfo_flags(group) {
+            response = without_audit(flags, response);
+        }
+    }
+    api::write(group, &fanotify_response::new(&fd, response))?;
+    Ok(decision)
+}
+
+type Recorder = Box<dyn Fn(&Record) + Send + Sync>;
+
+/// A [`Policy`] whose mode, and the mode of each of its rules, can be
+/// switched while it answers events, Eg: to enforce a rule once its
+/// dry-run records look right, without recreating the group.
+///
+/// Modes start as set in the policy. Decisions whose verdict differs
+/// from the action applied, because of [`Mode::DryRun`], are passed to
+/// a recorder, printing them to stderr unless set with [`Enforcer::on_record()`].
+///
+/// # Example
+/// ```rust
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::policy::*;
+/// # use std::sync::{Arc, Mutex};
+/// let policy = Policy::new(Evaluation::FirstMatch, Action::Allow)
+///     .rule(Rule::deny().path_prefix("/etc/shadow").dry_run());
+/// let records = Arc::new(Mutex::new(Vec::new()));
+/// let log = records.clone();
+/// let enforcer = Enforcer::new(policy).on_record(move |record| {
+///     log.lock().unwrap().push(record.clone());
+/// });
+///
+/// let access = Access::new("/etc/shadow", FAN_OPEN_PERM, std::process::id() as i32);
+/// let record = enforcer.decide(&access);
+/// assert_eq!((record.action(), record.response()), (Action::Deny, FAN_ALLOW));
+/// assert_eq!(records.lock().unwrap().len(), 1);
+///
+/// // Looks right, enforce it.
+/// enforcer.set_rule_mode(0, Mode::Enforce).unwrap();
+/// assert_eq!(enforcer.decide(&access).response(), FAN_DENY);
+///
+/// // Back to dry-run for every rule.
+/// enforcer.set_mode(Mode::DryRun);
+/// assert_eq!(enforcer.decide(&access).response(), FAN_ALLOW);
+/// ```
+///
+/// Shared behind an [`std::sync::Arc`], it decides in a
+/// [`crate::dispatch::PermissionDispatcher`] while modes are switched:
+/// ```rust,no_run
+/// # use naughtyfy::dispatch::*;
+/// # use naughtyfy::group::*;
+/// # use naughtyfy::policy::*;
+/// # use std::sync::Arc;
+/// let enforcer = Arc::new(Enforcer::new(Policy::load("/etc/naughtyfy.policy").unwrap()));
+/// let decider = enforcer.clone();
+/// let group = Fanotify::builder().class(Class::Content).build().unwrap();
+/// let dispatcher = PermissionDispatcher::builder()
+///     .spawn(group, move |event| decider.response(event))
+///     .unwrap();
+/// enforcer.set_mode(Mode::Enforce);
+/// ```
+pub struct Enforcer {
+    policy: Policy,
+    dry_run: AtomicBool,
+    /// Per rule, in order.
+    rules: Vec<AtomicBool>,
+    recorder: Recorder,
+}
+
+impl Enforcer {
+    /// Decide with `policy`, starting in its modes.
+    pub fn new(policy: Policy) -> Self {
+        Enforcer {
+            dry_run: AtomicBool::new(policy.mode == Mode::DryRun),
+            rules: policy
+                .rules
+                .iter()
+                .map(|rule| AtomicBool::new(rule.mode == Mode::DryRun))
+                .collect(),
+            policy,
+            recorder: Box::new(print),
+        }
+    }
+
+    /// Pass the dry-run decisions to `recorder` instead of stderr.
+    pub fn on_record<F>(mut self, recorder: F) -> Self
+    where
+        F: Fn(&Record) + Send + Sync + 'static,
+    {
+        self.recorder = Box::new(move |record: &Record| {
+            if record.differs() {
+                recorder(record);
+            }
+        });
+        self
+    }
+
+    /// The policy deciding.
+    #[inline]
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Current mode of the whole policy.
+    pub fn mode(&self) -> Mode {
+        to_mode(&self.dry_run)
+    }
+
+    /// Switch the whole policy, [`Mode::DryRun`] allows every access
+    /// whatever the mode of the rules.
+    pub fn set_mode(&self, mode: Mode) {
+        self.dry_run
+            .store(mode == Mode::DryRun, atomic::Ordering::Relaxed);
+    }
+
+    /// Current mode of the rule at `index` in [`Policy::rules()`].
+    pub fn rule_mode(&self, index: usize) -> Option<Mode> {
+        self.rules.get(index).map(to_mode)
+    }
+
+    /// Switch the rule at `index` in [`Policy::rules()`],
+    /// [`FanotifyError::Invalid`] if there is no such rule.
+    pub fn set_rule_mode(&self, index: usize, mode: Mode) -> Result<(), FanotifyError> {
+        let rule = self.rules.get(index).ok_or_else(|| {
+            FanotifyError::Invalid(format!(
+                "No rule {index}, the policy has {} rules",
+                self.rules.len()
+            ))
+        })?;
+        rule.store(mode == Mode::DryRun, atomic::Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Decide on `access` with the current modes, and record it
+    /// if the verdict was not applied.
+    pub fn decide(&self, access: &Access) -> Record {
+        let record = Record::new(&self.policy, access, |rule| {
+            effective(self.mode(), rule.and_then(|index| self.rule_mode(index)))
+        });
+        (self.recorder)(&record);
+        record
+    }
+
+    /// Response for the access reported by `event`,
+    /// Eg: to decide in a [`crate::dispatch::PermissionDispatcher`].
+    pub fn response(&self, event: &Event) -> Response {
+        let record = self.decide(&Access::from_event(event));
+        for_event(event, record.response())
+    }
+
+    /// Decide on `event` and answer its [`crate::permission::PermissionRequest`],
+    /// see [`Policy::respond()`].
+    pub fn respond(&self, event: &mut Event) -> Result<Record, FanotifyError> {
+        respond(event, |access| self.decide(access), |_| ())
+    }
+
+    /// Decide on `event` and answer it on `group`, see [`Policy::answer()`].
+    pub fn answer(&self, group: &OwnedFd, event: &Event) -> Result<Record, FanotifyError> {
+        answer(group, event, |access| self.decide(access), |_| ())
+    }
+}
+
+fn to_mode(dry_run: &AtomicBool) -> Mode {
+    match dry_run.load(atomic::Ordering::Relaxed) {
+        true => Mode::DryRun,
+        false => Mode::Enforce,
+    }
+}
+
+impl fmt::Debug for Enforcer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Enforcer")
+            .field("policy", &self.policy)
+            .field("mode", &self.mode())
+            .field(
+                "rules",
+                &(0..self.rules.len())
+                    .map(|index| self.rule_mode(index))
+                    .collect::<Vec<_>>(),
+            )
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,446 @@
+//! Text format of policies and their validation.
+
+use super::{Action, Evaluation, Glob, Mode, PathMatch, Policy, Rule};
+use crate::{errors::FanotifyError, flags::EventMask};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Error at a position of a policy file, columns count characters.
+struct Error {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl Error {
+    fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Error {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+}
+
+/// Word of a line, unquoted.
+struct Word {
+    text: String,
+    column: usize,
+}
+
+/// Split `line` in words, `"..."` may hold whitespace and `#` outside
+/// quotes starts a comment.
+fn words(number: usize, line: &str) -> Result<Vec<Word>, Error> {
+    let mut words = Vec::new();
+    let mut chars = line.chars().zip(1..).peekable();
+    while let Some(&(c, column)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '#' {
+            break;
+        }
+        let mut text = String::new();
+        while let Some((c, at)) = chars.next_if(|(c, _)| !c.is_whitespace()) {
+            if c != '"' {
+                text.push(c);
+                continue;
+            }
+            loop {
+                match chars.next() {
+                    Some(('"', _)) => break,
+                    Some(('\\', escape)) => match chars.next() {
+                        Some((c @ ('"' | '\\'), _)) => text.push(c),
+                        _ => return Err(Error::new(number, escape, "Unknown escape")),
+                    },
+                    Some((c, _)) => text.push(c),
+                    None => return Err(Error::new(number, at, "Unterminated quote")),
+                }
+            }
+        }
+        words.push(Word { text, column });
+    }
+    Ok(words)
+}
+
+fn action(word: &Word) -> Option<Action> {
+    [Action::Allow, Action::Deny, Action::Audit]
+        .into_iter()
+        .find(|action| action.to_string() == word.text)
+}
+
+fn mode(number: usize, column: usize, value: &str) -> Result<Mode, Error> {
+    [Mode::Enforce, Mode::DryRun]
+        .into_iter()
+        .find(|mode| mode.to_string() == value)
+        .ok_or_else(|| Error::new(number, column, "Expected enforce or dry-run"))
+}
+
+/// Id of `name` in the `/etc/passwd` like file `db`, or `name` as a number.
+fn lookup(db: &str, name: &str) -> Option<u32> {
+    if let Ok(id) = name.parse() {
+        return Some(id);
+    }
+    std::fs::read_to_string(db)
+        .ok()?
+        .lines()
+        .map(|entry| entry.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.first() == Some(&name))?
+        .get(2)?
+        .parse()
+        .ok()
+}
+
+fn path_match(glob: bool, value: &str) -> Result<PathMatch, String> {
+    if !value.starts_with('/') {
+        return Err(format!("Expected an absolute path, found {value:?}"));
+    }
+    match glob {
+        true => match Glob::new(value) {
+            Ok(glob) => Ok(PathMatch::Glob(glob)),
+            Err(_) => Err(format!("Unterminated [ in glob {value:?}")),
+        },
+        false => Ok(PathMatch::Prefix(PathBuf::from(value))),
+    }
+}
+
+/// Apply the `key=value` condition of `word` to `rule`.
+fn condition(rule: &mut Rule, number: usize, word: &Word) -> Result<(), Error> {
+    let Some((key, value)) = word.text.split_once('=') else {
+        return Err(Error::new(
+            number,
+            word.column,
+            format!("Expected a key=value condition, found {:?}", word.text),
+        ));
+    };
+    let column = word.column + key.chars().count() + 1;
+    let error = |message: String| Error::new(number, column, message);
+    let set = match key {
+        "path" | "glob" | "exe" | "exe-glob" | "ancestor" | "ancestor-glob" => {
+            let path = path_match(key.ends_with("glob"), value).map_err(error)?;
+            let field = match key {
+                "path" | "glob" => &mut rule.path,
+                "exe" | "exe-glob" => &mut rule.exe,
+                _ => &mut rule.ancestor,
+            };
+            field.replace(path).is_some()
+        }
+        "event" => {
+            let events = EventMask::from_str(value).map_err(|e| match e {
+                FanotifyError::Parse(message) => error(message),
+                e => error(e.to_string()),
+            })?;
+            if events.is_empty() {
+                return Err(error("Expected at least one event".to_string()));
+            }
+            rule.events.replace(events).is_some()
+        }
+        "user" | "group" => {
+            let (db, field) = match key {
+                "user" => ("/etc/passwd", &mut rule.uid),
+                _ => ("/etc/group", &mut rule.gid),
+            };
+            let id = lookup(db, value).ok_or_else(|| error(format!("Unknown {key} {value:?}")))?;
+            field.replace(id).is_some()
+        }
+        _ => {
+            return Err(Error::new(
+                number,
+                word.column,
+                format!("Unknown condition {key:?}"),
+            ))
+        }
+    };
+    if set {
+        return Err(Error::new(
+            number,
+            word.column,
+            format!(
+                "Condition on the {} given twice",
+                match key {
+                    "glob" => "path",
+                    key => key.trim_end_matches("-glob"),
+                }
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn parse(source: &str) -> Result<Policy, Error> {
+    let mut policy = Policy::new(Evaluation::default(), Action::Deny);
+    let (mut default, mut evaluation, mut given_mode) = (None, None, None);
+    for (line, number) in source.lines().zip(1..) {
+        let words = words(number, line)?;
+        let Some(first) = words.first() else {
+            continue;
+        };
+        let arguments = &words[1..];
+        if let Some(action) = action(first) {
+            let mut rule = Rule::new(action);
+            rule.line = Some(number);
+            for (i, word) in arguments.iter().enumerate() {
+                let Some(value) = word.text.strip_prefix("mode=") else {
+                    condition(&mut rule, number, word)?;
+                    continue;
+                };
+                if arguments[..i].iter().any(|w| w.text.starts_with("mode=")) {
+                    return Err(Error::new(number, word.column, "Mode given twice"));
+                }
+                rule.mode = mode(number, word.column + 5, value)?;
+            }
+            policy.push(rule);
+            continue;
+        }
+        if !matches!(first.text.as_str(), "default" | "evaluation" | "mode") {
+            return Err(Error::new(
+                number,
+                first.column,
+                format!(
+                    "Expected allow, deny, audit, default, evaluation or mode, found {:?}",
+                    first.text
+                ),
+            ));
+        }
+        let value = match arguments {
+            [value] => value,
+            _ => {
+                let column = arguments
+                    .get(1)
+                    .map_or(line.chars().count() + 1, |w| w.column);
+                return Err(Error::new(
+                    number,
+                    column,
+                    format!("Expected one value after {}", first.text),
+                ));
+            }
+        };
+        let previous = match first.text.as_str() {
+            "default" => {
+                policy.default = action(value).ok_or_else(|| {
+                    Error::new(number, value.column, "Expected allow, deny or audit")
+                })?;
+                default.replace(number)
+            }
+            "mode" => {
+                policy.mode = mode(number, value.column, &value.text)?;
+                given_mode.replace(number)
+            }
+            _ => {
+                policy.evaluation = match value.text.as_str() {
+                    "first-match" => Evaluation::FirstMatch,
+                    "most-specific" => Evaluation::MostSpecific,
+                    _ => {
+                        return Err(Error::new(
+                            number,
+                            value.column,
+                            "Expected first-match or most-specific",
+                        ))
+                    }
+                };
+                evaluation.replace(number)
+            }
+        };
+        if let Some(previous) = previous {
+            return Err(Error::new(
+                number,
+                first.column,
+                format!("{} already given on line {previous}", first.text),
+            ));
+        }
+    }
+    if default.is_none() {
+        return Err(Error::new(
+            source.lines().count().max(1),
+            1,
+            "Missing default verdict",
+        ));
+    }
+    Ok(policy)
+}
+
+/// Parses the policy file format, see the
+/// [module documentation](crate::policy#policy-files). Errors are
+/// [`FanotifyError::Parse`] starting with `line:column:`.
+impl FromStr for Policy {
+    type Err = FanotifyError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        parse(source)
+            .map_err(|e| FanotifyError::Parse(format!("{}:{}: {}", e.line, e.column, e.message)))
+    }
+}
+
+impl Policy {
+    /// Read the policy file at `path`, errors are [`FanotifyError::Parse`]
+    /// starting with `path:line:column:`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, FanotifyError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| FanotifyError::Parse(format!("Cannot read {}: {e}", path.display())))?;
+        parse(&source).map_err(|e| {
+            FanotifyError::Parse(format!(
+                "{}:{}:{}: {}",
+                path.display(),
+                e.line,
+                e.column,
+                e.message
+            ))
+        })
+    }
+}
+
+/// Kind of an [`Issue`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IssueKind {
+    /// The rule never decides, an other rule taking the same action
+    /// decides on every access it matches.
+    Unreachable,
+    /// The rule never decides, an other rule taking a different action
+    /// decides on every access it matches.
+    Conflict,
+}
+
+/// Rule of a policy that never decides, found by [`validate()`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Issue {
+    kind: IssueKind,
+    rule: usize,
+    by: usize,
+    message: String,
+}
+
+impl Issue {
+    /// What is wrong with the rule.
+    #[inline]
+    pub fn kind(&self) -> IssueKind {
+        self.kind
+    }
+
+    /// Index of the rule that never decides, in [`Policy::rules()`].
+    #[inline]
+    pub fn rule(&self) -> usize {
+        self.rule
+    }
+
+    /// Index of the rule deciding instead.
+    #[inline]
+    pub fn by(&self) -> usize {
+        self.by
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Check if every path matched by `inner` is matched by `outer`. Globs are
+/// only compared through their literal directory, so this may miss cases.
+fn path_covers(outer: &Option<PathMatch>, inner: &Option<PathMatch>) -> bool {
+    match (outer, inner) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(PathMatch::Prefix(outer)), Some(PathMatch::Prefix(inner))) => {
+            inner.starts_with(outer)
+        }
+        (Some(PathMatch::Prefix(outer)), Some(PathMatch::Glob(inner))) => {
+            let literal = &inner.as_str()[..inner.literal_len()];
+            let dir = &literal[..literal.rfind('/').map_or(0, |i| i + 1)];
+            Path::new(dir).starts_with(outer)
+        }
+        (Some(outer), Some(inner)) => outer == inner,
+    }
+}
+
+/// Check if `outer` matches every access matched by `inner`.
+fn covers(outer: &Rule, inner: &Rule) -> bool {
+    fn same<T: PartialEq>(outer: &Option<T>, inner: &Option<T>) -> bool {
+        outer.is_none() || outer == inner
+    }
+    let events = match (outer.events, inner.events) {
+        (None, _) => true,
+        (Some(_), None) => false,
+        (Some(outer), Some(inner)) => outer.contains(inner),
+    };
+    events
+        && path_covers(&outer.path, &inner.path)
+        && path_covers(&outer.exe, &inner.exe)
+        && path_covers(&outer.ancestor, &inner.ancestor)
+        && same(&outer.uid, &inner.uid)
+        && same(&outer.gid, &inner.gid)
+}
+
+fn describe(policy: &Policy, index: usize) -> String {
+    match policy.rules[index].line {
+        Some(line) => format!("rule on line {line}"),
+        None => format!("rule {}", index + 1),
+    }
+}
+
+/// Find the rules of `policy` that can never decide on an access, because
+/// an other rule matches everything they match and is picked first.
+/// Returns one [`Issue`] per such rule, in order.
+///
+/// # Example
+/// ```rust
+/// # use naughtyfy::policy::*;
+/// let policy: Policy = "
+///     default allow
+///     deny path=/etc
+///     allow path=/etc/hosts
+///     deny glob=/etc/*.conf
+/// "
+/// .parse()
+/// .unwrap();
+/// let issues = validate(&policy);
+/// assert_eq!(issues.len(), 2);
+/// assert_eq!(issues[0].kind(), IssueKind::Conflict);
+/// assert_eq!(issues[1].kind(), IssueKind::Unreachable);
+/// assert_eq!(
+///     issues[0].to_string(),
+///     "rule on line 4 (allow) never applies, rule on line 3 (deny) matches first"
+/// );
+/// ```
+pub fn validate(policy: &Policy) -> Vec<Issue> {
+    let rules = &policy.rules;
+    let mut issues = Vec::new();
+    for (rule, inner) in rules.iter().enumerate() {
+        let decides_first = |by: usize| match policy.evaluation {
+            Evaluation::FirstMatch => by < rule,
+            Evaluation::MostSpecific => {
+                let (outer, inner) = (rules[by].specificity(), inner.specificity());
+                outer > inner || (outer == inner && by < rule)
+            }
+        };
+        let Some(by) = (0..rules.len())
+            .find(|by| *by != rule && decides_first(*by) && covers(&rules[*by], inner))
+        else {
+            continue;
+        };
+        let outer = &rules[by];
+        let kind = match outer.action == inner.action {
+            true => IssueKind::Unreachable,
+            false => IssueKind::Conflict,
+        };
+        let message = format!(
+            "{} ({}) never applies, {} ({}) matches first",
+            describe(policy, rule),
+            inner.action,
+            describe(policy, by),
+            outer.action,
+        );
+        issues.push(Issue {
+            kind,
+            rule,
+            by,
+            message,
+        });
+    }
+    issues
+}
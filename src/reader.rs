@@ -0,0 +1,266 @@
+//! Reading events into a reused buffer.
+//!
+//! [`EventReader`] owns one aligned buffer and reads into it again and
+//! again, the events of a read are borrowed [`EventRef`] views decoding
+//! their fields on access. Convert a view into an owned [`Event`] to keep
+//! it past the next read.
+//!
+//! Every group or reader has its own [`BufferSize`], given in bytes.
+//! A read returns as many whole events as fit in the buffer, so busy
+//! groups want a large one while quiet groups can keep it small.
+//!
+//! # Example
+//! ```rust
+//! # use naughtyfy::reader::*;
+//! // Double when a read fills more than 3/4 of the buffer,
+//! // halve when it fills less than 1/8.
+//! let size = BufferSize::Adaptive { min: 4096, max: 65536 };
+//! assert_eq!(size.initial(), 4096);
+//! assert_eq!(size.next(4096, 4000), 8192);
+//! assert_eq!(size.next(8192, 512), 4096);
+//! assert_eq!(size.next(65536, 65000), 65536);
+//!
+//! assert_eq!(BufferSize::Fixed(16384).next(16384, 16384), 16384);
+//! assert!(BufferSize::Fixed(100).check().is_err());
+//! ```
+
+use crate::{
+    api,
+    errors::FanotifyError,
+    event::{Event, EventBuffer, EventIter},
+    exclude::SelfExclusion,
+    group::Fanotify,
+    permission::Responder,
+};
+use std::os::fd::AsFd;
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::{api::*, event::*, permission::PermissionRequest};
+
+/// Smallest accepted buffer, the size recommended by the kernel.
+/// Smaller buffers may not hold a single event reporting file handles
+/// and names, making `read()` fail with `EINVAL`.
+pub const FAN_EVENT_BUFFER_MIN_LEN: usize = 4096;
+
+/// Size in bytes of the buffer events are read into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferSize {
+    /// Always read into a buffer of this many bytes.
+    Fixed(usize),
+    /// Start at `min` bytes, double (up to `max`) when a read fills more
+    /// than 3/4 of the buffer and halve (down to `min`) when it fills
+    /// less than 1/8.
+    Adaptive {
+        /// Smallest size of the buffer, used for the first read.
+        min: usize,
+        /// Largest size of the buffer.
+        max: usize,
+    },
+}
+
+impl Default for BufferSize {
+    /// [`BufferSize::Fixed`] of [`FAN_EVENT_BUFFER_LEN`].
+    fn default() -> Self {
+        BufferSize::Fixed(FAN_EVENT_BUFFER_LEN)
+    }
+}
+
+impl BufferSize {
+    /// Size of the buffer for the first read.
+    pub fn initial(&self) -> usize {
+        match *self {
+            BufferSize::Fixed(len) => len,
+            BufferSize::Adaptive { min, .. } => min,
+        }
+    }
+
+    /// Size of the buffer for the read following one that filled
+    /// `filled` bytes of a `current` bytes buffer.
+    pub fn next(&self, current: usize, filled: usize) -> usize {
+        match *self {
+            BufferSize::Fixed(len) => len,
+            BufferSize::Adaptive { min, max } => {
+                if filled > current / 4 * 3 {
+                    current.saturating_mul(2).min(max)
+                } else if filled < current / 8 {
+                    (current / 2).max(min)
+                } else {
+                    current.clamp(min, max)
+                }
+            }
+        }
+    }
+
+    /// Returns [`FanotifyError::Invalid`] when a size is below
+    /// [`FAN_EVENT_BUFFER_MIN_LEN`] or `min` is greater than `max`.
+    pub fn check(&self) -> Result<(), FanotifyError> {
+        let min = match *self {
+            BufferSize::Fixed(len) => len,
+            BufferSize::Adaptive { min, max } => {
+                if min > max {
+                    return Err(FanotifyError::Invalid(format!(
+                        "Adaptive buffer min {min} is greater than max {max}"
+                    )));
+                }
+                min
+            }
+        };
+        if min < FAN_EVENT_BUFFER_MIN_LEN {
+            return Err(FanotifyError::Invalid(format!(
+                "Buffer of {min} bytes is below the minimum of {FAN_EVENT_BUFFER_MIN_LEN}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Reads events into a buffer allocated once and reused for every read.
+///
+/// The buffer only changes size when [`BufferSize::Adaptive`] asks for it.
+/// Descriptors of the previous read that were not taken out of their
+/// [`EventRef`] are closed by the next read, or when the reader is dropped.
+///
+/// # Example
+/// This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+/// ```rust
+/// # use naughtyfy::event::*;
+/// # use naughtyfy::flags::*;
+/// # use naughtyfy::group::*;
+/// # use naughtyfy::reader::*;
+/// let dir = std::env::temp_dir().canonicalize().unwrap();
+/// let dir = dir.join(format!("naughtyfy-reader-{}", std::process::id()));
+/// std::fs::create_dir(&dir).unwrap();
+///
+/// // The writes come from this process.
+/// match Fanotify::builder().exclude_self(false).build() {
+///     Ok(group) => {
+///         group
+///             .mark(FAN_MARK_ADD, FAN_CLOSE_WRITE | FAN_EVENT_ON_CHILD, AT_FDCWD, dir.as_path())
+///             .unwrap();
+///         let mut reader = EventReader::new(BufferSize::Fixed(4096)).unwrap();
+///         let mut kept = Vec::new();
+///         for name in ["first", "second"] {
+///             std::fs::write(dir.join(name), b"").unwrap();
+///             for event in reader.read(&group).unwrap() {
+///                 let event = event.unwrap();
+///                 assert_eq!(event.mask(), FAN_CLOSE_WRITE);
+///                 // Keep the event past the next read.
+///                 kept.push(Event::from(event));
+///             }
+///         }
+///         let names = kept.iter().map(|event| event.path().unwrap()).collect::<Vec<_>>();
+///         assert_eq!(names, [dir.join("first"), dir.join("second")]);
+///     }
+///     Err(e) => {
+///         // This can fail for multiple reason, most common being privileges.
+///         eprintln!("Cannot get fd due to {e}");
+///     }
+/// }
+/// std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct EventReader {
+    buffer: EventBuffer,
+    buffer_size: BufferSize,
+    /// Size of the buffer for the next read.
+    next_len: usize,
+    /// Offset of the next event for [`EventReader::next_event()`].
+    offset: usize,
+    /// Attaches a [`PermissionRequest`] to permission events.
+    pub(crate) responder: Option<Responder>,
+    /// Skips the events of this process, see [`crate::exclude`].
+    pub(crate) exclude: Option<SelfExclusion>,
+}
+
+impl Default for EventReader {
+    /// Reader using [`BufferSize::default()`].
+    fn default() -> Self {
+        EventReader::with_size(BufferSize::default())
+    }
+}
+
+impl EventReader {
+    /// Create a reader allocating its buffer according to `buffer_size`.
+    /// Returns [`FanotifyError::Invalid`] if [`BufferSize::check()`] fails.
+    pub fn new(buffer_size: BufferSize) -> Result<Self, FanotifyError> {
+        buffer_size.check()?;
+        Ok(EventReader::with_size(buffer_size))
+    }
+
+    /// Same as [`EventReader::new()`] for an already checked size.
+    pub(crate) fn with_size(buffer_size: BufferSize) -> Self {
+        EventReader {
+            buffer: EventBuffer::new(buffer_size.initial()),
+            buffer_size,
+            next_len: buffer_size.initial(),
+            offset: 0,
+            responder: None,
+            exclude: None,
+        }
+    }
+
+    /// Sizing of the buffer.
+    #[inline]
+    pub fn buffer_size(&self) -> BufferSize {
+        self.buffer_size
+    }
+
+    /// Current size of the buffer in bytes.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Read the pending events of `group` into the buffer and iterate
+    /// over them. See [`read()`] for how the descriptor behaves.
+    ///
+    /// Only the descriptors of a fanotify group are read, the events
+    /// own the descriptors they hold.
+    pub fn read(&mut self, group: &Fanotify) -> Result<EventIter<'_>, FanotifyError> {
+        self.fill(group)?;
+        Ok(self.buffer.events())
+    }
+
+    /// Read the pending events of `fd` into the buffer, returning the
+    /// number of bytes read.
+    pub(crate) fn fill<F: AsFd + ?Sized>(&mut self, fd: &F) -> Result<usize, FanotifyError> {
+        if self.next_len != self.buffer.capacity() {
+            self.buffer.resize(self.next_len);
+        }
+        self.offset = 0;
+        api::read_into(fd, &mut self.buffer)?;
+        let len = self.buffer.len();
+        self.next_len = self.buffer_size.next(self.buffer.capacity(), len);
+        if let Some(exclude) = &self.exclude {
+            exclude.filter(&mut self.buffer);
+        }
+        Ok(len)
+    }
+
+    /// Iterate again over the events of the last read.
+    /// Events taken out by a previous iteration have no descriptors left.
+    pub fn events(&mut self) -> EventIter<'_> {
+        self.buffer.events()
+    }
+
+    /// Take the next event of the last read not yet returned by this
+    /// method. `None` once all of them were, or after a parse error.
+    /// Permission events get a request when the reader has a responder.
+    pub(crate) fn next_event(&mut self) -> Option<Result<Event, FanotifyError>> {
+        match self.buffer.event_at(self.offset)? {
+            Ok((event, next)) => {
+                self.offset = next;
+                let mut event = event.into_event();
+                if let Some(responder) = &self.responder {
+                    event.attach(responder);
+                }
+                Some(Ok(event))
+            }
+            Err(e) => {
+                self.offset = self.buffer.len();
+                Some(Err(e))
+            }
+        }
+    }
+}
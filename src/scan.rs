@@ -0,0 +1,359 @@
+//! On-access scanning of file content.
+//!
+//! A [`Scanner`] looks at the content of the file behind a permission
+//! event and returns a [`Verdict`]. A [`ScanPipeline`] does the rest: it
+//! reads the events of a group, hands them to the scanner on a
+//! [`PermissionDispatcher`], keeps the verdicts in a [`DecisionCache`] and
+//! answers the kernel. The response for infected files, for scanner errors
+//! and for scans running past the timeout can all be configured.
+//!
+//! [`SignatureScanner`] is a simple scanner matching byte signatures,
+//! enough to run the whole pipeline end to end.
+//!
+//! # Example
+//! This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+//! ```rust
+//! # use naughtyfy::flags::*;
+//! # use naughtyfy::group::*;
+//! # use naughtyfy::scan::*;
+//! let dir = std::env::temp_dir().canonicalize().unwrap();
+//! let dir = dir.join(format!("naughtyfy-scan-{}", std::process::id()));
+//! std::fs::create_dir(&dir).unwrap();
+//! std::fs::write(dir.join("clean"), b"nothing to see").unwrap();
+//! std::fs::write(dir.join("infected"), b"header NAUGHTY-TEST-SIGNATURE trailer").unwrap();
+//!
+//! // The accesses come from this process.
+//! let builder = Fanotify::builder().exclude_self(false);
+//! match builder.class(Class::Content).build() {
+//!     Ok(group) => {
+//!         group
+//!             .mark(FAN_MARK_ADD, FAN_OPEN_PERM | FAN_EVENT_ON_CHILD, AT_FDCWD, dir.as_path())
+//!             .unwrap();
+//!         let scanner = SignatureScanner::new().signature("test", b"NAUGHTY-TEST-SIGNATURE");
+//!         let pipeline = ScanPipeline::builder().workers(2).spawn(group, scanner).unwrap();
+//!
+//!         assert!(std::fs::read(dir.join("clean")).is_ok());
+//!         let err = std::fs::read(dir.join("infected")).unwrap_err();
+//!         assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+//!         // Answered from the cache.
+//!         assert!(std::fs::read(dir.join("infected")).is_err());
+//!         assert_eq!((pipeline.infected(), pipeline.cache().hits()), (1, 1));
+//!         pipeline.shutdown().unwrap();
+//!     }
+//!     Err(e) => {
+//!         // This can fail for multiple reason, most common being privileges.
+//!         eprintln!("Cannot get fd due to {e}");
+//!     }
+//! }
+//! std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::{
+    cache::DecisionCache,
+    dispatch::{DispatcherBuilder, PermissionDispatcher},
+    errors::FanotifyError,
+    event::Event,
+    flags::*,
+    group::{check_response, Fanotify},
+    types::*,
+};
+use std::{
+    fs::File,
+    mem::ManuallyDrop,
+    os::{
+        fd::{AsRawFd, BorrowedFd, FromRawFd},
+        unix::fs::FileExt,
+    },
+    sync::{
+        atomic::{self, AtomicUsize},
+        Arc,
+    },
+    time::Duration,
+};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::api::*;
+
+/// Outcome of a scan.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Verdict {
+    /// Nothing found, the access is allowed.
+    Clean,
+    /// Holds the name of what was found.
+    Infected(String),
+    /// The file could not be scanned, holds the description.
+    Error(String),
+}
+
+/// Looks at the content of files behind permission events.
+///
+/// Called from several worker threads at once. Closures taking the
+/// event file descriptor and the event implement it.
+pub trait Scanner: Send + Sync {
+    /// Scan the file open as `fd`, reported by `event`.
+    ///
+    /// `fd` is shared with the event, read it with positioned reads
+    /// (Eg: [`FileExt::read_at()`]) rather than moving its offset.
+    fn scan(&self, fd: BorrowedFd<'_>, event: &Event) -> Verdict;
+}
+
+impl<F> Scanner for F
+where
+    F: Fn(BorrowedFd<'_>, &Event) -> Verdict + Send + Sync,
+{
+    fn scan(&self, fd: BorrowedFd<'_>, event: &Event) -> Verdict {
+        self(fd, event)
+    }
+}
+
+/// Chunk read at once by [`SignatureScanner`].
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Scanner reporting files holding any of its byte signatures.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SignatureScanner {
+    signatures: Vec<(String, Vec<u8>)>,
+}
+
+impl SignatureScanner {
+    /// Scanner without signatures, every file is clean.
+    pub fn new() -> Self {
+        SignatureScanner::default()
+    }
+
+    /// Report files holding `bytes` as infected by `name`.
+    /// Empty signatures are ignored.
+    pub fn signature<B: AsRef<[u8]>>(mut self, name: &str, bytes: B) -> Self {
+        if !bytes.as_ref().is_empty() {
+            self.signatures
+                .push((name.to_string(), bytes.as_ref().to_vec()));
+        }
+        self
+    }
+
+    /// Signatures as `(name, bytes)`, in order.
+    pub fn signatures(&self) -> &[(String, Vec<u8>)] {
+        &self.signatures
+    }
+
+    /// Name of the first signature found in `data`.
+    fn find(&self, data: &[u8]) -> Option<&str> {
+        self.signatures
+            .iter()
+            .find(|(_, bytes)| data.windows(bytes.len()).any(|window| window == bytes))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+impl Scanner for SignatureScanner {
+    fn scan(&self, fd: BorrowedFd<'_>, _event: &Event) -> Verdict {
+        if self.signatures.is_empty() {
+            return Verdict::Clean;
+        }
+        // Borrowed, never closed.
+        let file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd.as_raw_fd()) });
+        // Kept from the previous chunk so signatures across chunks match.
+        let overlap = self
+            .signatures
+            .iter()
+            .map(|(_, bytes)| bytes.len() - 1)
+            .max()
+            .unwrap_or_default();
+        let mut data = vec![0; overlap + CHUNK_LEN];
+        let (mut kept, mut offset) = (0, 0);
+        loop {
+            let read = match file.read_at(&mut data[kept..], offset) {
+                Ok(0) => return Verdict::Clean,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Verdict::Error(e.to_string()),
+            };
+            let len = kept + read;
+            if let Some(name) = self.find(&data[..len]) {
+                return Verdict::Infected(name.to_string());
+            }
+            offset += read as u64;
+            kept = overlap.min(len);
+            data.copy_within(len - kept..len, 0);
+        }
+    }
+}
+
+/// Timeout of [`ScanPipelineBuilder::default()`].
+pub const DEFAULT_SCAN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Configuration of a [`ScanPipeline`], created by [`ScanPipeline::builder()`].
+#[derive(Debug)]
+pub struct ScanPipelineBuilder {
+    dispatcher: DispatcherBuilder,
+    on_infected: Response,
+    on_error: Response,
+    on_timeout: Response,
+    cache: DecisionCache,
+}
+
+impl Default for ScanPipelineBuilder {
+    /// Workers of [`DispatcherBuilder::default()`], a
+    /// [`DEFAULT_SCAN_TIMEOUT`], infected files and scanner errors denied,
+    /// timeouts allowed, and a cache keyed on the file only.
+    fn default() -> Self {
+        ScanPipelineBuilder {
+            dispatcher: DispatcherBuilder::default().deadline(DEFAULT_SCAN_TIMEOUT),
+            on_infected: FAN_DENY,
+            on_error: FAN_DENY,
+            on_timeout: FAN_ALLOW,
+            cache: DecisionCache::builder()
+                .key_exe(false)
+                .key_uid(false)
+                .build(),
+        }
+    }
+}
+
+impl ScanPipelineBuilder {
+    /// Number of files scanned at once.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.dispatcher = self.dispatcher.workers(workers);
+        self
+    }
+
+    /// Time a scan may take, counted from the read of the event.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.dispatcher = self.dispatcher.deadline(timeout);
+        self
+    }
+
+    /// Response for infected files.
+    pub fn on_infected(mut self, response: Response) -> Self {
+        self.on_infected = response;
+        self
+    }
+
+    /// Response for files the scanner failed on, never cached.
+    pub fn on_error(mut self, response: Response) -> Self {
+        self.on_error = response;
+        self
+    }
+
+    /// Response for scans still running once the timeout passed,
+    /// their verdict is dropped.
+    pub fn on_timeout(mut self, response: Response) -> Self {
+        self.on_timeout = response;
+        self
+    }
+
+    /// Cache keeping the verdicts, Eg: with a capacity of 0 to scan
+    /// every access.
+    pub fn cache(mut self, cache: DecisionCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Start scanning the permission events of `group` with `scanner`.
+    ///
+    /// Returns [`FanotifyError::Invalid`] for a response the group cannot
+    /// send, and the errors of [`DispatcherBuilder::spawn()`].
+    pub fn spawn<S>(self, group: Fanotify, scanner: S) -> Result<ScanPipeline, FanotifyError>
+    where
+        S: Scanner + 'static,
+    {
+        for response in [self.on_infected, self.on_error] {
+            check_response(group.flags(), &fanotify_response::new(&FAN_NOFD, response))?;
+        }
+        let cache = Arc::new(self.cache);
+        let stats = Arc::new(Stats::default());
+        let (on_infected, on_error) = (self.on_infected, self.on_error);
+        let decide = {
+            let (cache, stats) = (cache.clone(), stats.clone());
+            move |event: &Event| {
+                if let Some(response) = cache.get(event) {
+                    return response;
+                }
+                let verdict = match event.fd() {
+                    Some(fd) => scanner.scan(fd, event),
+                    None => Verdict::Error("Event has no file descriptor".to_string()),
+                };
+                let response = match verdict {
+                    Verdict::Clean => FAN_ALLOW,
+                    Verdict::Infected(name) => {
+                        stats.infected.fetch_add(1, atomic::Ordering::Relaxed);
+                        eprintln!("Infected by {name}: {:?}", event.path().unwrap_or_default());
+                        on_infected
+                    }
+                    Verdict::Error(e) => {
+                        stats.errors.fetch_add(1, atomic::Ordering::Relaxed);
+                        eprintln!("Cannot scan {:?}: {e}", event.path().unwrap_or_default());
+                        return on_error;
+                    }
+                };
+                cache.insert(event, response);
+                response
+            }
+        };
+        let dispatcher = self
+            .dispatcher
+            .default_response(self.on_timeout)
+            .spawn(group, decide)?;
+        Ok(ScanPipeline {
+            dispatcher,
+            cache,
+            stats,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    infected: AtomicUsize,
+    errors: AtomicUsize,
+}
+
+/// Scans the files behind the permission events of a group and answers
+/// them, see the [module documentation](crate::scan).
+///
+/// Dropping the pipeline shuts it down like [`ScanPipeline::shutdown()`].
+#[derive(Debug)]
+pub struct ScanPipeline {
+    dispatcher: PermissionDispatcher,
+    cache: Arc<DecisionCache>,
+    stats: Arc<Stats>,
+}
+
+impl ScanPipeline {
+    /// Start configuring a pipeline, see [`ScanPipelineBuilder`].
+    pub fn builder() -> ScanPipelineBuilder {
+        ScanPipelineBuilder::default()
+    }
+
+    /// The group being read, Eg: to add marks once scanning started.
+    pub fn group(&self) -> &Fanotify {
+        self.dispatcher.group()
+    }
+
+    /// Cache keeping the verdicts, Eg: to clear it after a signature update.
+    pub fn cache(&self) -> &DecisionCache {
+        &self.cache
+    }
+
+    /// Number of scans that found an infected file.
+    pub fn infected(&self) -> usize {
+        self.stats.infected.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Number of scans that failed.
+    pub fn errors(&self) -> usize {
+        self.stats.errors.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Number of scans that ran past the timeout.
+    pub fn timeouts(&self) -> usize {
+        self.dispatcher.expired()
+    }
+
+    /// Stop reading, let the running scans finish and close the group,
+    /// see [`PermissionDispatcher::shutdown()`].
+    pub fn shutdown(self) -> Result<(), FanotifyError> {
+        self.dispatcher.shutdown()
+    }
+}
@@ -0,0 +1,205 @@
+//! Async reading of a fanotify group with tokio.
+//!
+//! Enabled by the `tokio` feature. [`AsyncFanotify`] registers a
+//! [`FAN_NONBLOCK`] group with the tokio reactor through [`AsyncFd`], so
+//! waiting for events does not need a thread of its own. Events are read
+//! with the same [`EventReader`] and parser as the blocking API.
+//!
+//! # Example
+//! This example may throw error due to absence of `CAP_SYS_ADMIN` [capabilitity](https://man7.org/linux/man-pages/man7/capabilities.7.html)
+//! ```rust
+//! # use naughtyfy::flags::*;
+//! # use naughtyfy::group::*;
+//! # use naughtyfy::tokio::*;
+//! #[tokio::main(flavor = "current_thread")]
+//! async fn main() {
+//!     // Our own events are wanted here.
+//!     let group = match Fanotify::builder().nonblocking().exclude_self(false).build() {
+//!         Ok(group) => group,
+//!         Err(e) => {
+//!             // This can fail for multiple reason, most common being privileges.
+//!             eprintln!("Cannot get fd due to {e}");
+//!             return;
+//!         }
+//!     };
+//!     let tmp = std::env::temp_dir().canonicalize().unwrap();
+//!     group
+//!         .mark(FAN_MARK_ADD, FAN_OPEN | FAN_EVENT_ON_CHILD, AT_FDCWD, tmp.as_path())
+//!         .unwrap();
+//!     let mut group = AsyncFanotify::new(group).unwrap();
+//!
+//!     let file = tmp.join(format!("naughtyfy-tokio-{}", std::process::id()));
+//!     std::fs::write(&file, b"").unwrap();
+//!     loop {
+//!         let event = group.next_event().await.unwrap();
+//!         if event.path().is_ok_and(|path| path == file) {
+//!             break;
+//!         }
+//!     }
+//!     std::fs::remove_file(&file).unwrap();
+//! }
+//! ```
+
+use crate::{
+    errors::FanotifyError, event::Event, flags::*, group::Fanotify, reader::EventReader,
+    types::fanotify_response,
+};
+use ::tokio::io::unix::AsyncFd;
+use futures_core::Stream;
+use std::{
+    future::poll_fn,
+    io,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::{api::*, permission::PermissionRequest};
+
+/// Errors of the reactor carry an errno when they come from the kernel.
+fn reactor_error(e: io::Error) -> FanotifyError {
+    match e.raw_os_error() {
+        Some(errno) => FanotifyError::Read(errno),
+        None => FanotifyError::Invalid(e.to_string()),
+    }
+}
+
+/// A [`FAN_NONBLOCK`] group registered with the tokio reactor.
+///
+/// Implements [`Stream`] of events, the stream never ends on its own.
+/// Dropping an event closes its file descriptor like with the blocking API.
+#[derive(Debug)]
+pub struct AsyncFanotify {
+    inner: Arc<AsyncFd<Fanotify>>,
+    reader: EventReader,
+}
+
+impl AsyncFanotify {
+    /// Register `group` with the reactor of the current tokio runtime.
+    ///
+    /// Returns [`FanotifyError::Invalid`] if the group was initialized
+    /// without [`FAN_NONBLOCK`] or when called outside of a runtime.
+    pub fn new(group: Fanotify) -> Result<Self, FanotifyError> {
+        if !group.flags().contains(FAN_NONBLOCK) {
+            return Err(FanotifyError::Invalid(
+                "Async reading needs a group initialized with FAN_NONBLOCK".to_string(),
+            ));
+        }
+        let reader = group.reader();
+        let inner = AsyncFd::new(group).map_err(reactor_error)?;
+        Ok(AsyncFanotify {
+            inner: Arc::new(inner),
+            reader,
+        })
+    }
+
+    /// The group being read.
+    #[inline]
+    pub fn get_ref(&self) -> &Fanotify {
+        self.inner.get_ref()
+    }
+
+    /// Handle answering permission events from any task,
+    /// while this one keeps reading.
+    pub fn responder(&self) -> Responder {
+        Responder {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Wait for the next event of the group.
+    ///
+    /// Cancel safe, an event read but not returned yet is kept
+    /// for the next call.
+    pub async fn next_event(&mut self) -> Result<Event, FanotifyError> {
+        poll_fn(|cx| self.poll_next_event(cx)).await
+    }
+
+    /// Poll for the next event of the group, registering the waker of `cx`
+    /// with the reactor when none is pending.
+    pub fn poll_next_event(&mut self, cx: &mut Context<'_>) -> Poll<Result<Event, FanotifyError>> {
+        loop {
+            if let Some(event) = self.reader.next_event() {
+                return Poll::Ready(event);
+            }
+            let mut guard = ready!(self.inner.poll_read_ready(cx)).map_err(reactor_error)?;
+            match self.reader.fill(guard.get_inner()) {
+                Ok(_) => continue,
+                Err(FanotifyError::Read(libc::EINTR)) => continue,
+                Err(FanotifyError::Read(libc::EAGAIN)) => guard.clear_ready(),
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+        }
+    }
+
+    /// Answer a permission event, see [`Fanotify::respond()`].
+    pub async fn respond(&self, response: &fanotify_response) -> Result<isize, FanotifyError> {
+        self.inner.get_ref().respond(response)
+    }
+}
+
+impl Stream for AsyncFanotify {
+    type Item = Result<Event, FanotifyError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_event(cx).map(Some)
+    }
+}
+
+impl AsFd for AsyncFanotify {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.get_ref().as_fd()
+    }
+}
+
+impl AsRawFd for AsyncFanotify {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.get_ref().as_raw_fd()
+    }
+}
+
+/// Answers permission events of an [`AsyncFanotify`] from any task,
+/// created by [`AsyncFanotify::responder()`].
+///
+/// The kernel takes responses without ever blocking the writer, so the
+/// methods complete on first poll. They are `async` to be awaited in
+/// line with the rest of a task.
+#[derive(Debug, Clone)]
+pub struct Responder {
+    inner: Arc<AsyncFd<Fanotify>>,
+}
+
+impl Responder {
+    /// Answer a permission event, see [`Fanotify::respond()`].
+    pub async fn respond(&self, response: &fanotify_response) -> Result<isize, FanotifyError> {
+        self.inner.get_ref().respond(response)
+    }
+
+    /// Send `response` for `event`, through its [`PermissionRequest`] if it
+    /// still has one. Returns [`FanotifyError::Invalid`] if the event has no
+    /// file descriptor to answer with.
+    async fn answer(&self, event: &mut Event, response: Response) -> Result<isize, FanotifyError> {
+        if let Some(request) = event.take_permission() {
+            return request.respond(response);
+        }
+        match event.fd() {
+            Some(fd) => self.respond(&fanotify_response::new(&fd, response)).await,
+            None => Err(FanotifyError::Invalid(
+                "Event has no file descriptor to respond to".to_string(),
+            )),
+        }
+    }
+
+    /// Allow the access reported by `event`.
+    pub async fn allow(&self, event: &mut Event) -> Result<isize, FanotifyError> {
+        self.answer(event, FAN_ALLOW).await
+    }
+
+    /// Deny the access reported by `event`.
+    pub async fn deny(&self, event: &mut Event) -> Result<isize, FanotifyError> {
+        self.answer(event, FAN_DENY).await
+    }
+}
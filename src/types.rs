@@ -1,7 +1,10 @@
 //! Contains all the necessary structs
 //! needed for fanotify to work
 
-use libc::{__s32, __u16, __u32, __u64, __u8, c_int};
+use crate::flags::{
+    EventMask, Response, FAN_AUDIT, FAN_DENY_ERRNO, FAN_INFO, FAN_RESPONSE_INFO_AUDIT_RULE,
+};
+use libc::{__s32, __u16, __u32, __u8, c_int};
 use std::ffi::OsStr;
 use std::os::fd::AsRawFd;
 pub use std::os::fd::OwnedFd as Fd;
@@ -13,7 +16,10 @@ use crate::api::*;
 use crate::flags::*;
 
 /// After a successful read(2), the read buffer contains the following structure
-#[derive(Debug, Clone)]
+///
+/// Plain data, the `fd` it holds is not closed on drop.
+/// Use [`crate::event::Event`] for an event owning its descriptor.
+#[derive(Debug, Clone, Copy)]
 #[allow(non_camel_case_types)]
 #[repr(C)]
 pub struct fanotify_event_metadata {
@@ -39,7 +45,7 @@ pub struct fanotify_event_metadata {
     /// type.  No such optional headers exist in the current implementation.
     pub metadata_len: __u16,
     /// This is a bit mask describing the event (see below).
-    pub mask: __u64,
+    pub mask: EventMask,
     /// This is an open file descriptor for the object being accessed,or FAN_NOFD if a queue overflow occurred.  
     /// If the fanotify file descriptor has been initialized using FAN_REPORT_FID,
     /// applications should expect this value to be set to FAN_NOFD
@@ -60,32 +66,26 @@ pub struct fanotify_event_metadata {
     pub pid: __s32,
 }
 
-/// Droping bounded file description
-impl Drop for fanotify_event_metadata {
-    fn drop(&mut self) {
-        if self.fd >= 0 {
-            if let Err(e) = close(self.fd) {
-                eprintln!("Fd :: {}\nErr :: {}", self.fd, e);
-            }
-        }
-    }
-}
-
-/// To be used within [`fanotify_event_info_fid`]
-#[derive(Debug, Clone, Copy)]
+/// Filesystem id as reported in [`fanotify_event_info_fid`].
+/// Contains the same value as `f_fsid` when calling statfs(2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[allow(non_camel_case_types)]
-struct __kernel_fsid_t {
-    #[allow(dead_code)]
-    val: [c_int; 2],
+#[repr(C)]
+pub struct __kernel_fsid_t {
+    pub val: [c_int; 2],
 }
 
-/// This is the header part of [`fanotify_event_info_fid`]
+/// This is the header part of every additional information record
+/// that follows [`fanotify_event_metadata`] in the read buffer.
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct fanotify_event_info_header {
+    /// Type of the record, one of the `FAN_EVENT_INFO_TYPE_*` values
+    /// (Eg: [`FAN_EVENT_INFO_TYPE_FID`]).
     pub info_type: __u8,
     pub pad: __u8,
+    /// Size of the record including this header.
     pub len: __u16,
 }
 
@@ -95,9 +95,12 @@ pub struct fanotify_event_info_header {
 /// you should also expect to receive one or more
 /// additional information records of the structure detailed below
 /// following the generic [`fanotify_event_metadata`] structure within
-/// the read buffer:
+/// the read buffer.
+///
+/// The record is variable length, use [`crate::event`] to parse it
+/// out of a read buffer.
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 #[repr(C)]
 pub struct fanotify_event_info_fid {
     /// It is a generic header that contains information used to
@@ -105,29 +108,29 @@ pub struct fanotify_event_info_fid {
     /// event.  For example, when an fanotify file descriptor is
     /// created using [`FAN_REPORT_FID`], a single information record
     /// is expected to be attached to the event with info_type
-    /// field value of `FAN_EVENT_INFO_TYPE_FID`.  When an fanotify
+    /// field value of [`FAN_EVENT_INFO_TYPE_FID`].  When an fanotify
     /// file descriptor is created using the combination of
     /// [`FAN_REPORT_FID`] and [`FAN_REPORT_DIR_FID`], there may be two
     /// information records attached to the event: one with
-    /// info_type field value of `FAN_EVENT_INFO_TYPE_DFID`,
+    /// info_type field value of [`FAN_EVENT_INFO_TYPE_DFID`],
     /// identifying a parent directory object, and one with
-    /// info_type field value of `FAN_EVENT_INFO_TYPE_FID`,
+    /// info_type field value of [`FAN_EVENT_INFO_TYPE_FID`],
     /// identifying a non-directory object.  The
     /// fanotify_event_info_header contains a len field.  The
     /// value of len is the size of the additional information
     /// record including the fanotify_event_info_header itself.
     /// The total size of all additional information records is
     /// not expected to be bigger than ( event_len - metadata_len ).
-    hdr: fanotify_event_info_header,
+    pub hdr: fanotify_event_info_header,
 
     /// This is a unique identifier of the filesystem containing
     /// the object associated with the event.  It is a structure
     /// of type __kernel_fsid_t and contains the same value as
     /// f_fsid when calling statfs(2).
-    fsid: __kernel_fsid_t,
+    pub fsid: __kernel_fsid_t,
 
     /// This is a variable length structure of type struct
-    /// file_handle.  It is an opaque handle that corresponds to a
+    /// [`file_handle`].  It is an opaque handle that corresponds to a
     /// specified object on a filesystem as returned by
     /// name_to_handle_at(2).  It can be used to uniquely identify
     /// a file on a filesystem and can be passed as an argument to
@@ -135,47 +138,118 @@ pub struct fanotify_event_info_fid {
     /// modification events [`FAN_CREATE`], [`FAN_DELETE`], and [`FAN_MOVE`],
     /// the file_handle identifies the modified directory and not
     /// the created/deleted/moved child object.  If the value of
-    /// info_type field is `FAN_EVENT_INFO_TYPE_DFID_NAME`, the file
+    /// info_type field is [`FAN_EVENT_INFO_TYPE_DFID_NAME`], the file
     /// handle is followed by a null terminated string that
     /// identifies the created/deleted/moved directory entry name.
     /// For other events such as [`FAN_OPEN`], [`FAN_ATTRIB`],
     /// [`FAN_DELETE_SELF`], and [`FAN_MOVE_SELF`], if the value of
-    /// info_type field is `FAN_EVENT_INFO_TYPE_FID`, the
+    /// info_type field is [`FAN_EVENT_INFO_TYPE_FID`], the
     /// file_handle identifies the object correlated to the event.
     /// If the value of info_type field is
-    /// `FAN_EVENT_INFO_TYPE_DFID`, the file_handle identifies the
+    /// [`FAN_EVENT_INFO_TYPE_DFID`], the file_handle identifies the
     /// directory object correlated to the event or the parent
     /// directory of a non-directory object correlated to the
     /// event.  If the value of info_type field is
-    /// `FAN_EVENT_INFO_TYPE_DFID_NAME`, the file_handle identifies
+    /// [`FAN_EVENT_INFO_TYPE_DFID_NAME`], the file_handle identifies
     /// the same directory object that would be reported with
-    /// `FAN_EVENT_INFO_TYPE_DFID` and the file handle is followed
+    /// [`FAN_EVENT_INFO_TYPE_DFID`] and the file handle is followed
     /// by a null terminated string that identifies the name of a
     /// directory entry in that directory, or '.' to identify the
     /// directory object itself.
-    file_handle: __u8,
+    pub handle: [__u8; 0],
+}
+
+/// Header of the opaque handle carried by [`fanotify_event_info_fid`].
+/// `handle_bytes` bytes of handle data follow it.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct file_handle {
+    /// Size of the handle data following this header.
+    pub handle_bytes: __u32,
+    /// Filesystem specific type of the handle.
+    pub handle_type: c_int,
 }
 
-/// A struct that holds both [`fanotify_event_metadata`] and [`fanotify_event_info_fid`]
-/// in sequence for reading from buffer when fanotify is initialized with
-/// [`FAN_REPORT_FID`] or [`FAN_REPORT_DIR_FID`] flag
+/// Information record carried when [`init()`] was initialised with
+/// [`FAN_REPORT_PIDFD`].
 #[allow(non_camel_case_types)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
-pub struct fanotify_event_with_fid {
-    /// This is a structure of type [`fanotify_event_metadata`].
-    /// It contains the event metadata
-    pub metadata: fanotify_event_metadata,
+pub struct fanotify_event_info_pidfd {
+    pub hdr: fanotify_event_info_header,
+    /// A pidfd referring to the process that generated the event,
+    /// or [`FAN_NOPIDFD`] / [`FAN_EPIDFD`] on failure.
+    /// The reading application is responsible for closing it.
+    pub pidfd: __s32,
+}
 
-    /// This is a structure of type [`fanotify_event_info_fid`].
-    /// It contains the file system id and file handle.
-    pub fid: fanotify_event_info_fid,
+/// Information record carried by [`FAN_FS_ERROR`] events.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct fanotify_event_info_error {
+    pub hdr: fanotify_event_info_header,
+    /// The errno of the first error since the last read.
+    pub error: __s32,
+    /// Number of errors that occurred since the last read.
+    pub error_count: __u32,
+}
+
+/// Header of the information record following a [`fanotify_response`]
+/// marked with [`FAN_INFO`].
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct fanotify_response_info_header {
+    /// Type of the record, one of the `FAN_RESPONSE_INFO_*` values
+    /// (Eg: [`FAN_RESPONSE_INFO_AUDIT_RULE`]).
+    pub info_type: __u8,
+    pub pad: __u8,
+    /// Size of the record including this header.
+    pub len: __u16,
+}
+
+/// Audit rule that caused the decision, logged with [`FAN_AUDIT`].
+///
+/// The trust fields are `0` for untrusted, `1` for trusted
+/// and `2` for unknown.
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct fanotify_response_info_audit_rule {
+    pub hdr: fanotify_response_info_header,
+    /// Number of the rule that made the decision.
+    pub rule_number: __u32,
+    /// Trust in the subject (process) of the access.
+    pub subj_trust: __u32,
+    /// Trust in the object (file) of the access.
+    pub obj_trust: __u32,
+}
+
+impl fanotify_response_info_audit_rule {
+    /// Record for rule `rule_number` with the trust in the subject and object.
+    pub fn new(rule_number: u32, subj_trust: u32, obj_trust: u32) -> Self {
+        fanotify_response_info_audit_rule {
+            hdr: fanotify_response_info_header {
+                info_type: FAN_RESPONSE_INFO_AUDIT_RULE,
+                pad: 0,
+                len: std::mem::size_of::<Self>() as __u16,
+            },
+            rule_number,
+            subj_trust,
+            obj_trust,
+        }
+    }
 }
 
 /// A struct that creates a response to be written to a
 /// `PERM` type flag (Eg: [`FAN_OPEN_PERM`])
+///
+/// The kernel response is followed by room for an audit rule record,
+/// only written along when the response has [`FAN_INFO`] set.
 #[allow(non_camel_case_types)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct fanotify_response {
     /// This is the file descriptor from the structure
@@ -184,16 +258,59 @@ pub struct fanotify_response {
     /// This field indicates whether or not the permission is to
     /// be granted.  Its value must be either [`FAN_ALLOW`] to allow
     /// the file operation or [`FAN_DENY`] to deny the file operation.
-    pub response: __u32,
+    pub response: Response,
+    /// Record following the response with [`FAN_INFO`],
+    /// set by [`fanotify_response::with_audit_rule()`].
+    pub audit_rule: fanotify_response_info_audit_rule,
 }
 
 impl fanotify_response {
-    /// Create a new response using [`Fd`] and `PERM`
-    /// type from flags
-    pub fn new(fd: &Fd, response: __u32) -> Self {
+    /// Create a new response using the event file descriptor
+    /// (Eg: [`Fd`], [`crate::event::Event::fd()`]) and `PERM` type from flags
+    pub fn new<F: AsRawFd + ?Sized>(fd: &F, response: Response) -> Self {
         fanotify_response {
             fd: fd.as_raw_fd(),
             response,
+            audit_rule: fanotify_response_info_audit_rule::default(),
+        }
+    }
+
+    /// Deny the event of `fd`, failing the access with `errno`.
+    /// See [`FAN_DENY_ERRNO()`].
+    pub fn deny_errno<F: AsRawFd + ?Sized>(fd: &F, errno: i32) -> Self {
+        fanotify_response::new(fd, FAN_DENY_ERRNO(errno))
+    }
+
+    /// Attach the audit rule that made the decision, setting
+    /// [`FAN_INFO`] and [`FAN_AUDIT`] (the group needs [`FAN_ENABLE_AUDIT`]).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use naughtyfy::flags::*;
+    /// # use naughtyfy::types::*;
+    /// let response = fanotify_response::new(&3, FAN_DENY);
+    /// assert_eq!(response.write_len(), 8);
+    /// let rule = fanotify_response_info_audit_rule::new(7, 1, 0);
+    /// let response = response.with_audit_rule(rule);
+    /// assert!(response.response.contains(FAN_DENY | FAN_INFO | FAN_AUDIT));
+    /// assert_eq!(response.write_len(), 8 + 16);
+    /// ```
+    pub fn with_audit_rule(mut self, audit_rule: fanotify_response_info_audit_rule) -> Self {
+        self.response |= FAN_INFO | FAN_AUDIT;
+        self.audit_rule = audit_rule;
+        self
+    }
+
+    /// Number of bytes written to the group by [`write()`],
+    /// the information record is only included with [`FAN_INFO`].
+    ///
+    /// Never past the end of the struct, whatever `audit_rule.hdr.len` holds.
+    pub fn write_len(&self) -> usize {
+        let len = std::mem::offset_of!(fanotify_response, audit_rule);
+        if self.response.contains(FAN_INFO) {
+            len + std::mem::size_of::<fanotify_response_info_audit_rule>()
+        } else {
+            len
         }
     }
 }
@@ -0,0 +1,594 @@
+//! Scanning in separate worker processes.
+//!
+//! Scanners parse untrusted content, and a crash in a parser must not take
+//! down the process answering permission events. A [`WorkerPool`] runs a
+//! [`Scanner`] in worker processes instead: each request passes the event
+//! file descriptor to an idle worker with `SCM_RIGHTS`, and the worker
+//! sends its [`Verdict`] back on the same socket.
+//!
+//! A worker that dies or runs past the timeout is killed and restarted,
+//! and the request it held gets the crash verdict, [`Verdict::Error`]
+//! unless set with [`WorkerPoolBuilder::on_crash()`]. A request that
+//! could not be handed to a worker, because it ended while idle, is
+//! retried once on its replacement. The pool is itself a
+//! [`Scanner`], so it plugs into a [`crate::scan::ScanPipeline`].
+//!
+//! Workers are started from an executable calling [`serve()`] first thing
+//! in `main`, by default the current one. Every descriptor other than the
+//! socket and the standard streams is closed when they start, so only the
+//! main process holds the [`init()`] descriptor. Workers also run with
+//! `PR_SET_NO_NEW_PRIVS` and without any capability: the bounding and
+//! ambient sets are cleared and the securebits locked, so they cannot get
+//! one back, even as root. A pool started as root must drop its workers to
+//! another user with [`WorkerPoolBuilder::user()`], root workers would
+//! still own the files root owns. That is the whole of their isolation:
+//! there is no seccomp filter nor namespace. Point
+//! [`WorkerPoolBuilder::program()`] at a wrapper to confine them further.
+//!
+//! # Example
+//! The worker crashing on a file is restarted.
+//! ```rust
+//! # use naughtyfy::event::Event;
+//! # use naughtyfy::scan::*;
+//! # use naughtyfy::worker::*;
+//! # use std::os::fd::*;
+//! # use std::time::Duration;
+//! // Returns unless started as a worker, then scans until the pool closes.
+//! serve(|fd: BorrowedFd<'_>, event: &Event| {
+//!     let content = std::fs::read(format!("/proc/self/fd/{}", fd.as_raw_fd())).unwrap();
+//!     if content.starts_with(b"CRASH") {
+//!         std::process::abort();
+//!     }
+//!     if content.starts_with(b"PID") {
+//!         return Verdict::Infected(std::process::id().to_string());
+//!     }
+//!     SignatureScanner::new()
+//!         .signature("test", b"NAUGHTY-TEST-SIGNATURE")
+//!         .scan(fd, event)
+//! });
+//!
+//! let dir = std::env::temp_dir().join(format!("naughtyfy-worker-{}", std::process::id()));
+//! std::fs::create_dir(&dir).unwrap();
+//! std::fs::write(dir.join("clean"), b"nothing to see").unwrap();
+//! std::fs::write(dir.join("infected"), b"header NAUGHTY-TEST-SIGNATURE trailer").unwrap();
+//! std::fs::write(dir.join("crash"), b"CRASH").unwrap();
+//! std::fs::write(dir.join("pid"), b"PID").unwrap();
+//! let open = |name| std::fs::File::open(dir.join(name)).unwrap();
+//!
+//! let mut builder = WorkerPool::builder().workers(1);
+//! if unsafe { libc::geteuid() } == 0 {
+//!     // Workers cannot run as root, scan as nobody instead.
+//!     builder = builder.user(65534, 65534);
+//! }
+//! let pool = builder.spawn().unwrap();
+//! assert_eq!(pool.scan_fd(open("clean").as_fd()), Verdict::Clean);
+//! let verdict = pool.scan_fd(open("infected").as_fd());
+//! assert_eq!(verdict, Verdict::Infected("test".to_string()));
+//! assert!(matches!(pool.scan_fd(open("crash").as_fd()), Verdict::Error(_)));
+//! // A new worker took over.
+//! assert_eq!(pool.scan_fd(open("clean").as_fd()), Verdict::Clean);
+//! assert_eq!(pool.restarts(), 1);
+//!
+//! // Killed while idle, replaced before the next request.
+//! let Verdict::Infected(pid) = pool.scan_fd(open("pid").as_fd()) else {
+//!     panic!("no pid");
+//! };
+//! unsafe { libc::kill(pid.parse().unwrap(), libc::SIGKILL) };
+//! # let stat = format!("/proc/{pid}/stat");
+//! # while !std::fs::read_to_string(&stat).unwrap().contains(") Z ") {
+//! #     std::thread::sleep(Duration::from_millis(1));
+//! # }
+//! assert_eq!(pool.scan_fd(open("clean").as_fd()), Verdict::Clean);
+//! assert_eq!(pool.restarts(), 2);
+//! std::fs::remove_dir_all(&dir).unwrap();
+//! ```
+
+use crate::{
+    clamd::send_fd,
+    event::Event,
+    flags::*,
+    scan::{Scanner, Verdict},
+};
+use std::{
+    ffi::OsString,
+    io::{self, Read, Write},
+    mem,
+    os::{
+        fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+        unix::{net::UnixStream, process::CommandExt},
+    },
+    path::PathBuf,
+    process::{self, Child, Command, Stdio},
+    ptr,
+    sync::{
+        atomic::{self, AtomicUsize},
+        Condvar, Mutex, MutexGuard,
+    },
+    thread,
+    time::Duration,
+};
+
+// Used for docs
+#[allow(unused_imports)]
+use crate::api::*;
+
+/// Set in the environment of the workers, see [`serve()`].
+pub const WORKER_ENV: &str = "NAUGHTYFY_WORKER";
+
+/// Time a worker may take per request with [`WorkerPoolBuilder::default()`].
+pub const DEFAULT_WORKER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Request: the event mask and pid, the descriptor travels alongside.
+const REQUEST_LEN: usize = mem::size_of::<u64>() + mem::size_of::<i32>();
+
+/// Longest description a worker may send back.
+const MAX_REPLY_LEN: usize = 64 * 1024;
+
+/// Not in the `libc` versions this crate supports.
+const CLOSE_RANGE_CLOEXEC: libc::c_uint = 1 << 2;
+const CAP_SETPCAP: u32 = 8;
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+/// `SECBIT_NOROOT`, `SECBIT_NO_SETUID_FIXUP` and `SECBIT_NO_CAP_AMBIENT_RAISE`
+/// with their locks, and `SECBIT_KEEP_CAPS_LOCKED`.
+const SECURE_BITS: libc::c_ulong = 0b1110_1111;
+
+/// `cap_user_header_t` of `capget(2)`.
+#[repr(C)]
+struct CapHeader {
+    version: u32,
+    pid: i32,
+}
+
+/// `cap_user_data_t` of `capget(2)`, two of them for 64 capabilities.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct CapData {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+/// Serve the requests of the [`WorkerPool`] that started this process with
+/// `scanner`, then exit. Returns right away in any other process.
+///
+/// Call it first thing in `main`, before any thread or descriptor is
+/// created. A panic in `scanner` ends the worker, and the pool restarts it.
+/// [`WORKER_ENV`] is removed, so processes started by `scanner` are not
+/// workers.
+pub fn serve<S: Scanner>(scanner: S) {
+    if std::env::var_os(WORKER_ENV).is_none() {
+        return;
+    }
+    // No other thread is running yet.
+    std::env::remove_var(WORKER_ENV);
+    // The pool passes the socket as standard input.
+    let socket = unsafe { UnixStream::from_raw_fd(libc::STDIN_FILENO) };
+    let code = match serve_on(&socket, &scanner) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Scanner worker {} failed: {e}", process::id());
+            1
+        }
+    };
+    process::exit(code);
+}
+
+fn serve_on<S: Scanner>(mut socket: &UnixStream, scanner: &S) -> io::Result<()> {
+    while let Some((mask, pid, fd)) = receive(socket)? {
+        let event = Event::with_fd(mask, pid, fd);
+        let verdict = match event.fd() {
+            Some(fd) => scanner.scan(fd, &event),
+            None => Verdict::Error("No file descriptor in the request".to_string()),
+        };
+        let (kind, text) = match &verdict {
+            Verdict::Clean => (0, ""),
+            Verdict::Infected(name) => (1, name.as_str()),
+            Verdict::Error(description) => (2, description.as_str()),
+        };
+        let text = &text.as_bytes()[..text.len().min(MAX_REPLY_LEN)];
+        let mut reply = vec![kind];
+        reply.extend_from_slice(&(text.len() as u32).to_ne_bytes());
+        reply.extend_from_slice(text);
+        socket.write_all(&reply)?;
+    }
+    Ok(())
+}
+
+/// Next request on `socket`, `None` once the pool closed it.
+fn receive(socket: &UnixStream) -> io::Result<Option<(EventMask, i32, Option<OwnedFd>)>> {
+    let mut data = [0u8; REQUEST_LEN];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr().cast(),
+        iov_len: data.len(),
+    };
+    // `u64` keeps the control buffer aligned for `cmsghdr`.
+    let mut control = [0u64; 4];
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr().cast();
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+    let len = loop {
+        match unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) } {
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+            -1 => return Err(io::Error::last_os_error()),
+            len => break len as usize,
+        }
+    };
+    let mut fd = None;
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        unsafe {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_RIGHTS {
+                let raw = ptr::read_unaligned(libc::CMSG_DATA(cmsg).cast::<libc::c_int>());
+                fd = Some(OwnedFd::from_raw_fd(raw));
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    match len {
+        0 => Ok(None),
+        REQUEST_LEN => {
+            let (mask, pid) = data.split_at(mem::size_of::<u64>());
+            Ok(Some((
+                EventMask::from_bits_retain(u64::from_ne_bytes(mask.try_into().unwrap())),
+                i32::from_ne_bytes(pid.try_into().unwrap()),
+                fd,
+            )))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Truncated request of {len} bytes"),
+        )),
+    }
+}
+
+/// Configuration of a [`WorkerPool`], created by [`WorkerPool::builder()`].
+#[derive(Debug, Clone)]
+pub struct WorkerPoolBuilder {
+    program: Option<PathBuf>,
+    args: Vec<OsString>,
+    workers: usize,
+    timeout: Duration,
+    user: Option<(u32, u32)>,
+    on_crash: Option<Verdict>,
+}
+
+impl Default for WorkerPoolBuilder {
+    /// One worker per available CPU running the current executable,
+    /// with a [`DEFAULT_WORKER_TIMEOUT`].
+    fn default() -> Self {
+        WorkerPoolBuilder {
+            program: None,
+            args: Vec::new(),
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            timeout: DEFAULT_WORKER_TIMEOUT,
+            user: None,
+            on_crash: None,
+        }
+    }
+}
+
+impl WorkerPoolBuilder {
+    /// Executable started as worker, it must call [`serve()`].
+    /// Replaces the current executable.
+    pub fn program<P: Into<PathBuf>>(mut self, program: P) -> Self {
+        self.program = Some(program.into());
+        self
+    }
+
+    /// Add an argument passed to the workers.
+    pub fn arg<A: Into<OsString>>(mut self, arg: A) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Number of worker processes, requests wait for an idle one.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Time a worker may take per request before it is restarted.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Run the workers as `uid` and `gid`, without supplementary groups.
+    /// Required when the pool is started as root, `user(0, 0)` keeps
+    /// the workers root, still without capabilities.
+    pub fn user(mut self, uid: u32, gid: u32) -> Self {
+        self.user = Some((uid, gid));
+        self
+    }
+
+    /// Verdict of the requests held by a worker that crashed or timed out.
+    /// Replaces a [`Verdict::Error`] describing what happened.
+    pub fn on_crash(mut self, verdict: Verdict) -> Self {
+        self.on_crash = Some(verdict);
+        self
+    }
+
+    /// Start the workers, failing with the error of the first one
+    /// that cannot be started.
+    ///
+    /// Returns [`io::ErrorKind::PermissionDenied`] when started as root
+    /// without [`WorkerPoolBuilder::user()`].
+    pub fn spawn(self) -> io::Result<WorkerPool> {
+        if self.user.is_none() && unsafe { libc::geteuid() } == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Workers would run as root, set WorkerPoolBuilder::user()",
+            ));
+        }
+        let workers = (0..self.workers)
+            .map(|_| Worker::start(&self).map(Some))
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(WorkerPool {
+            config: self,
+            idle: Mutex::new(workers),
+            available: Condvar::new(),
+            restarts: AtomicUsize::new(0),
+        })
+    }
+}
+
+/// Switch to `user` and clear every capability set of the calling process,
+/// locking the securebits so `execve()` gives none back to root. Runs
+/// between `fork()` and `execve()`, system calls only.
+fn drop_privileges(user: Option<(u32, u32)>) -> io::Result<()> {
+    let mut header = CapHeader {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    let mut data = [CapData::default(); 2];
+    if unsafe { libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Needs CAP_SETPCAP, a process started without it has no capability
+    // to give up there.
+    if data[0].effective & (1 << CAP_SETPCAP) != 0 {
+        if unsafe { libc::prctl(libc::PR_SET_SECUREBITS, SECURE_BITS, 0, 0, 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        for cap in 0.. {
+            if unsafe { libc::prctl(libc::PR_CAPBSET_DROP, cap, 0, 0, 0) } != 0 {
+                let e = io::Error::last_os_error();
+                // Past the last capability of the kernel.
+                if e.raw_os_error() == Some(libc::EINVAL) {
+                    break;
+                }
+                return Err(e);
+            }
+        }
+    }
+    if let Some((uid, gid)) = user {
+        unsafe {
+            if libc::setgroups(0, ptr::null()) != 0
+                || libc::setgid(gid) != 0
+                || libc::setuid(uid) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    let clear = libc::PR_CAP_AMBIENT_CLEAR_ALL as libc::c_ulong;
+    if unsafe { libc::prctl(libc::PR_CAP_AMBIENT, clear, 0, 0, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let data = [CapData::default(); 2];
+    if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// A worker process and the socket it serves.
+#[derive(Debug)]
+struct Worker {
+    child: Child,
+    socket: UnixStream,
+}
+
+impl Worker {
+    fn start(config: &WorkerPoolBuilder) -> io::Result<Self> {
+        let program = match &config.program {
+            Some(program) => program.clone(),
+            None => std::env::current_exe()?,
+        };
+        let (socket, theirs) = UnixStream::pair()?;
+        socket.set_read_timeout(Some(config.timeout))?;
+        socket.set_write_timeout(Some(config.timeout))?;
+        let mut command = Command::new(program);
+        command
+            .args(&config.args)
+            .env(WORKER_ENV, "1")
+            .stdin(Stdio::from(OwnedFd::from(theirs)));
+        let user = config.user;
+        unsafe {
+            command.pre_exec(move || {
+                // Only the standard streams survive exec, never the group.
+                if libc::syscall(
+                    libc::SYS_close_range,
+                    3,
+                    libc::c_uint::MAX,
+                    CLOSE_RANGE_CLOEXEC,
+                ) != 0
+                {
+                    for fd in 3..libc::sysconf(libc::_SC_OPEN_MAX).clamp(3, 65536) as i32 {
+                        libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                    }
+                }
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                drop_privileges(user)
+            });
+        }
+        let child = command.spawn()?;
+        Ok(Worker { child, socket })
+    }
+
+    /// Hand the request over, fails if the worker ended while idle.
+    fn send(&self, fd: BorrowedFd<'_>, mask: EventMask, pid: i32) -> io::Result<()> {
+        let mut data = [0u8; REQUEST_LEN];
+        data[..8].copy_from_slice(&mask.bits().to_ne_bytes());
+        data[8..].copy_from_slice(&pid.to_ne_bytes());
+        send_fd(&self.socket, fd.as_raw_fd(), &data)
+    }
+
+    /// Verdict on the request handed over with [`Worker::send()`].
+    fn reply(&mut self) -> io::Result<Verdict> {
+        let mut header = [0u8; 5];
+        self.socket.read_exact(&mut header)?;
+        let len = u32::from_ne_bytes(header[1..].try_into().unwrap()) as usize;
+        if len > MAX_REPLY_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Reply of {len} bytes"),
+            ));
+        }
+        let mut text = vec![0; len];
+        self.socket.read_exact(&mut text)?;
+        let text = String::from_utf8_lossy(&text).into_owned();
+        match header[0] {
+            0 => Ok(Verdict::Clean),
+            1 => Ok(Verdict::Infected(text)),
+            2 => Ok(Verdict::Error(text)),
+            kind => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown verdict {kind}"),
+            )),
+        }
+    }
+
+    /// Kill the worker and describe how it ended.
+    fn stop(mut self) -> String {
+        let _ = self.child.kill();
+        match self.child.wait() {
+            Ok(status) => status.to_string(),
+            Err(e) => e.to_string(),
+        }
+    }
+}
+
+/// Scanner running in worker processes, see the [module documentation](crate::worker).
+///
+/// The workers are killed when it is dropped.
+#[derive(Debug)]
+pub struct WorkerPool {
+    config: WorkerPoolBuilder,
+    /// `None` for a worker that could not be restarted yet.
+    idle: Mutex<Vec<Option<Worker>>>,
+    available: Condvar,
+    restarts: AtomicUsize,
+}
+
+impl WorkerPool {
+    /// Configure a new pool.
+    pub fn builder() -> WorkerPoolBuilder {
+        WorkerPoolBuilder::default()
+    }
+
+    fn idle(&self) -> MutexGuard<'_, Vec<Option<Worker>>> {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn take(&self) -> Option<Worker> {
+        let mut idle = self.idle();
+        loop {
+            if let Some(worker) = idle.pop() {
+                return worker;
+            }
+            idle = self.available.wait(idle).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    fn put(&self, worker: Option<Worker>) {
+        self.idle().push(worker);
+        self.available.notify_one();
+    }
+
+    /// Start a worker in place of one that ended.
+    fn restart(&self) -> Option<Worker> {
+        Worker::start(&self.config)
+            .map_err(|e| eprintln!("Cannot start scanner worker: {e}"))
+            .ok()
+    }
+
+    /// Kill `worker` after `e` and start another in its place,
+    /// returning what happened.
+    fn replace(&self, worker: Worker, e: io::Error) -> String {
+        let id = worker.child.id();
+        let e = match e.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => "timed out".to_string(),
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset => "exited".to_string(),
+            _ => e.to_string(),
+        };
+        let description = format!("Scanner worker {id} {e} ({})", worker.stop());
+        eprintln!("{description}, restarting it");
+        self.restarts.fetch_add(1, atomic::Ordering::Relaxed);
+        self.put(self.restart());
+        description
+    }
+
+    fn request(&self, fd: BorrowedFd<'_>, mask: EventMask, pid: i32) -> Verdict {
+        let mut retried = false;
+        loop {
+            let Some(mut worker) = self.take().or_else(|| self.restart()) else {
+                self.put(None);
+                return Verdict::Error("No scanner worker running".to_string());
+            };
+            // Not delivered, a worker that ended while idle is retried once.
+            if let Err(e) = worker.send(fd, mask, pid) {
+                let description = self.replace(worker, e);
+                if !retried {
+                    retried = true;
+                    continue;
+                }
+                return Verdict::Error(description);
+            }
+            return match worker.reply() {
+                Ok(verdict) => {
+                    self.put(Some(worker));
+                    verdict
+                }
+                Err(e) => {
+                    let description = self.replace(worker, e);
+                    self.config
+                        .on_crash
+                        .clone()
+                        .unwrap_or(Verdict::Error(description))
+                }
+            };
+        }
+    }
+
+    /// Scan the file open as `fd` in a worker, which gets an event
+    /// without mask nor pid.
+    pub fn scan_fd(&self, fd: BorrowedFd<'_>) -> Verdict {
+        self.request(fd, EventMask::empty(), 0)
+    }
+
+    /// Number of workers restarted after crashing or timing out.
+    pub fn restarts(&self) -> usize {
+        self.restarts.load(atomic::Ordering::Relaxed)
+    }
+}
+
+impl Scanner for WorkerPool {
+    fn scan(&self, fd: BorrowedFd<'_>, event: &Event) -> Verdict {
+        self.request(fd, event.mask(), event.pid())
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        for worker in self.idle().drain(..).flatten() {
+            worker.stop();
+        }
+    }
+}